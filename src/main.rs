@@ -1,250 +1,15422 @@
+use audiopus::{coder::Decoder as OpusDecoder, Channels as OpusChannels, SampleRate};
+use chrono::{DateTime, Datelike, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng,
+};
+use serde::{Deserialize, Serialize};
 use serenity::{
     async_trait,
-    client::{Context, EventHandler},
+    builder::CreateComponents,
+    client::bridge::gateway::GatewayIntents,
+    client::{bridge::gateway::ShardManager, Context, EventHandler},
     framework::{
         standard::{
-            macros::{command, group},
-            CommandResult,
+            macros::{check, command, group},
+            Args, CommandOptions, CommandResult, Reason,
         },
         StandardFramework,
     },
+    futures::StreamExt,
+    gateway::GatewayError,
+    http::{error::Error as HttpError, AttachmentType, Http},
     model::{
-        channel::Message,
-        id::{ChannelId, GuildId},
+        channel::{Attachment, ChannelType, Message, MessageType},
+        guild::{Guild, Member},
+        id::{ChannelId, GuildId, RoleId, UserId},
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+        misc::Mentionable,
+        permissions::Permissions,
         prelude::{Ready, VoiceState},
+        user::OnlineStatus,
     },
-    prelude::{Mutex, TypeMapKey},
+    prelude::{Mutex, RwLock, TypeMap, TypeMapKey},
     Client,
 };
+use sha2::{Digest, Sha256};
 use songbird::{
-    input::{self, cached::Memory},
-    Call, Event, EventContext, EventHandler as VoiceEventHandler, SerenityInit, TrackEvent,
+    driver::Bitrate,
+    error::JoinError,
+    input::{
+        cached::{Compressed, Memory},
+        children_to_reader, Codec, CodecType, Container, Input, Metadata, Reader,
+    },
+    tracks::{LoopState, PlayMode as TrackPlayMode, TrackHandle, TrackQueue},
+    Call, CoreEvent, Event, EventContext, EventHandler as VoiceEventHandler, SerenityInit,
+    TrackEvent,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    env, fmt,
+    hash::Hash,
+    io::{self, Read, Seek, SeekFrom, Write},
+    net::SocketAddr,
+    ops::{Bound, RangeInclusive},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader, Packet as MediaPacket},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
 };
-use std::{collections::HashMap, env, sync::Arc};
 use tokio::{
     fs::File,
-    io::AsyncWriteExt,
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{OwnedMutexGuard, OwnedSemaphorePermit, Semaphore},
 };
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn, Instrument, Span};
 use tracing_subscriber::EnvFilter;
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() {
+fn main() {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .init();
 
-    let token = env::var("DISCORD_TOKEN").expect("discord token");
+    // before the runtime starts its threads, the config file goes into the environment
+    let guild_defaults = match env::var_os("CONFIG_FILE") {
+        Some(path) => match load_config_file(Path::new(&path)) {
+            Ok(guild_defaults) => guild_defaults,
+            Err(e) => {
+                error!("Invalid config file {path:?}: {e}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // multi threaded, so a slow decode or download can't hold up voice state updates
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Error building the runtime")
+        .block_on(run(guild_defaults));
+}
+
+async fn run(guild_defaults: Option<GuildConfig>) {
+    let config = Config::from_env();
+    if let Some(Err(e)) = guild_defaults
+        .as_ref()
+        .map(|defaults| check_guild_config(defaults, config.max_duration_ceiling))
+    {
+        error!("Invalid [guild] in the config file: {e}");
+        std::process::exit(1);
+    }
+    if find_executable(&config.ffmpeg_path).is_none() {
+        error!(
+            "ffmpeg binary {:?} not found or not executable, set FFMPEG_PATH to a valid ffmpeg",
+            config.ffmpeg_path
+        );
+    }
+
+    // every upload goes through the temp dir and ends up in the data dir, better to
+    // stop now than to fail each of them later
+    let data_dir = tokio::fs::create_dir_all(&config.data_dir)
+        .await
+        .and(can_write(&config.data_dir).await);
+    for (dir, writable) in [
+        (config.data_dir.clone(), data_dir),
+        (env::temp_dir(), can_write(&env::temp_dir()).await),
+    ] {
+        if let Err(e) = writable {
+            error!("Cannot write to {dir:?}, check its permissions and free disk space: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    if env::args().nth(1).as_deref() == Some("--selftest") {
+        let code = if selftest(&config).await { 0 } else { 1 };
+        std::process::exit(code);
+    }
+
+    let token = discord_token();
+
+    let (owners, bot_id) = match Http::new_with_token(&token)
+        .get_current_application_info()
+        .await
+    {
+        Ok(info) => (HashSet::from([info.owner.id]), Some(UserId(info.id.0))),
+        Err(e) => {
+            warn!("Error fetching application owner: {e}");
+            (HashSet::new(), None)
+        }
+    };
+
+    let mut guild_configs = load_guild_configs(&config.data_dir).await;
+    if let Some(backup_path) = &config.backup_path {
+        restore_missing_configs(backup_path, &config.data_dir, &mut guild_configs).await;
+    }
+    let cache_ttl = config.cache_ttl;
+    let save_interval = config.save_interval;
+    let backup = config
+        .backup_path
+        .clone()
+        .map(|path| (path, config.backup_interval));
+    let sharding = config.sharding;
+    let admin = match (config.admin_addr, config.admin_token.clone()) {
+        (Some(addr), Some(token)) => Some((addr, token)),
+        (Some(_), None) => {
+            error!("ADMIN_ADDR is set without ADMIN_TOKEN, admin API disabled");
+            None
+        }
+        (None, _) => None,
+    };
+    let muted = env_or("MUTED", false);
+
+    let pcm_cache = config.pcm_cache.clone();
+    let mut cached_pcm = match &pcm_cache {
+        Some(path) if config.cache_format == CacheFormat::Raw => read_pcm_cache(path).await,
+        _ => HashMap::new(),
+    };
+    // everything is loaded before the client is even built, so no voice state update
+    // can ever see the store half loaded and skip a join that should have played
+    let mut sound_store = HashMap::new();
+    if let Some(store) = &config.object_store {
+        fetch_persisted_sounds(&config, store).await;
+    }
+    load_sounds(&config, &guild_configs, &mut cached_pcm, &mut sound_store).await;
+    drop(cached_pcm);
+    if let Some(import_dir) = &config.import_dir {
+        import_sounds(import_dir, &config, &mut guild_configs, &mut sound_store).await;
+    }
+    if let Some(manifest) = &config.sound_manifest {
+        load_manifest(manifest, &config, &guild_configs, &mut sound_store).await;
+    }
+
+    info!(
+        guilds_with_sounds = sound_store.keys().filter(|k| matches!(k, SoundKey::Guild(_))).count(),
+        users_with_sounds = sound_store.keys().filter(|k| matches!(k, SoundKey::User(_))).count(),
+        guild_configs = guild_configs.len(),
+        token_from_file = env::var_os("DISCORD_TOKEN_FILE").is_some(),
+        ffmpeg_path = ?config.ffmpeg_path,
+        data_dir = ?config.data_dir,
+        sound_manifest = ?config.sound_manifest,
+        linger = ?config.linger,
+        cooldown = ?config.cooldown,
+        audio_timeout = ?config.audio_timeout,
+        slow_decode = ?config.slow_decode,
+        drain_timeout = ?config.drain_timeout,
+        cache_ttl = ?config.cache_ttl,
+        import_dir = ?config.import_dir,
+        react_on_set = config.react_on_set,
+        ytdl_path = ?config.ytdl_path,
+        join_jitter = ?config.join_jitter,
+        prefixes = ?config.prefixes,
+        bitrate_kbps = ?config.bitrate_kbps,
+        compress_sounds = config.compress_sounds,
+        preroll = ?config.preroll,
+        waveform_on_set = config.waveform_on_set,
+        crossfade = ?config.crossfade,
+        timezone = %config.timezone,
+        cache_format = ?config.cache_format,
+        sharding = ?config.sharding,
+        admin_addr = ?config.admin_addr,
+        locale = %config.locale,
+        muted,
+        "Starting up"
+    );
+    // tracing takes at most 32 fields per event
+    info!(
+        max_sound_bytes = config.max_sound_bytes,
+        max_decoded_bytes = config.max_decoded_bytes,
+        silence_db = ?config.silence_db,
+        clip_warn_percent = ?config.clip_warn_percent,
+        max_duration = ?config.max_duration,
+        max_duration_ceiling = ?config.max_duration_ceiling,
+        max_guilds = ?config.max_guilds,
+        guild_limit_action = ?config.guild_limit_action,
+        "Limits"
+    );
+    if config.presences {
+        info!("Asking for presences, to tell the platforms members join from");
+    }
+    if config.alert_webhook.is_some() {
+        info!(
+            alert_events = ?config.alert_events,
+            alert_interval = ?config.alert_interval,
+            "Alerting through a webhook"
+        );
+    }
+
+    let preroll = match &config.preroll {
+        Some(path) => match decode(path, None, None, &config).await {
+            Ok(decoded) => Some(decoded.memory),
+            Err(e) => {
+                error!("Error decoding the pre-roll {path:?}, playing without it: {e}");
+                None
+            }
+        },
+        None => None,
+    };
 
-    let framework = StandardFramework::new().group(&GENERAL_GROUP);
+    let framework = StandardFramework::new()
+        .configure(|c| {
+            c.owners(owners)
+                .prefixes(&config.prefixes)
+                .on_mention(bot_id)
+        })
+        .group(&GENERAL_GROUP)
+        .group(&OWNER_GROUP);
 
+    let gateway_intents = intents(&config);
     let mut client = Client::builder(&token)
+        .intents(gateway_intents)
         .event_handler(Handler)
         .framework(framework)
-        .type_map_insert::<SoundStore>(HashMap::new())
+        .type_map_insert::<SoundStore>(Arc::new(RwLock::new(sound_store)))
+        .type_map_insert::<Plays>(HashMap::new())
+        .type_map_insert::<Farewells>(HashMap::new())
+        .type_map_insert::<Held>(HashMap::new())
+        .type_map_insert::<RecentSets>(HashMap::new())
+        .type_map_insert::<Unsaved>(HashSet::new())
+        .type_map_insert::<SoundLocks>(HashMap::new())
+        .type_map_insert::<PlaybackLocks>(HashMap::new())
+        .type_map_insert::<ActivityLocks>(HashMap::new())
+        .type_map_insert::<Downloads>(Arc::new(Semaphore::new(config.max_downloads)))
+        .type_map_insert::<AdminJobs>(BTreeMap::new())
+        .type_map_insert::<DiscordContext>(None)
+        .type_map_insert::<VoiceCounts>(HashMap::new())
+        .type_map_insert::<RecentTransitions>(HashMap::new())
+        .type_map_insert::<Tracks>(HashMap::new())
+        .type_map_insert::<Following>(HashMap::new())
+        .type_map_insert::<LastSounds>(HashMap::new())
+        .type_map_insert::<Preroll>(preroll)
+        .type_map_insert::<Muted>(Arc::new(AtomicBool::new(muted)))
+        .type_map_insert::<Draining>(Arc::new(AtomicBool::new(false)))
+        .type_map_insert::<LastPlays>(HashMap::new())
+        .type_map_insert::<LastSoundPlays>(HashMap::new())
+        .type_map_insert::<Nudged>(HashSet::new())
+        .type_map_insert::<Clocks>(Arc::new(SystemClock))
+        .type_map_insert::<VoiceHealth>(JoinFailures::default())
+        .type_map_insert::<Alerts>(AlertLog::default())
+        .type_map_insert::<GuildConfigs>(guild_configs)
+        .type_map_insert::<GuildDefaults>(guild_defaults)
+        .type_map_insert::<Config>(Arc::new(config))
         .register_songbird()
         .await
         .expect("successful client creation");
 
+    if let Some(ttl) = cache_ttl {
+        tokio::spawn(evict_idle_sounds(client.data.clone(), ttl));
+    }
+    tokio::spawn(rotate_sounds(client.data.clone()));
+    if let Some(interval) = save_interval {
+        tokio::spawn(save_periodically(client.data.clone(), interval));
+    }
+    if let Some((path, interval)) = backup {
+        tokio::spawn(take_snapshots(client.data.clone(), path, interval));
+    }
+    if let Some((addr, token)) = admin {
+        tokio::spawn(admin_server(client.data.clone(), addr, token));
+    }
+
     let shard_manager = client.shard_manager.clone();
+    client
+        .data
+        .write()
+        .await
+        .insert::<Shards>(shard_manager.clone());
+    let data = client.data.clone();
     tokio::spawn(async move {
-        tokio::signal::ctrl_c()
-            .await
-            .expect("regiter ctrlc handler");
-        info!("Shutting down");
+        // ctrl-c stops right away, SIGTERM (what orchestrators send on rolling
+        // updates) lets the sounds playing finish first
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result.expect("register ctrlc handler");
+                info!("Shutting down");
+            }
+            _ = terminated() => {
+                tokio::select! {
+                    _ = drain(&data) => {}
+                    result = tokio::signal::ctrl_c() => {
+                        result.expect("register ctrlc handler");
+                        info!("Shutting down without waiting for the drain");
+                    }
+                }
+            }
+        }
 
+        flush(&data).await;
         shard_manager.lock().await.shutdown_all().await;
     });
 
-    if let Err(e) = client.start().await {
-        error!("Client error: {e}");
+    // the type map and songbird are shared by every shard of the client, songbird
+    // sends each guild's voice updates through the shard the guild is on
+    let started = match sharding {
+        Sharding::Single => client.start().await,
+        Sharding::Fixed(count) => client.start_shards(count).await,
+        Sharding::Auto => client.start_autosharded().await,
+    };
+    match started {
+        Ok(()) => {}
+        // serenity stops every shard the first time the gateway rejects the token, on
+        // startup or when reconnecting, instead of retrying
+        Err(serenity::Error::Gateway(GatewayError::InvalidAuthentication)) => {
+            error!("Discord rejected the token, it was probably revoked or reset. Shutting down, set a new one and restart");
+            let text = "Discord rejected the token, the bot is down until it gets a new one";
+            alert(client.data.clone(), AlertEvent::Auth, text.to_string()).await;
+            // tell supervisors this isn't a normal stop, a restart won't fix it by itself
+            std::process::exit(1);
+        }
+        // the same goes for privileged intents the application isn't allowed. The
+        // presence intent is the only one asked for, see [`intents`]
+        Err(serenity::Error::Gateway(GatewayError::DisallowedGatewayIntents)) => {
+            error!("Discord refused the presence intent PRESENCES asks for. Turn on \"Presence Intent\" in the Bot page of the application in the developer portal, or unset PRESENCES, and restart");
+            std::process::exit(1);
+        }
+        Err(serenity::Error::Gateway(GatewayError::InvalidGatewayIntents)) => {
+            error!(
+                "Discord says the gateway intents {gateway_intents:?} are invalid, shutting down"
+            );
+            std::process::exit(1);
+        }
+        Err(e) => error!("Client error: {e}"),
+    }
+
+    if let Some(path) = pcm_cache {
+        // already logged
+        save_pcm_cache(&client.data, &path).await.ok();
     }
 
     info!("Bye!");
 }
 
-struct SoundStore;
+/// Resolves when the process gets a SIGTERM.
+#[cfg(unix)]
+async fn terminated() {
+    use tokio::signal::unix::{signal, SignalKind};
 
-impl TypeMapKey for SoundStore {
-    type Value = HashMap<GuildId, Memory>;
+    signal(SignalKind::terminate())
+        .expect("register sigterm handler")
+        .recv()
+        .await;
+}
+
+#[cfg(not(unix))]
+async fn terminated() {
+    std::future::pending().await
 }
 
-const TOO_MUCH_ATTACH_MSG: &str =
-    "Vou usar só o primeiro arquivo que tu mandou, o resto eu to ignorando!!";
+/// Stops taking new sounds and starting playback, then waits until nothing is
+/// playing anymore, or `Config::drain_timeout` passes, so a new instance can take
+/// over without cutting anybody's sound off. Shutting down is left to the caller.
+async fn drain(data: &RwLock<TypeMap>) {
+    let timeout = {
+        let data = data.read().await;
+        data.get::<Draining>()
+            .expect("draining is set")
+            .store(true, Ordering::Relaxed);
+        data.get::<Config>().expect("config is set").drain_timeout
+    };
+    info!("Draining, waiting up to {timeout:?} for sounds to finish");
 
-#[group]
-#[commands(set)]
-struct General;
+    let started = Instant::now();
+    while anything_playing(data).await {
+        if started.elapsed() >= timeout {
+            warn!("Sounds still playing after {timeout:?}, shutting down anyway");
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    info!("Drained in {:?}, shutting down", started.elapsed());
+}
 
-#[command]
-async fn set(ctx: &Context, msg: &Message) -> CommandResult {
-    let gid = if let Some(gid) = msg.guild_id {
-        gid
-    } else {
-        return Ok(());
+async fn anything_playing(data: &RwLock<TypeMap>) -> bool {
+    let tracks: Vec<_> = {
+        let data = data.read().await;
+        let tracks = data.get::<Tracks>().expect("tracks are set");
+        tracks.values().cloned().collect()
     };
+    for track in tracks {
+        if let Ok(state) = track.get_info().await {
+            if state.playing == TrackPlayMode::Play {
+                return true;
+            }
+        }
+    }
+    false
+}
 
-    if msg.attachments.is_empty() {
-        if let Err(e) = msg.reply(ctx, "Cadê o áudio carai??").await {
-            warn!("Error replying: {e}");
+/// Reads the token from the file at `DISCORD_TOKEN_FILE` (docker/k8s secrets) if
+/// set, otherwise from `DISCORD_TOKEN`.
+fn discord_token() -> String {
+    match env::var_os("DISCORD_TOKEN_FILE") {
+        Some(path) => std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("reading discord token file {path:?}: {e}"))
+            .trim_end()
+            .to_string(),
+        None => env::var("DISCORD_TOKEN").expect("discord token"),
+    }
+}
+
+struct Config {
+    ffmpeg_path: PathBuf,
+    data_dir: PathBuf,
+    /// Json file mapping guild ids to the path of their default sound.
+    sound_manifest: Option<PathBuf>,
+    /// How long to stay connected after a sound ends, in case somebody else joins.
+    linger: Duration,
+    /// Minimum time between entrance sounds in a guild, unless the guild overrides it.
+    cooldown: Duration,
+    /// Largest attachment `set` accepts.
+    max_sound_bytes: u64,
+    /// Most sounds downloaded and decoded at once, along with admin API jobs, see
+    /// [`Downloads`].
+    max_downloads: usize,
+    /// Most memory a new sound may take once decoded to PCM. Small files of long,
+    /// very compressible audio get past `max_sound_bytes` but not this.
+    max_decoded_bytes: u64,
+    /// New sounds that never get louder than this, in dBFS, are rejected as silent.
+    silence_db: Option<f64>,
+    /// Percent of a new sound's samples at full scale that gets the `set` reply to
+    /// warn it's clipping, see [`clipped_percent`].
+    clip_warn_percent: Option<f64>,
+    /// How long downloading or decoding a sound may take.
+    audio_timeout: Duration,
+    /// Decodes taking longer than this to start are logged as slow. Only starting
+    /// ffmpeg and filling the first chunk of the cache is timed, the rest of the
+    /// audio is decoded as it plays.
+    slow_decode: Duration,
+    /// Longest a SIGTERM or `drain` waits for sounds still playing before shutting
+    /// down anyway.
+    drain_timeout: Duration,
+    /// Decoded sounds that don't play for this long are dropped from memory.
+    cache_ttl: Option<Duration>,
+    /// Changed guild configs are saved together this often instead of on every change,
+    /// see [`save_unsaved`].
+    save_interval: Option<Duration>,
+    /// Bucket the persisted sounds are mirrored to and fetched back from at startup,
+    /// for hosts where the data directory doesn't survive a restart.
+    object_store: Option<ObjectStore>,
+    /// Folder of another bot's sounds to import at startup.
+    import_dir: Option<PathBuf>,
+    /// How the imported files are named, see `parse_import_name`.
+    import_pattern: String,
+    /// Answer `set` with a reaction instead of a reply, to keep channels quiet.
+    react_on_set: bool,
+    /// Longest sound accepted, unless the guild sets its own limit.
+    max_duration: Duration,
+    /// Longest a guild may set its own limit to, see `GuildConfig::max_duration_secs`.
+    max_duration_ceiling: Duration,
+    /// yt-dlp binary used to set sounds from links, which are refused when unset.
+    ytdl_path: Option<PathBuf>,
+    /// Longest random wait before joining, so the bot doesn't pop in instantly.
+    join_jitter: Duration,
+    /// Any of these starts a command, as does mentioning the bot.
+    prefixes: Vec<String>,
+    /// Voice bitrate in kbps, unless the guild sets its own. Songbird's default
+    /// when unset.
+    bitrate_kbps: Option<u32>,
+    /// Persist sounds zstd compressed. Either kind is read back.
+    compress_sounds: bool,
+    /// Keep uploads under a hash of their file name instead of the name itself, so
+    /// it shows up nowhere in the logs or the data directory, see [`stored_name`].
+    /// Labels aren't covered: one taken from the file name when none is given still
+    /// shows up in both.
+    redact_filenames: bool,
+    /// Clip played whenever the bot connects to a channel, before the sound.
+    preroll: Option<PathBuf>,
+    /// Attach a picture of the sound's waveform to the reply to `set`.
+    waveform_on_set: bool,
+    /// How long queued sounds overlap, see [`crossfade`]. Zero cuts straight from
+    /// one to the next.
+    crossfade: Duration,
+    /// Most guilds the bot operates in, no limit when unset.
+    max_guilds: Option<usize>,
+    /// What happens when the bot is added to a guild past `max_guilds`.
+    guild_limit_action: GuildLimitAction,
+    /// What the bot does about being server muted when it's about to play.
+    server_mute_action: ServerMuteAction,
+    /// Timezone of sound schedules that don't name their own.
+    timezone: Tz,
+    /// How decoded sounds are kept in memory.
+    cache_format: CacheFormat,
+    /// How many gateway shards to run.
+    sharding: Sharding,
+    /// Where the admin HTTP API listens, see [`admin_server`]. Off when unset.
+    admin_addr: Option<SocketAddr>,
+    /// Bearer token every admin API request must carry.
+    admin_token: Option<String>,
+    /// Language of guilds that don't pick one and whose Discord locale isn't one of
+    /// ours, see [`locale`].
+    locale: Locale,
+    /// Discord webhook, or any url taking a json POST, told about trouble the
+    /// operator should know of, see [`alert`].
+    alert_webhook: Option<String>,
+    alert_events: HashSet<AlertEvent>,
+    /// Least time between two alerts of the same event, and the window decode
+    /// failures are counted in.
+    alert_interval: Duration,
+    /// Ask for members' presences, to tell the [`Platform`] they join from. A
+    /// privileged intent, that has to be enabled in the developer portal too.
+    presences: bool,
+    /// Where the decoded sounds are written on shutdown and read back from on startup
+    /// instead of decoding them again, see [`CachedPcm`]. None when unset.
+    pcm_cache: Option<PathBuf>,
+    /// Where a [`Snapshot`] of every guild's settings is kept, none when unset.
+    backup_path: Option<PathBuf>,
+    backup_interval: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CacheFormat {
+    /// Raw PCM, opus encoded by songbird every time it plays.
+    Raw,
+    /// Opus encoded once when decoded, about a tenth of the memory and nothing left
+    /// to encode when joining. Plays at the bitrate it was encoded with.
+    Opus,
+}
+
+impl FromStr for CacheFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(CacheFormat::Raw),
+            "opus" => Ok(CacheFormat::Opus),
+            _ => Err(()),
         }
-        return Ok(());
     }
+}
 
-    if msg.attachments.len() > 1 {
-        if let Err(e) = msg.reply(ctx, TOO_MUCH_ATTACH_MSG).await {
-            warn!("Error replying: {e}");
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Sharding {
+    /// One shard, enough for up to 2500 guilds.
+    Single,
+    /// This many shards, all in this process.
+    Fixed(u64),
+    /// As many as Discord recommends for the bot's guild count.
+    Auto,
+}
+
+impl FromStr for Sharding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Sharding::Auto),
+            count => match count.parse() {
+                Ok(1) => Ok(Sharding::Single),
+                Ok(0) | Err(_) => Err(()),
+                Ok(count) => Ok(Sharding::Fixed(count)),
+            },
         }
     }
+}
 
-    match save_audio(ctx, msg, gid).await {
-        Ok(()) => {
-            if let Err(e) = msg.reply(ctx, "Blz, vou tocar esse áudio aí!!").await {
-                warn!("Error replying: {e}");
-            }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GuildLimitAction {
+    /// Says so in the guild and leaves it.
+    Leave,
+    /// Only logs a warning.
+    Warn,
+}
+
+impl FromStr for GuildLimitAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "leave" => Ok(GuildLimitAction::Leave),
+            "warn" => Ok(GuildLimitAction::Warn),
+            _ => Err(()),
         }
-        Err(_) => {
-            if let Err(e) = msg.reply(ctx, "Deu pau").await {
-                warn!("Error replying: {e}");
-            }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ServerMuteAction {
+    /// Doesn't play and logs a warning, nobody would hear it.
+    Skip,
+    /// Unmutes itself if it has Mute Members, and skips like `Skip` otherwise.
+    Unmute,
+}
+
+impl FromStr for ServerMuteAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(ServerMuteAction::Skip),
+            "unmute" => Ok(ServerMuteAction::Unmute),
+            _ => Err(()),
         }
     }
+}
 
-    Ok(())
+/// Languages the bot replies in. Only `set` and what can go wrong with it are
+/// translated so far, everything else is still Portuguese.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Locale {
+    #[serde(rename = "pt-BR")]
+    PtBr,
+    #[serde(rename = "en")]
+    En,
 }
 
-async fn save_audio(ctx: &Context, msg: &Message, gid: GuildId) -> Result<(), AudioError> {
-    let attach = msg.attachments.first().expect("already checked size");
-    match attach.download().await {
-        Ok(content) => {
-            let track = track_from(&content, gid, &attach.filename).await?;
+impl FromStr for Locale {
+    type Err = ();
 
-            let mut data = ctx.data.write().await;
-            let sound_store = data.get_mut::<SoundStore>().expect("sound store is set");
-            sound_store.insert(gid, track);
+    /// Takes Discord's locales too, like `pt-BR` or `en-US`, by their language.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split(['-', '_']).next().unwrap_or_default() {
+            "pt" => Ok(Locale::PtBr),
+            "en" => Ok(Locale::En),
+            _ => Err(()),
+        }
+    }
+}
 
-            Ok(())
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Locale::PtBr => write!(f, "pt-BR"),
+            Locale::En => write!(f, "en"),
         }
-        Err(e) => {
-            warn!("Error downloading user audio: {}", e);
-            Ok(())
+    }
+}
+
+impl Config {
+    fn from_env() -> Self {
+        let max_duration = Duration::from_secs(env_or("MAX_SOUND_SECS", 60));
+        Self {
+            ffmpeg_path: env::var_os("FFMPEG_PATH")
+                .map_or_else(|| PathBuf::from("ffmpeg"), PathBuf::from),
+            data_dir: env::var_os("DATA_DIR").map_or_else(|| PathBuf::from("data"), PathBuf::from),
+            sound_manifest: env::var_os("SOUND_MANIFEST").map(PathBuf::from),
+            linger: Duration::from_secs(env_or("LINGER_SECS", 0)),
+            cooldown: Duration::from_secs(env_or("COOLDOWN_SECS", 0)),
+            max_sound_bytes: env_or("MAX_SOUND_BYTES", 8 * 1024 * 1024),
+            max_downloads: env_or("MAX_DOWNLOADS", 4).max(1),
+            max_decoded_bytes: env_or("MAX_DECODED_MB", 64u64).saturating_mul(1024 * 1024),
+            silence_db: match env::var("SILENCE_DB").as_deref() {
+                Ok("off") => None,
+                _ => Some(env_or("SILENCE_DB", -60.0)),
+            },
+            clip_warn_percent: match env::var("CLIP_WARN_PERCENT").as_deref() {
+                Ok("off") => None,
+                _ => Some(env_or("CLIP_WARN_PERCENT", 1.0)),
+            },
+            audio_timeout: Duration::from_secs(env_or("AUDIO_TIMEOUT_SECS", 30)),
+            slow_decode: Duration::from_millis(env_or("SLOW_DECODE_MS", 2000)),
+            drain_timeout: Duration::from_secs(env_or("DRAIN_TIMEOUT_SECS", 25)),
+            cache_ttl: match env_or("CACHE_TTL_SECS", 0) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            save_interval: match env_or("SAVE_INTERVAL_SECS", 0) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            object_store: env::var("S3_BUCKET").ok().and_then(ObjectStore::from_env),
+            import_dir: env::var_os("IMPORT_DIR").map(PathBuf::from),
+            import_pattern: env::var("IMPORT_PATTERN")
+                .unwrap_or_else(|_| "{guild}_{label}".to_string()),
+            react_on_set: env_or("REACT_ON_SET", false),
+            max_duration,
+            // guilds may only lower the limit when unset
+            max_duration_ceiling: Duration::from_secs(env_or("MAX_SOUND_SECS_CEILING", 0))
+                .max(max_duration),
+            ytdl_path: env::var_os("YTDL_PATH").map(PathBuf::from),
+            join_jitter: Duration::from_millis(env_or("JOIN_JITTER_MS", 0)),
+            prefixes: parse_prefixes(&env::var("PREFIXES").unwrap_or_default()),
+            compress_sounds: env_or("COMPRESS_SOUNDS", false),
+            redact_filenames: env_or("REDACT_FILENAMES", false),
+            preroll: env::var_os("PREROLL_PATH").map(PathBuf::from),
+            waveform_on_set: env_or("WAVEFORM_ON_SET", false),
+            crossfade: Duration::from_millis(env_or("CROSSFADE_MS", 0)),
+            max_guilds: match env_or("MAX_GUILDS", 0) {
+                0 => None,
+                max => Some(max),
+            },
+            guild_limit_action: env_or("GUILD_LIMIT_ACTION", GuildLimitAction::Leave),
+            server_mute_action: env_or("SERVER_MUTE_ACTION", ServerMuteAction::Skip),
+            timezone: env_or("TIMEZONE", Tz::UTC),
+            cache_format: env_or("CACHE_FORMAT", CacheFormat::Raw),
+            sharding: env_or("SHARDS", Sharding::Single),
+            admin_addr: env::var("ADMIN_ADDR")
+                .ok()
+                .and_then(|addr| match addr.parse() {
+                    Ok(addr) => Some(addr),
+                    Err(_) => {
+                        warn!("Invalid value for ADMIN_ADDR: {addr:?}, admin API disabled");
+                        None
+                    }
+                }),
+            locale: env_or("LOCALE", Locale::PtBr),
+            alert_webhook: env::var("ALERT_WEBHOOK_URL")
+                .ok()
+                .filter(|url| !url.is_empty()),
+            alert_events: parse_alert_events(&env::var("ALERT_EVENTS").unwrap_or_default()),
+            alert_interval: Duration::from_secs(env_or("ALERT_INTERVAL_SECS", 10 * 60)),
+            presences: env_or("PRESENCES", false),
+            pcm_cache: env::var_os("PCM_CACHE_PATH").map(PathBuf::from),
+            backup_path: env::var_os("BACKUP_PATH").map(PathBuf::from),
+            backup_interval: Duration::from_secs(env_or("BACKUP_INTERVAL_SECS", 60 * 60).max(1)),
+            admin_token: env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            bitrate_kbps: env::var("BITRATE_KBPS")
+                .ok()
+                .and_then(|kbps| match kbps.parse() {
+                    Ok(kbps) if BITRATE_KBPS_RANGE.contains(&kbps) => Some(kbps),
+                    _ => {
+                        warn!("Invalid value for BITRATE_KBPS: {kbps:?}, using songbird's default");
+                        None
+                    }
+                }),
+        }
+    }
+
+    /// `ffprobe` ships alongside `ffmpeg`, so look for it in the same place.
+    fn ffprobe_path(&self) -> PathBuf {
+        match self.ffmpeg_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.join("ffprobe"),
+            _ => PathBuf::from("ffprobe"),
         }
     }
 }
 
-use songbird::input::error::Error as AudioError;
+/// The gateway intents to connect with: serenity's default, the non privileged ones,
+/// and the presences when `Config::presences` asks for them.
+fn intents(config: &Config) -> GatewayIntents {
+    if config.presences {
+        GatewayIntents::non_privileged() | GatewayIntents::GUILD_PRESENCES
+    } else {
+        GatewayIntents::non_privileged()
+    }
+}
 
-async fn track_from(content: &[u8], gid: GuildId, name: &str) -> Result<Memory, AudioError> {
-    let path = env::temp_dir().join(format!("{}{}", gid, name));
-    {
-        match File::create(&path).await {
-            Ok(mut file) => {
-                if let Err(e) = file.write_all(content).await {
-                    warn!("Error writing file: {e}");
-                }
+/// Comma separated events to alert on, all of them when there are none.
+fn parse_alert_events(events: &str) -> HashSet<AlertEvent> {
+    let events: HashSet<AlertEvent> = events
+        .split(',')
+        .map(str::trim)
+        .filter(|event| !event.is_empty())
+        .filter_map(|event| {
+            let parsed = event.parse().ok();
+            if parsed.is_none() {
+                warn!("Unknown alert event {event:?}, ignoring it");
+            }
+            parsed
+        })
+        .collect();
+    if events.is_empty() {
+        HashSet::from([AlertEvent::Decode, AlertEvent::Voice, AlertEvent::Auth])
+    } else {
+        events
+    }
+}
+
+/// Comma separated prefixes, the framework's default `~` when there are none.
+fn parse_prefixes(prefixes: &str) -> Vec<String> {
+    let prefixes: Vec<String> = prefixes
+        .split(',')
+        .map(str::trim)
+        .filter(|prefix| !prefix.is_empty())
+        .map(str::to_string)
+        .collect();
+    if prefixes.is_empty() {
+        vec!["~".to_string()]
+    } else {
+        prefixes
+    }
+}
+
+/// Reads the TOML config file at `path` into the environment: its top level keys are
+/// the env vars the bot reads, which take precedence when also set in the
+/// environment. A `[guild]` table holds the settings of guilds without a config of
+/// their own, the [`GuildConfig`] defaults otherwise, and is returned.
+fn load_config_file(path: &Path) -> Result<Option<GuildConfig>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let table: toml::Table = content
+        .parse()
+        .map_err(|e: toml::de::Error| e.to_string())?;
+
+    let mut guild_defaults = None;
+    for (key, value) in table {
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            toml::Value::Boolean(value) => value.to_string(),
+            toml::Value::Table(guild) if key == "guild" => {
+                // through JSON, the format guild configs are kept in
+                let guild = serde_json::to_value(guild).map_err(|e| e.to_string())?;
+                let guild = serde_json::from_value(guild).map_err(|e| format!("[guild]: {e}"))?;
+                guild_defaults = Some(guild);
+                continue;
             }
-            Err(e) => warn!("Error creating file: {e}"),
+            // lists are comma separated, like in the env
+            toml::Value::Array(values) => values
+                .iter()
+                .map(|value| match value {
+                    toml::Value::String(value) => Ok(value.clone()),
+                    _ => Err(format!("{key} can only list strings")),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            _ => return Err(format!("{key} has to be a string, number or boolean")),
+        };
+        if env::var_os(&key).is_none() {
+            // called before any other thread starts, nothing else reads the environment
+            env::set_var(&key, value);
         }
     }
+    Ok(guild_defaults)
+}
 
-    let track = {
-        let track_input = input::ffmpeg(&path).await?;
-        Memory::new(track_input)?
+struct GuildDefaults;
+
+impl TypeMapKey for GuildDefaults {
+    /// Settings of guilds without a config of their own, from the config file.
+    type Value = Option<GuildConfig>;
+}
+
+/// Parses the env var `key`, falling back to `default` when it's unset or invalid.
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Invalid value for {key}: {value:?}, using the default");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+impl TypeMapKey for Config {
+    type Value = Arc<Config>;
+}
+
+/// Resolves `path` the way the shell would: paths with a directory component are
+/// checked directly, bare names are searched for in `PATH`.
+fn find_executable(path: &Path) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        path.metadata()
+            .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
     };
 
-    // if let Err(e) = remove_file(path).await {
-    //     warn!("Error deleting file: {e}");
-    // }
+    if path.components().count() > 1 {
+        return is_executable(path).then(|| path.to_path_buf());
+    }
 
-    Ok(track)
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(path))
+        .find(|candidate| is_executable(candidate))
 }
 
-struct Handler;
+struct GuildConfigs;
 
-#[async_trait]
-impl EventHandler for Handler {
-    async fn ready(&self, _ctx: Context, ready: Ready) {
-        info!("Connected as {}", ready.user.name);
+impl TypeMapKey for GuildConfigs {
+    type Value = HashMap<GuildId, GuildConfig>;
+}
+
+/// Per guild settings, persisted as json in the data directory.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct GuildConfig {
+    /// Members that must already be in the channel for a join to play the sound.
+    min_members: usize,
+    /// Nothing plays while it's set, for joins or otherwise, see `paused` and `setup`.
+    paused: bool,
+    /// Like `paused` while it's active, see `quiet-hours`. Its `days` stay empty.
+    quiet_hours: Option<Schedule>,
+    /// Overrides the global cooldown between entrance sounds.
+    cooldown_secs: Option<u64>,
+    /// Overrides how long the bot stays connected after a sound ends.
+    linger_secs: Option<u64>,
+    /// Sounds played for joins loop instead of ending, and the bot stays until
+    /// `stay off` or everyone leaves the channel. See [`Looping::Forever`].
+    stay: bool,
+    /// For this long after playing a member's entrance sound, the bot moves along
+    /// with them when they move channels, see [`follow`].
+    follow_secs: Option<u64>,
+    /// Percent of the joins that play a sound, all of them when unset.
+    chance: Option<u8>,
+    /// Play in the guild's AFK channel too, which is skipped by default.
+    play_in_afk: bool,
+    mode: PlayMode,
+    /// Settings of each of the guild's sounds, by label.
+    sounds: HashMap<String, SoundConfig>,
+    /// Only welcome each member the first time they ever join.
+    first_join_only: bool,
+    /// Members already welcomed while `first_join_only` was on.
+    seen: HashSet<UserId>,
+    /// The only channel commands are taken from, when set.
+    command_channel: Option<ChannelId>,
+    /// Voice channels where sounds never play.
+    blocked_channels: HashSet<ChannelId>,
+    /// Replaces the reply to a successful `set`, `{label}` standing for the sound.
+    set_message: Option<String>,
+    /// Where the guild's sounds post their [`SoundConfig::caption`] as they play.
+    announce_channel: Option<ChannelId>,
+    /// Posted to the `announce_channel` when the bot leaves after a sound, see
+    /// [`Farewell`].
+    farewell: Option<String>,
+    /// Where every entrance sound played is written down along with who it was for,
+    /// for those who can't hear it.
+    transcript_channel: Option<ChannelId>,
+    /// Overrides the global voice bitrate, in kbps.
+    bitrate_kbps: Option<u32>,
+    sound_override: Option<SoundOverride>,
+    /// Lets admins of this guild copy its sounds to the other guilds they manage.
+    allow_sharing: bool,
+    /// Voice channels whose joins play some other guild's sounds instead, see [`borrow`].
+    borrowed_channels: HashMap<ChannelId, GuildId>,
+    /// Explain how to set a sound in the system channel when somebody joins before
+    /// there's any, see [`nudge`].
+    nudge_unset: bool,
+    /// Members who may not set or remove sounds here, whatever their roles.
+    blocked_users: HashSet<UserId>,
+    /// Members whose joins play nothing until some unix time, see `silence`.
+    silenced: HashMap<UserId, u64>,
+    /// Bots whose joins play sounds like anybody else's, every other bot is ignored.
+    allowed_bots: HashSet<UserId>,
+    /// Where in the stereo field the sounds kept for this guild play, see [`key_guild`].
+    pan: Option<Pan>,
+    /// Shapes the tone of the same sounds as `pan`, applied before it.
+    eq: Equalizer,
+    /// How long the same sounds fade out for at their end, so they don't cut off.
+    fade_out_ms: Option<u64>,
+    /// The guild's own [`AudioProfile`]s by name, next to the [`BUILTIN_PROFILES`].
+    profiles: BTreeMap<String, AudioProfile>,
+    /// Percent of their volume the guild's sounds play at, see [`playback_volume`].
+    volume: Option<u32>,
+    /// Loudness in LUFS the guild's sounds are brought to as they play, from their
+    /// [`SoundConfig::loudness`], see `agc`.
+    loudness_target: Option<f32>,
+    boost: Option<VolumeBoost>,
+    /// Overrides `Config::max_duration` for the guild's own sounds, up to
+    /// `Config::max_duration_ceiling`.
+    max_duration_secs: Option<u64>,
+    /// Language of the replies, instead of the guild's Discord locale.
+    locale: Option<Locale>,
+    verbosity: Verbosity,
+    /// Only sounds of this category play while it's set, see [`SoundConfig::category`].
+    active_category: Option<String>,
+    rotation: Option<Rotation>,
+    /// Unix time each member last joined a voice channel here, see [`Comeback`]. Only
+    /// kept while there's a `comeback`.
+    last_seen: HashMap<UserId, u64>,
+    comeback: Option<Comeback>,
+    milestone: Option<Milestone>,
+    /// Label of the guild sound played when someone boosts the server, see
+    /// [`celebrate_boost`].
+    boost_sound: Option<String>,
+}
+
+/// A sound played instead of the rest for whoever makes a voice channel reach some
+/// number of members, right after the [`Comeback`] sound.
+#[derive(Clone, Serialize, Deserialize)]
+struct Milestone {
+    /// The guild sound played.
+    label: String,
+    /// Members in the channel, the joiner included, it plays at.
+    members: usize,
+}
+
+/// Largest `Milestone::members`, as many as a voice channel can limit itself to.
+const MAX_MILESTONE_MEMBERS: usize = 99;
+
+/// Most entries of a guild's activity log kept when it's trimmed, older ones are
+/// dropped.
+const MAX_ACTIVITY: usize = 5000;
+
+/// Size a guild's activity log is trimmed back to its latest [`MAX_ACTIVITY`]
+/// entries past.
+const MAX_ACTIVITY_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Each guild's sets, removes and plays of its sounds are appended to a
+/// `{guild id}.jsonl` in here, for `stats-export`. They're kept out of the guild
+/// config, which would otherwise be written out whole on every join, and end up in
+/// its exports and snapshots.
+const ACTIVITY_DIR: &str = "activity";
+
+fn activity_path(data_dir: &Path, gid: GuildId) -> PathBuf {
+    data_dir.join(ACTIVITY_DIR).join(format!("{gid}.jsonl"))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Activity {
+    /// Unix time it happened at.
+    at: u64,
+    kind: ActivityKind,
+    /// The guild sound it was about, none for plays of other sounds.
+    label: Option<String>,
+    /// Who set or removed it, or whose join it played for. None for sounds imported
+    /// at startup.
+    user: Option<UserId>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ActivityKind {
+    Set,
+    Remove,
+    Play,
+}
+
+impl fmt::Display for ActivityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ActivityKind::Set => "set",
+            ActivityKind::Remove => "remove",
+            ActivityKind::Play => "play",
+        })
     }
+}
 
-    async fn voice_state_update(
-        &self,
-        ctx: Context,
-        gid: Option<GuildId>,
-        old: Option<VoiceState>,
-        new: VoiceState,
-    ) {
-        if is_bot(&new) {
-            return;
-        }
+struct ActivityLocks;
 
-        let gid = if let Some(gid) = gid {
-            gid
-        } else {
-            return;
-        };
+impl TypeMapKey for ActivityLocks {
+    /// Held while appending to or trimming a guild's activity log.
+    type Value = HashMap<GuildId, Arc<Mutex<()>>>;
+}
 
-        // if somebody joined some channel
-        if let Some(channel_id) = joined_channel(old.as_ref(), &new) {
-            let data = ctx.data.read().await;
-            let sound_store = data.get::<SoundStore>().expect("sound store is set");
+async fn record_activity(
+    ctx: &Context,
+    gid: GuildId,
+    kind: ActivityKind,
+    label: Option<String>,
+    user: UserId,
+) {
+    let (data_dir, lock) = {
+        let mut data = ctx.data.write().await;
+        let data_dir = data
+            .get::<Config>()
+            .expect("config is set")
+            .data_dir
+            .clone();
+        let locks = data
+            .get_mut::<ActivityLocks>()
+            .expect("activity locks are set");
+        (data_dir, locks.entry(gid).or_default().clone())
+    };
+    let activity = Activity {
+        at: unix_now(),
+        kind,
+        label,
+        user: Some(user),
+    };
+    let _lock = lock.lock().await;
+    if let Err(e) = append_activity(&data_dir, gid, &activity).await {
+        warn!("Error recording the {kind} activity of guild {gid}: {e}");
+    }
+}
 
-            // if there is a sound set to play on the guild
-            if let Some(sound) = sound_store.get(&gid) {
-                let manager = songbird::get(&ctx).await.expect("songbird is set");
+/// Appends `activity` to `gid`'s log, trimming it once it's past
+/// [`MAX_ACTIVITY_LOG_BYTES`]. Nothing else may write the log meanwhile.
+async fn append_activity(data_dir: &Path, gid: GuildId, activity: &Activity) -> io::Result<()> {
+    let path = activity_path(data_dir, gid);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut line = serde_json::to_vec(activity).expect("activity serializes");
+    line.push(b'\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    file.write_all(&line).await?;
+    if file.metadata().await?.len() <= MAX_ACTIVITY_LOG_BYTES {
+        return Ok(());
+    }
+    drop(file);
 
-                // TODO: check if not already playing on another channel
+    let activity = read_activity(data_dir, gid).await?;
+    let mut content = Vec::new();
+    for activity in &activity[activity.len().saturating_sub(MAX_ACTIVITY)..] {
+        serde_json::to_writer(&mut content, activity).expect("activity serializes");
+        content.push(b'\n');
+    }
+    write_atomically(&path, &content).await
+}
 
-                // join channel
-                let (call, res) = manager.join(gid, channel_id).await;
-                if let Err(e) = res {
-                    warn!("Error joining channel: {e}");
-                } else {
-                    let handle = {
-                        let input = sound
-                            .new_handle()
-                            .try_into()
-                            .expect("created from an input, converting back should work");
+/// `gid`'s activity log, oldest first. Lines that don't parse, like one cut short by
+/// a crash, are skipped.
+async fn read_activity(data_dir: &Path, gid: GuildId) -> io::Result<Vec<Activity>> {
+    let content = match tokio::fs::read(activity_path(data_dir, gid)).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect())
+}
 
-                        call.lock().await.play_only_source(input)
-                    };
-                    handle
-                        .add_event(Event::Track(TrackEvent::End), Disconnect { call })
-                        .expect("do not return error for valid events");
-                }
-            }
-        }
+/// A sound played instead of the rest for members joining again after a long time
+/// away, only the override and [`borrow`]ed channels go before it.
+#[derive(Clone, Serialize, Deserialize)]
+struct Comeback {
+    /// The guild sound played.
+    label: String,
+    /// How long since their last join makes them count as back.
+    after_secs: u64,
+}
+
+/// Plays one sound for every join until it's time to move on to the next, instead of
+/// picking at random each time.
+#[derive(Clone, Serialize, Deserialize)]
+struct Rotation {
+    every_secs: u64,
+    /// The sound playing for now, `None` until the first turn.
+    label: Option<String>,
+    /// When `label` got its turn, unix seconds.
+    since: u64,
+}
+
+/// Shortest turn a sound can get in a rotation.
+const MIN_ROTATION_SECS: u64 = 60;
+
+/// Which replies a guild wants, the rest become reactions, see [`acknowledge`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Verbosity {
+    /// Replies to everything.
+    #[default]
+    Verbose,
+    /// Only replies when something went wrong.
+    Quiet,
+    /// Never replies to say how a command went. Commands that show something, like
+    /// `list`, still do.
+    Silent,
+}
+
+/// Positions a sound in the stereo field, applied by ffmpeg while decoding.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Pan {
+    /// From -1 (all left) to 1 (all right).
+    Fixed(f32),
+    /// Slowly moves from one side to the other.
+    Sweep,
+}
+
+const PAN_RANGE: RangeInclusive<f32> = -1.0..=1.0;
+
+/// How often a `Pan::Sweep` goes back and forth.
+const PAN_SWEEP_HZ: f32 = 0.25;
+
+/// Boosts or cuts of some frequencies, in dB.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Equalizer {
+    /// Low shelf around 100 Hz.
+    bass: Option<f32>,
+    /// High shelf around 3 kHz.
+    treble: Option<f32>,
+    /// Peaks an octave wide, by their center in Hz.
+    bands: BTreeMap<u32, f32>,
+}
+
+/// Gains past this much clip loud sounds or leave quiet ones inaudible.
+const EQ_GAIN_RANGE: RangeInclusive<f32> = -12.0..=12.0;
+
+/// Center frequencies a band can have, about what anybody hears.
+const EQ_FREQ_RANGE: RangeInclusive<u32> = 20..=20_000;
+
+const MAX_EQ_BANDS: usize = 5;
+
+impl Equalizer {
+    /// The ffmpeg audio filters doing it, `None` when it's flat.
+    fn filter(&self) -> Option<String> {
+        let shelves = [("bass", self.bass), ("treble", self.treble)]
+            .into_iter()
+            .filter_map(|(name, gain)| Some(format!("{name}=g={}", gain?)));
+        let bands = self
+            .bands
+            .iter()
+            .map(|(freq, gain)| format!("equalizer=f={freq}:t=o:w=1:g={gain}"));
+        let filters: Vec<String> = shelves.chain(bands).collect();
+        (!filters.is_empty()).then(|| filters.join(","))
+    }
+
+    fn gains(&self) -> impl Iterator<Item = f32> + '_ {
+        self.bass
+            .into_iter()
+            .chain(self.treble)
+            .chain(self.bands.values().copied())
     }
 }
 
-fn is_bot(vs: &VoiceState) -> bool {
-    if let Some(memb) = &vs.member {
-        memb.user.bot
-    } else {
-        false
+impl fmt::Display for Equalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shelves = [("grave", self.bass), ("agudo", self.treble)]
+            .into_iter()
+            .filter_map(|(name, gain)| Some(format!("{name} {:+}dB", gain?)));
+        let bands = self
+            .bands
+            .iter()
+            .map(|(freq, gain)| format!("{freq}Hz {gain:+}dB"));
+        let parts: Vec<String> = shelves.chain(bands).collect();
+        write!(f, "{}", parts.join(", "))
     }
 }
 
-fn joined_channel(old: Option<&VoiceState>, new: &VoiceState) -> Option<ChannelId> {
-    if old.and_then(|old| old.channel_id) != new.channel_id {
-        new.channel_id
-    } else {
-        None
+impl Pan {
+    /// The ffmpeg audio filter doing it.
+    fn filter(self) -> String {
+        match self {
+            Pan::Fixed(balance) => format!("stereotools=balance_out={balance}"),
+            Pan::Sweep => format!("apulsator=hz={PAN_SWEEP_HZ}"),
+        }
     }
 }
 
-struct Disconnect {
-    call: Arc<Mutex<Call>>,
+impl fmt::Display for Pan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pan::Fixed(balance) => write!(f, "{balance}"),
+            Pan::Sweep => write!(f, "sweep"),
+        }
+    }
 }
 
-#[async_trait]
-impl VoiceEventHandler for Disconnect {
-    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        if let EventContext::Track(_) = ctx {
-            if let Err(e) = self.call.lock().await.leave().await {
-                warn!("Error leaving channel: {e}");
-            }
+/// The `eq`, `fade-out` and `pan` of a guild under a name, to switch between them
+/// all at once with `profile`.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct AudioProfile {
+    eq: Equalizer,
+    fade_out_ms: Option<u64>,
+    pan: Option<Pan>,
+}
+
+impl AudioProfile {
+    /// The settings `guild_config` has now.
+    fn of(guild_config: &GuildConfig) -> Self {
+        AudioProfile {
+            eq: guild_config.eq.clone(),
+            fade_out_ms: guild_config.fade_out_ms,
+            pan: guild_config.pan,
         }
-        None
+    }
+
+    fn apply(self, guild_config: &mut GuildConfig) {
+        guild_config.eq = self.eq;
+        guild_config.fade_out_ms = self.fade_out_ms;
+        guild_config.pan = self.pan;
+    }
+}
+
+/// Profiles every guild has, which can't be replaced or removed.
+const BUILTIN_PROFILES: [&str; 3] = ["clean", "loud", "retro"];
+
+fn builtin_profile(name: &str) -> Option<AudioProfile> {
+    let eq = match name {
+        "clean" => Equalizer::default(),
+        "loud" => Equalizer {
+            bass: Some(6.0),
+            treble: Some(4.0),
+            bands: BTreeMap::new(),
+        },
+        // thin and boxy, like an old radio
+        "retro" => Equalizer {
+            bass: Some(-12.0),
+            treble: Some(-9.0),
+            bands: BTreeMap::from([(1000, 6.0)]),
+        },
+        _ => return None,
+    };
+    Some(AudioProfile {
+        eq,
+        ..AudioProfile::default()
+    })
+}
+
+/// Most profiles a guild can save, besides the [`BUILTIN_PROFILES`].
+const MAX_PROFILES: usize = 10;
+
+/// The guild whose settings apply to `key`'s sounds. Users' global sounds and roles'
+/// sounds follow them around, so only the global settings apply to those.
+fn key_guild(key: SoundKey) -> Option<GuildId> {
+    match key {
+        SoundKey::Guild(gid)
+        | SoundKey::Member(gid, _)
+        | SoundKey::Override(gid)
+        | SoundKey::Unmute(gid)
+        | SoundKey::Intro(gid) => Some(gid),
+        SoundKey::User(_) | SoundKey::Role(_) => None,
+    }
+}
+
+/// The ffmpeg filter `key`'s sounds are decoded with, from its guild's settings.
+fn audio_filter(key: SoundKey, guild_configs: &HashMap<GuildId, GuildConfig>) -> Option<String> {
+    let guild_config = guild_configs.get(&key_guild(key)?)?;
+    // afade can only fade out from a given time, and the filter is the same for
+    // sounds of any length, so it fades in the reversed sound instead
+    let fade_out = guild_config.fade_out_ms.map(|ms| {
+        let secs = ms as f64 / 1000.0;
+        format!("areverse,afade=t=in:d={secs},areverse")
+    });
+    let filters: Vec<String> = guild_config
+        .eq
+        .filter()
+        .into_iter()
+        .chain(fade_out)
+        .chain(guild_config.pan.map(Pan::filter))
+        .collect();
+    (!filters.is_empty()).then(|| filters.join(","))
+}
+
+/// Longest sound `key` may set: its guild's limit if it has one, otherwise the global one.
+async fn duration_limit(ctx: &Context, key: SoundKey, config: &Config) -> Duration {
+    let secs = match key_guild(key) {
+        Some(gid) => guild_config(ctx, gid).await.max_duration_secs,
+        None => None,
+    };
+    let limit = secs.map_or(config.max_duration, |secs| {
+        Duration::from_secs(secs).min(config.max_duration_ceiling)
+    });
+    match key {
+        SoundKey::Intro(_) => limit.min(MAX_INTRO_DURATION),
+        _ => limit,
+    }
+}
+
+/// Longest an intro may be, it plays before every sound.
+const MAX_INTRO_DURATION: Duration = Duration::from_secs(5);
+
+async fn key_filter(ctx: &Context, key: SoundKey) -> Option<String> {
+    let data = ctx.data.read().await;
+    let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+    audio_filter(key, guild_configs)
+}
+
+/// Plays the guild's sounds louder than its `volume` for a number of joins or until
+/// some time, whichever runs out first, see `boost`.
+#[derive(Clone, Serialize, Deserialize)]
+struct VolumeBoost {
+    /// Added to the volume, `50` plays them at 150% of it.
+    percent: u32,
+    /// When it runs out, the same way an override does.
+    until: SoundOverride,
+}
+
+/// Most a boost can add to the guild's volume, in percent.
+const MAX_BOOST_PERCENT: u32 = 100;
+
+/// A temporary sound played instead of the guild's usual ones for a number of
+/// joins or until some time, whichever runs out first.
+#[derive(Clone, Serialize, Deserialize)]
+struct SoundOverride {
+    /// Joins left to play it on.
+    remaining: Option<u32>,
+    /// Unix time it stops at.
+    expires_at: Option<u64>,
+}
+
+impl SoundOverride {
+    fn is_active(&self) -> bool {
+        self.remaining != Some(0) && self.expires_at.is_none_or(|at| unix_now() < at)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct SoundConfig {
+    /// How likely the sound is to be picked, relative to the guild's other sounds.
+    weight: u32,
+    /// Only plays while this is active, instead of the guild's unscheduled sounds.
+    schedule: Option<Schedule>,
+    /// Times it played as an entrance sound, for `top`.
+    plays: u64,
+    /// Sounds only play while their category is the guild's `active_category`.
+    category: Option<String>,
+    /// Posted to the guild's `announce_channel` whenever the sound plays.
+    caption: Option<String>,
+    /// Members the sound plays for instead of the unassigned sounds, and only for
+    /// them. Everybody's when empty.
+    only_for: HashSet<UserId>,
+    /// Percent of its volume the sound plays at, on top of the guild's `volume`.
+    volume: Option<u32>,
+    /// Like `only_for`, for members joining from this platform, after those.
+    platform: Option<Platform>,
+    /// Plays over and over, with no gap, for this many seconds instead of once.
+    loop_secs: Option<u32>,
+    /// Replaces the guild's cooldown when this sound is picked, counted from the last
+    /// time it played rather than any sound did.
+    cooldown_secs: Option<u64>,
+    /// Integrated loudness of the file in LUFS, measured when it's set.
+    loudness: Option<f32>,
+}
+
+impl Default for SoundConfig {
+    fn default() -> Self {
+        Self {
+            weight: DEFAULT_WEIGHT,
+            schedule: None,
+            plays: 0,
+            category: None,
+            caption: None,
+            only_for: HashSet::new(),
+            volume: None,
+            platform: None,
+            loop_secs: None,
+            cooldown_secs: None,
+            loudness: None,
+        }
+    }
+}
+
+/// When a sound is active, in local time of `timezone`, daylight saving included.
+#[derive(Clone, Serialize, Deserialize)]
+struct Schedule {
+    /// Every day when empty.
+    days: Vec<Weekday>,
+    /// From the first hour up to, but not including, the second. Wraps around midnight
+    /// when the first is later, e.g. `(22, 2)`. All day when unset.
+    hours: Option<(u32, u32)>,
+    /// IANA name like `America/Sao_Paulo`, checked when the schedule is set.
+    timezone: String,
+}
+
+impl Schedule {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let timezone: Tz = self.timezone.parse().unwrap_or(Tz::UTC);
+        let now = now.with_timezone(&timezone);
+        let on_day = self.days.is_empty() || self.days.contains(&now.weekday());
+        let in_hours = self.hours.is_none_or(|(start, end)| {
+            let hour = now.hour();
+            if start < end {
+                start <= hour && hour < end
+            } else {
+                start <= hour || hour < end
+            }
+        });
+        on_day && in_hours
+    }
+
+    /// Whether it still makes sense, since configs can be imported from anywhere.
+    fn is_valid(&self) -> bool {
+        let hours_ok = self
+            .hours
+            .is_none_or(|(start, end)| start < 24 && end < 24 && start != end);
+        hours_ok && self.timezone.parse::<Tz>().is_ok()
+    }
+}
+
+/// Parses `schedule`'s days, like `fri` or `seg,qua` (`*` for every day), its
+/// optional hours, like `18-23`, and its optional timezone, `default_timezone`
+/// otherwise.
+fn parse_schedule(args: &[&str], default_timezone: Tz) -> Result<Schedule, String> {
+    let (days, rest) = args.split_first().ok_or("Faltou dizer os dias")?;
+    let days = if *days == "*" {
+        Vec::new()
+    } else {
+        days.split(',')
+            .map(|day| parse_weekday(day).ok_or(format!("Não conheço o dia `{day}`")))
+            .collect::<Result<_, _>>()?
+    };
+
+    let (hours, rest) = match rest.split_first() {
+        Some((hours, rest)) if hours.contains('-') => (Some(parse_hours(hours)?), rest),
+        _ => (None, rest),
+    };
+
+    let timezone = match rest {
+        [] => default_timezone,
+        [timezone] => timezone.parse().map_err(|_| {
+            format!("Não conheço o fuso `{timezone}`, usa tipo `America/Sao_Paulo`")
+        })?,
+        _ => return Err("Tem coisa demais aí".to_string()),
+    };
+
+    Ok(Schedule {
+        days,
+        hours,
+        timezone: timezone.name().to_string(),
+    })
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day.to_lowercase().as_str() {
+        "dom" => Some(Weekday::Sun),
+        "seg" => Some(Weekday::Mon),
+        "ter" => Some(Weekday::Tue),
+        "qua" => Some(Weekday::Wed),
+        "qui" => Some(Weekday::Thu),
+        "sex" => Some(Weekday::Fri),
+        "sab" | "sáb" => Some(Weekday::Sat),
+        day => day.parse().ok(),
+    }
+}
+
+fn parse_hours(hours: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Não entendi o horário `{hours}`, usa tipo `18-23`");
+    let (start, end) = hours.split_once('-').ok_or_else(invalid)?;
+    let start: u32 = start.parse().map_err(|_| invalid())?;
+    let end: u32 = end.parse().map_err(|_| invalid())?;
+    if start > 23 || end > 24 || start == end % 24 {
+        return Err(invalid());
+    }
+    Ok((start, end % 24))
+}
+
+const DEFAULT_WEIGHT: u32 = 1;
+
+/// Which sound plays when somebody joins.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PlayMode {
+    /// The guild's sound, welcoming people to the room.
+    #[default]
+    Room,
+    /// The joiner's personal sound announcing them, or the guild's if they have none.
+    Joiner,
+}
+
+/// Guild configs used to be kept together in this file, which gets split up into
+/// `GUILD_CONFIGS_DIR` once loaded.
+const GUILD_CONFIGS_FILE: &str = "guilds.json";
+
+/// Each guild's config is kept in its own `{guild id}.json` in here, apart from
+/// its sounds.
+const GUILD_CONFIGS_DIR: &str = "guilds";
+
+fn guild_config_path(data_dir: &Path, gid: GuildId) -> PathBuf {
+    data_dir.join(GUILD_CONFIGS_DIR).join(format!("{gid}.json"))
+}
+
+async fn load_guild_configs(data_dir: &Path) -> HashMap<GuildId, GuildConfig> {
+    let mut guild_configs = HashMap::new();
+
+    let dir = data_dir.join(GUILD_CONFIGS_DIR);
+    match tokio::fs::read_dir(&dir).await {
+        Ok(mut entries) => loop {
+            let path = match entries.next_entry().await {
+                Ok(Some(entry)) => entry.path(),
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Error reading {dir:?}: {e}");
+                    break;
+                }
+            };
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let gid = match path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.parse().ok())
+            {
+                Some(gid) => GuildId(gid),
+                None => {
+                    warn!("Skipping {path:?}, not named after a guild id");
+                    continue;
+                }
+            };
+            let guild_config = tokio::fs::read(&path).await.map_err(|e| e.to_string());
+            match guild_config
+                .and_then(|content| serde_json::from_slice(&content).map_err(|e| e.to_string()))
+            {
+                Ok(guild_config) => {
+                    guild_configs.insert(gid, guild_config);
+                }
+                Err(e) => error!("Error loading {path:?}, using the default config: {e}"),
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => error!("Error reading {dir:?}, starting with default guild configs: {e}"),
+    }
+
+    migrate_guild_configs(data_dir, &mut guild_configs).await;
+    guild_configs
+}
+
+/// Moves the configs of the old `GUILD_CONFIGS_FILE` to their own files, keeping it
+/// around if any of them can't be saved.
+async fn migrate_guild_configs(data_dir: &Path, guild_configs: &mut HashMap<GuildId, GuildConfig>) {
+    let path = data_dir.join(GUILD_CONFIGS_FILE);
+    let old: HashMap<GuildId, GuildConfig> = match tokio::fs::read(&path).await {
+        Ok(content) => match serde_json::from_slice(&content) {
+            Ok(old) => old,
+            Err(e) => {
+                error!("Error parsing {path:?}, not migrating it: {e}");
+                return;
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            error!("Error reading {path:?}, not migrating it: {e}");
+            return;
+        }
+    };
+
+    let mut migrated = true;
+    for (gid, guild_config) in old {
+        // a config saved since is newer than the old file
+        if guild_configs.contains_key(&gid) {
+            continue;
+        }
+        if let Err(e) = save_guild_config(data_dir, gid, &guild_config).await {
+            warn!("Error migrating the config of guild {gid}: {e}");
+            migrated = false;
+        }
+        guild_configs.insert(gid, guild_config);
+    }
+
+    if migrated {
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => info!("Migrated {path:?} to {GUILD_CONFIGS_DIR:?}"),
+            Err(e) => warn!("Error removing {path:?} after migrating it: {e}"),
+        }
+    }
+}
+
+async fn save_guild_config(
+    data_dir: &Path,
+    gid: GuildId,
+    guild_config: &GuildConfig,
+) -> io::Result<()> {
+    let content = serde_json::to_vec_pretty(guild_config).expect("guild config serializes");
+    write_atomically(&guild_config_path(data_dir, gid), &content).await
+}
+
+/// Changes `gid`'s config with `update` and persists it, right away or with the next
+/// batch when there's a `Config::save_interval`. Reads see the change either way.
+async fn update_guild_config(ctx: &Context, gid: GuildId, update: impl FnOnce(&mut GuildConfig)) {
+    update_guild_config_in(&ctx.data, gid, update).await;
+}
+
+/// [`update_guild_config`] for tasks that have no [`Context`].
+async fn update_guild_config_in(
+    data: &RwLock<TypeMap>,
+    gid: GuildId,
+    update: impl FnOnce(&mut GuildConfig),
+) {
+    let (guild_config, data_dir) = {
+        let mut data = data.write().await;
+        let batched = data
+            .get::<Config>()
+            .expect("config is set")
+            .save_interval
+            .is_some();
+        let defaults = data
+            .get::<GuildDefaults>()
+            .expect("guild defaults are set")
+            .clone()
+            .unwrap_or_default();
+        let guild_configs = data
+            .get_mut::<GuildConfigs>()
+            .expect("guild configs are set");
+        let guild_config = guild_configs.entry(gid).or_insert_with(|| defaults.clone());
+        update(guild_config);
+        if batched {
+            data.get_mut::<Unsaved>()
+                .expect("unsaved is set")
+                .insert(gid);
+            return;
+        }
+        let guild_config = guild_config.clone();
+
+        let config = data.get::<Config>().expect("config is set");
+        (guild_config, config.data_dir.clone())
+    };
+
+    let saved = save_guild_config(&data_dir, gid, &guild_config).await;
+    if let Err(e) = &saved {
+        warn!("Error saving the config of guild {gid}, trying again on shutdown: {e}");
+    }
+    let mut data = data.write().await;
+    let unsaved = data.get_mut::<Unsaved>().expect("unsaved is set");
+    if saved.is_ok() {
+        unsaved.remove(&gid);
+    } else {
+        unsaved.insert(gid);
+    }
+}
+
+struct Unsaved;
+
+impl TypeMapKey for Unsaved {
+    /// Guilds whose config changed in memory and isn't saved yet, because it's waiting
+    /// for the next batch or failed to save. See [`flush`].
+    type Value = HashSet<GuildId>;
+}
+
+/// Longest shutting down waits for [`flush`].
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Saves the configs in [`Unsaved`] every `interval`, see `Config::save_interval`.
+async fn save_periodically(data: Arc<RwLock<TypeMap>>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+
+        let (saved, failed) = save_unsaved(&data).await;
+        for (gid, e) in &failed {
+            warn!("Error saving the config of guild {gid}, trying again later: {e}");
+        }
+        if saved > 0 {
+            debug!(
+                saved,
+                failed = failed.len(),
+                "Saved the changed guild configs"
+            );
+        }
+    }
+}
+
+/// Saves the configs in [`Unsaved`], returning how many were saved and why the rest
+/// couldn't be, which stay there. Each is taken out before saving, so a change made
+/// meanwhile puts it back for the next time.
+async fn save_unsaved(data: &RwLock<TypeMap>) -> (usize, Vec<(GuildId, io::Error)>) {
+    let (unsaved, data_dir) = {
+        let mut data = data.write().await;
+        let gids = std::mem::take(data.get_mut::<Unsaved>().expect("unsaved is set"));
+        let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+        let unsaved: Vec<(GuildId, GuildConfig)> = gids
+            .into_iter()
+            .filter_map(|gid| Some((gid, guild_configs.get(&gid)?.clone())))
+            .collect();
+        let data_dir = data
+            .get::<Config>()
+            .expect("config is set")
+            .data_dir
+            .clone();
+        (unsaved, data_dir)
+    };
+
+    let (mut saved, mut failed) = (0, Vec::new());
+    for (gid, guild_config) in unsaved {
+        match save_guild_config(&data_dir, gid, &guild_config).await {
+            Ok(()) => saved += 1,
+            Err(e) => {
+                let mut data = data.write().await;
+                data.get_mut::<Unsaved>()
+                    .expect("unsaved is set")
+                    .insert(gid);
+                failed.push((gid, e));
+            }
+        }
+    }
+    (saved, failed)
+}
+
+/// Saves what's still only in memory before shutting down: the configs that failed
+/// to save when they changed or were waiting for their batch. Sounds whose file
+/// couldn't be kept have nothing to be saved from, those are only counted so the
+/// loss is in the logs.
+async fn flush(data: &RwLock<TypeMap>) {
+    let flushed = tokio::time::timeout(FLUSH_TIMEOUT, async {
+        let (saved, failed) = save_unsaved(data).await;
+        for (gid, e) in &failed {
+            error!("Error saving the config of guild {gid} on shutdown, it's lost: {e}");
+        }
+        let sound_store = {
+            let data = data.read().await;
+            data.get::<SoundStore>()
+                .expect("sound store is set")
+                .clone()
+        };
+        let fileless = sound_store
+            .read()
+            .await
+            .values()
+            .flatten()
+            .filter(|sound| sound.source.is_none())
+            .count();
+        (saved, failed.len(), fileless)
+    })
+    .await;
+
+    match flushed {
+        Ok((saved, failed, fileless)) => {
+            info!(saved, failed, "Flushed the guild configs left unsaved");
+            if fileless > 0 {
+                warn!("{fileless} sounds have no file and won't be there after restarting");
+            }
+        }
+        Err(_) => error!("Flushing took longer than {FLUSH_TIMEOUT:?}, shutting down anyway"),
+    }
+}
+
+async fn guild_config(ctx: &Context, gid: GuildId) -> GuildConfig {
+    let data = ctx.data.read().await;
+    let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+    match guild_configs.get(&gid) {
+        Some(guild_config) => guild_config.clone(),
+        None => data
+            .get::<GuildDefaults>()
+            .expect("guild defaults are set")
+            .clone()
+            .unwrap_or_default(),
+    }
+}
+
+/// Reads the guild configs back from disk, for edits made outside the bot, swapping
+/// them all in at once so commands see either the old configs or the new ones.
+/// Returns the settings that changed in each guild. Env settings stay as they were,
+/// the environment of a running process doesn't change.
+///
+/// Changes still waiting to be saved are written first, so they aren't read back
+/// over. Guilds whose config changed in memory while the files were being read keep
+/// it, and so do guilds with no file or whose config couldn't be saved.
+async fn reload_guild_configs(data: &RwLock<TypeMap>) -> BTreeMap<GuildId, Vec<String>> {
+    let (_, failed) = save_unsaved(data).await;
+    for (gid, e) in &failed {
+        warn!("Error saving the config of guild {gid} before reloading, keeping it: {e}");
+    }
+    let (data_dir, before) = {
+        let data = data.read().await;
+        let data_dir = data
+            .get::<Config>()
+            .expect("config is set")
+            .data_dir
+            .clone();
+        let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+        (data_dir, guild_configs.clone())
+    };
+    let loaded = load_guild_configs(&data_dir).await;
+
+    let changed = {
+        let mut data = data.write().await;
+        let unsaved = data.get::<Unsaved>().expect("unsaved is set").clone();
+        let guild_configs = data
+            .get_mut::<GuildConfigs>()
+            .expect("guild configs are set");
+        let mut changed = BTreeMap::new();
+        for (gid, new) in loaded {
+            let old = guild_configs.get(&gid);
+            let untouched = match (old, before.get(&gid)) {
+                (Some(old), Some(before)) => settings(old) == settings(before),
+                (None, None) => true,
+                _ => false,
+            };
+            if !untouched || unsaved.contains(&gid) {
+                warn!("The config of guild {gid} changed while reloading, keeping it");
+                continue;
+            }
+            let settings = changed_settings(old.unwrap_or(&GuildConfig::default()), &new);
+            if !settings.is_empty() {
+                changed.insert(gid, settings);
+            }
+            guild_configs.insert(gid, new);
+        }
+        changed
+    };
+
+    for gid in changed.keys() {
+        redecode_guild_sounds(data, *gid).await;
+    }
+    info!("Reloaded guild configs, {} changed", changed.len());
+    changed
+}
+
+/// Names of the settings that differ between `old` and `new`.
+fn changed_settings(old: &GuildConfig, new: &GuildConfig) -> Vec<String> {
+    let (old, new) = (settings(old), settings(new));
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .cloned()
+        .collect()
+}
+
+/// `guild_config` as a json object, with arrays sorted so sets that only differ in
+/// order compare equal.
+fn settings(guild_config: &GuildConfig) -> serde_json::Map<String, serde_json::Value> {
+    fn normalize(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Array(values) => {
+                values.iter_mut().for_each(normalize);
+                values.sort_by_cached_key(|value| value.to_string());
+            }
+            serde_json::Value::Object(map) => map.values_mut().for_each(normalize),
+            _ => {}
+        }
+    }
+
+    let mut value = serde_json::to_value(guild_config).unwrap_or_default();
+    normalize(&mut value);
+    match value {
+        serde_json::Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    }
+}
+
+/// Writes to a temporary file first so a crash mid write doesn't corrupt `path`.
+async fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, content).await?;
+    tokio::fs::rename(tmp, path).await
+}
+
+/// Every guild's config and what sounds there were, all in one file apart from the
+/// data dir, to fall back on when the files in there are lost or corrupt.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    /// Unix time it was taken at.
+    taken_at: u64,
+    guild_configs: HashMap<GuildId, GuildConfig>,
+    /// Only what they were and where their files were, not the audio itself.
+    sounds: Vec<SnapshotSound>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotSound {
+    key: String,
+    label: String,
+    source: Option<PathBuf>,
+}
+
+/// Writes `snapshot` to `path` as a crc32 of the json that follows it on the next
+/// line, so a snapshot that got corrupted is never restored from.
+async fn write_snapshot(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    let json = serde_json::to_vec(snapshot).expect("snapshot serializes");
+    let mut content = format!("{:08x}\n", crc32fast::hash(&json)).into_bytes();
+    content.extend_from_slice(&json);
+    write_atomically(path, &content).await
+}
+
+async fn read_snapshot(path: &Path) -> Result<Snapshot, String> {
+    let content = tokio::fs::read(path).await.map_err(|e| e.to_string())?;
+    let newline = content
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or("no checksum")?;
+    let (checksum, json) = (&content[..newline], &content[newline + 1..]);
+    let checksum = std::str::from_utf8(checksum)
+        .ok()
+        .and_then(|checksum| u32::from_str_radix(checksum, 16).ok())
+        .ok_or("invalid checksum")?;
+    if crc32fast::hash(json) != checksum {
+        return Err("checksum doesn't match, it's corrupt".to_string());
+    }
+    serde_json::from_slice(json).map_err(|e| e.to_string())
+}
+
+/// Writes a [`Snapshot`] to `path` every `interval`.
+async fn take_snapshots(data: Arc<RwLock<TypeMap>>, path: PathBuf, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // the first tick is right away, when there's nothing new to keep
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+
+        let (guild_configs, sound_store) = {
+            let data = data.read().await;
+            let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+            let sound_store = data.get::<SoundStore>().expect("sound store is set");
+            (guild_configs.clone(), sound_store.clone())
+        };
+        let sounds: Vec<SnapshotSound> = sound_store
+            .read()
+            .await
+            .iter()
+            .flat_map(|(key, sounds)| {
+                sounds.iter().map(|sound| SnapshotSound {
+                    key: key.to_string(),
+                    label: sound.label.clone(),
+                    source: sound.source.clone(),
+                })
+            })
+            .collect();
+        let snapshot = Snapshot {
+            taken_at: unix_now(),
+            guild_configs,
+            sounds,
+        };
+        match write_snapshot(&path, &snapshot).await {
+            Ok(()) => info!(
+                guild_configs = snapshot.guild_configs.len(),
+                sounds = snapshot.sounds.len(),
+                "Wrote a snapshot to {path:?}"
+            ),
+            Err(e) => error!("Error writing a snapshot to {path:?}: {e}"),
+        }
+    }
+}
+
+/// Puts back the configs of guilds in the snapshot at `path` that have none in the
+/// data dir, which went missing or couldn't be loaded. Sound files can't be brought
+/// back, the ones missing are only logged.
+async fn restore_missing_configs(
+    path: &Path,
+    data_dir: &Path,
+    guild_configs: &mut HashMap<GuildId, GuildConfig>,
+) {
+    let snapshot = match read_snapshot(path).await {
+        Ok(snapshot) => snapshot,
+        Err(e) if !path.exists() => {
+            debug!("No snapshot to restore from at {path:?}: {e}");
+            return;
+        }
+        Err(e) => {
+            error!("Error reading the snapshot {path:?}, not restoring from it: {e}");
+            return;
+        }
+    };
+
+    let mut restored = 0;
+    for (gid, guild_config) in snapshot.guild_configs {
+        if guild_configs.contains_key(&gid) {
+            continue;
+        }
+        match save_guild_config(data_dir, gid, &guild_config).await {
+            Ok(()) => info!("Restored the config of guild {gid} from the snapshot"),
+            Err(e) => warn!("Error saving the restored config of guild {gid}: {e}"),
+        }
+        guild_configs.insert(gid, guild_config);
+        restored += 1;
+    }
+    for sound in &snapshot.sounds {
+        if let Some(source) = sound.source.as_ref().filter(|source| !source.exists()) {
+            warn!(
+                "Sound {} of {} is in the snapshot but its file {source:?} is gone",
+                sound.label, sound.key
+            );
+        }
+    }
+    if restored > 0 {
+        info!(
+            restored,
+            taken_at = snapshot.taken_at,
+            "Restored guild configs from {path:?}"
+        );
+    }
+}
+
+struct SoundStore;
+
+impl TypeMapKey for SoundStore {
+    type Value = Arc<RwLock<HashMap<SoundKey, Vec<Sound>>>>;
+}
+
+/// The sound store has its own lock, so setting or decoding a sound doesn't hold up
+/// everybody else using `ctx.data`.
+async fn sound_store(ctx: &Context) -> Arc<RwLock<HashMap<SoundKey, Vec<Sound>>>> {
+    sound_store_in(&ctx.data).await
+}
+
+async fn sound_store_in(data: &RwLock<TypeMap>) -> Arc<RwLock<HashMap<SoundKey, Vec<Sound>>>> {
+    let data = data.read().await;
+    data.get::<SoundStore>()
+        .expect("sound store is set")
+        .clone()
+}
+
+struct SoundLocks;
+
+impl TypeMapKey for SoundLocks {
+    type Value = HashMap<SoundKey, Arc<Mutex<()>>>;
+}
+
+/// Held while changing `key`'s sounds, in memory and on disk, so a `set` and a
+/// `remove` of the same sounds happen one after the other instead of interleaving.
+/// Otherwise a set could decode, see the remove go through, and then bring the files
+/// back without the sound, or the other way around.
+async fn lock_sounds(ctx: &Context, key: SoundKey) -> OwnedMutexGuard<()> {
+    lock_sounds_in(&ctx.data, key).await
+}
+
+async fn lock_sounds_in(data: &RwLock<TypeMap>, key: SoundKey) -> OwnedMutexGuard<()> {
+    let lock = {
+        let mut data = data.write().await;
+        let locks = data.get_mut::<SoundLocks>().expect("sound locks are set");
+        locks.entry(key).or_default().clone()
+    };
+    lock.lock_owned().await
+}
+
+struct PlaybackLocks;
+
+impl TypeMapKey for PlaybackLocks {
+    type Value = HashMap<GuildId, Arc<Mutex<()>>>;
+}
+
+/// Held while a voice state update of `gid` decides what to play and starts it. Every
+/// update runs in a task of its own, so this only lines up the joins of one guild,
+/// which would otherwise race each other for its one call: a slow join (decoding an
+/// evicted sound, connecting, the join jitter) in one guild holds up nobody else's.
+async fn lock_playback(ctx: &Context, gid: GuildId) -> OwnedMutexGuard<()> {
+    lock_playback_in(&ctx.data, gid).await
+}
+
+async fn lock_playback_in(data: &RwLock<TypeMap>, gid: GuildId) -> OwnedMutexGuard<()> {
+    let lock = {
+        let mut data = data.write().await;
+        let locks = data
+            .get_mut::<PlaybackLocks>()
+            .expect("playback locks are set");
+        locks.entry(gid).or_default().clone()
+    };
+    lock.lock_owned().await
+}
+
+/// Whose sound it is.
+///
+/// When someone joins, the first of these that has a sound for them plays: the
+/// sound of the guild a channel is [`borrow`]ing from, the guild's
+/// [`SoundKey::Override`], its [`Comeback`] sound if they were away long enough, its
+/// [`Milestone`] sound if they fill the channel up to it, their own sound for the
+/// guild and then their global one (both only in [`PlayMode::Joiner`] guilds), the
+/// sound of their highest role that has one, and then the guild's sound: one
+/// assigned to them with [`SoundConfig::only_for`] if there is any, else one for the
+/// [`Platform`] they joined from, otherwise one of those assigned to nobody.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum SoundKey {
+    Guild(GuildId),
+    /// A user's personal sound, played in guilds using [`PlayMode::Joiner`].
+    User(UserId),
+    /// A user's personal sound for one guild only, played instead of their global one.
+    Member(GuildId, UserId),
+    /// Shared by everyone with the role, whatever the guild's mode.
+    Role(RoleId),
+    /// A guild's temporary sound, see [`SoundOverride`].
+    Override(GuildId),
+    /// Played when a member's server mute is lifted, see [`unmuted_in`].
+    Unmute(GuildId),
+    /// A guild's sting, played right before each sound played for a join or an
+    /// unmute, see [`play_with_intro`].
+    Intro(GuildId),
+}
+
+impl fmt::Display for SoundKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoundKey::Guild(gid) => write!(f, "guild-{gid}"),
+            SoundKey::User(uid) => write!(f, "user-{uid}"),
+            SoundKey::Member(gid, uid) => write!(f, "member-{gid}-{uid}"),
+            SoundKey::Role(rid) => write!(f, "role-{rid}"),
+            SoundKey::Override(gid) => write!(f, "override-{gid}"),
+            SoundKey::Unmute(gid) => write!(f, "unmute-{gid}"),
+            SoundKey::Intro(gid) => write!(f, "intro-{gid}"),
+        }
+    }
+}
+
+impl FromStr for SoundKey {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(uid) = s.strip_prefix("user-") {
+            Ok(SoundKey::User(UserId(uid.parse()?)))
+        } else if let Some(ids) = s.strip_prefix("member-") {
+            // a missing `-` fails parsing the guild id
+            let (gid, uid) = ids.split_once('-').unwrap_or((ids, ""));
+            Ok(SoundKey::Member(
+                GuildId(gid.parse()?),
+                UserId(uid.parse()?),
+            ))
+        } else if let Some(rid) = s.strip_prefix("role-") {
+            Ok(SoundKey::Role(RoleId(rid.parse()?)))
+        } else if let Some(gid) = s.strip_prefix("override-") {
+            Ok(SoundKey::Override(GuildId(gid.parse()?)))
+        } else if let Some(gid) = s.strip_prefix("unmute-") {
+            Ok(SoundKey::Unmute(GuildId(gid.parse()?)))
+        } else if let Some(gid) = s.strip_prefix("intro-") {
+            Ok(SoundKey::Intro(GuildId(gid.parse()?)))
+        } else {
+            // guild sounds used to be kept under the bare guild id
+            let gid = s.strip_prefix("guild-").unwrap_or(s);
+            Ok(SoundKey::Guild(GuildId(gid.parse()?)))
+        }
+    }
+}
+
+struct Sound {
+    /// Name that tells the sound apart from the others of the same key.
+    label: String,
+    /// Decoded audio, `None` while evicted from memory by the cache TTL.
+    memory: Option<CachedAudio>,
+    /// Codec of the original file.
+    codec: Option<String>,
+    /// Sample rate, channels and duration of the original file.
+    metadata: Metadata,
+    /// Hash of the uploaded file, guilds that upload the same file share the decoded audio.
+    hash: ContentHash,
+    /// ffmpeg filter the audio was decoded with, see [`audio_filter`].
+    filter: Option<String>,
+    /// Where the original file is kept, if anywhere.
+    source: Option<PathBuf>,
+    /// When the sound was last set or played.
+    last_used: Instant,
+    /// When the sound was set.
+    set_at: SystemTime,
+}
+
+impl Sound {
+    fn new(label: String, decoded: Decoded, hash: ContentHash, source: Option<PathBuf>) -> Self {
+        Self {
+            label,
+            metadata: decoded.memory.metadata().clone(),
+            memory: Some(decoded.memory),
+            codec: decoded.codec,
+            filter: decoded.filter,
+            hash,
+            source,
+            last_used: Instant::now(),
+            set_at: SystemTime::now(),
+        }
+    }
+}
+
+/// Adds `sound` to `key`'s sounds, replacing the one with the same label.
+fn insert_sound(sound_store: &mut HashMap<SoundKey, Vec<Sound>>, key: SoundKey, sound: Sound) {
+    let sounds = sound_store.entry(key).or_default();
+    sounds.retain(|s| s.label != sound.label);
+    sounds.push(sound);
+}
+
+/// Turns user input into something usable as a label (and directory name).
+fn sanitize_label(label: &str) -> String {
+    let label: String = label
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() => Some(c.to_lowercase().next().unwrap_or(c)),
+            '-' | '_' => Some(c),
+            ' ' | '.' => Some('-'),
+            _ => None,
+        })
+        .take(MAX_LABEL_LEN)
+        .collect();
+
+    if label.is_empty() {
+        "audio".to_string()
+    } else {
+        label
+    }
+}
+
+const MAX_LABEL_LEN: usize = 32;
+
+/// Label from the title tag, followed by the artist when there's room, like the ID3
+/// tags of mp3s. `None` when there's no title or nothing usable is left of it.
+fn label_from_tags(metadata: &Metadata) -> Option<String> {
+    let title = metadata.title.as_deref()?;
+    let tags = match &metadata.artist {
+        Some(artist) => format!("{title} {artist}"),
+        None => title.to_string(),
+    };
+    let label = sanitize_label(tags.trim());
+    // whatever was there got filtered out, so `sanitize_label` fell back
+    (label != "audio" || tags.trim().eq_ignore_ascii_case("audio")).then_some(label)
+}
+
+/// Label for a sound uploaded without one, from its file name.
+fn label_from_filename(name: &str) -> String {
+    let stem = Path::new(name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    sanitize_label(stem)
+}
+
+/// Every so often drops the decoded audio of sounds that haven't played for `ttl`,
+/// `fetch_sound` decodes them again from their source on the next join.
+async fn evict_idle_sounds(data: Arc<RwLock<TypeMap>>, ttl: Duration) {
+    let sound_store = {
+        let data = data.read().await;
+        data.get::<SoundStore>()
+            .expect("sound store is set")
+            .clone()
+    };
+
+    let mut interval = tokio::time::interval(ttl.min(Duration::from_secs(60)));
+    loop {
+        interval.tick().await;
+
+        let mut sound_store = sound_store.write().await;
+        let evicted = sound_store
+            .values_mut()
+            .flatten()
+            .filter(|sound| sound.source.is_some() && sound.last_used.elapsed() >= ttl)
+            .filter_map(|sound| sound.memory.take())
+            .count();
+        if evicted > 0 {
+            info!("Evicted {evicted} idle sounds from memory");
+        }
+    }
+}
+
+/// Gives the next sound its turn in the guilds rotating sounds whose current one has
+/// been playing long enough.
+async fn rotate_sounds(data: Arc<RwLock<TypeMap>>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(MIN_ROTATION_SECS));
+    loop {
+        interval.tick().await;
+
+        let due: Vec<GuildId> = {
+            let data = data.read().await;
+            let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+            let now = unix_now();
+            guild_configs
+                .iter()
+                .filter(|(_, guild_config)| {
+                    guild_config.rotation.as_ref().is_some_and(|rotation| {
+                        rotation.label.is_none()
+                            || now.saturating_sub(rotation.since) >= rotation.every_secs
+                    })
+                })
+                .map(|(gid, _)| *gid)
+                .collect()
+        };
+        for gid in due {
+            if let Some(label) = advance_rotation(&data, gid).await {
+                info!("Rotated guild {gid} to sound {label}");
+            }
+        }
+    }
+}
+
+/// Moves `gid`'s rotation on to the sound after the current one, in label order and
+/// within the active category. Returns the sound whose turn it is now.
+async fn advance_rotation(data: &RwLock<TypeMap>, gid: GuildId) -> Option<String> {
+    let guild_config = {
+        let data = data.read().await;
+        let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+        guild_configs.get(&gid)?.clone()
+    };
+    let current = guild_config.rotation.as_ref()?.label.clone();
+
+    let labels: BTreeSet<String> = {
+        let sound_store = {
+            let data = data.read().await;
+            data.get::<SoundStore>()
+                .expect("sound store is set")
+                .clone()
+        };
+        let sound_store = sound_store.read().await;
+        let in_category = |label: &String| match &guild_config.active_category {
+            Some(active) => guild_config
+                .sounds
+                .get(label)
+                .is_some_and(|config| config.category.as_ref() == Some(active)),
+            None => true,
+        };
+        // sounds assigned to somebody or some platform only take turns for them, and
+        // scheduled ones play on their schedule instead
+        let takes_turns = |label: &String| {
+            guild_config.sounds.get(label).is_none_or(|config| {
+                config.only_for.is_empty() && config.platform.is_none() && config.schedule.is_none()
+            })
+        };
+        sound_store
+            .get(&SoundKey::Guild(gid))?
+            .iter()
+            .map(|sound| sound.label.clone())
+            .filter(in_category)
+            .filter(takes_turns)
+            .collect()
+    };
+    let next = match &current {
+        Some(current) => labels
+            .range::<String, _>((Bound::Excluded(current), Bound::Unbounded))
+            .next()
+            .or_else(|| labels.iter().next()),
+        None => labels.iter().next(),
+    }?
+    .clone();
+
+    update_guild_config_in(data, gid, |guild_config| {
+        if let Some(rotation) = &mut guild_config.rotation {
+            rotation.label = Some(next.clone());
+            rotation.since = unix_now();
+        }
+    })
+    .await;
+    Some(next)
+}
+
+/// Longest request head the admin API reads before giving up on it.
+const MAX_ADMIN_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Longest request body the admin API takes, bigger ones are turned away with a 413.
+const MAX_ADMIN_BODY_BYTES: usize = 1024 * 1024;
+
+/// Most guilds a single bulk job sets sounds or configs for.
+const MAX_ADMIN_BULK: usize = 100;
+
+/// How long a client has to send the whole request head.
+const ADMIN_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most admin API jobs waiting or running at once, more are turned away with a 429.
+const MAX_PENDING_ADMIN_JOBS: usize = 16;
+
+/// Most admin API jobs kept around, finished ones are forgotten oldest first.
+const MAX_ADMIN_JOBS: usize = 100;
+
+struct AdminJobs;
+
+impl TypeMapKey for AdminJobs {
+    /// Jobs submitted through the admin API, by increasing id.
+    type Value = BTreeMap<u64, AdminJob>;
+}
+
+struct DiscordContext;
+
+impl TypeMapKey for DiscordContext {
+    /// Kept from the first ready for the admin API jobs that talk to Discord, `None`
+    /// until the bot has connected.
+    type Value = Option<Context>;
+}
+
+/// What an admin API job does, parsed from the path and body it was submitted with.
+enum AdminJobKind {
+    /// `/jobs/reload`, see [`reload_guild_configs`].
+    Reload,
+    /// `/jobs/sounds`, sets a sound from a link in each guild, like `set` does.
+    SetSounds(Vec<BulkSound>),
+    /// `/jobs/configs`, replaces each guild's config, like `config-import` does.
+    SetConfigs(BTreeMap<GuildId, GuildConfig>),
+}
+
+impl AdminJobKind {
+    fn name(&self) -> &'static str {
+        match self {
+            AdminJobKind::Reload => "/reload",
+            AdminJobKind::SetSounds(_) => "/sounds",
+            AdminJobKind::SetConfigs(_) => "/configs",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BulkSound {
+    guild: GuildId,
+    url: String,
+    /// Added under this label, or replacing the guild's sounds without one.
+    #[serde(default)]
+    label: Option<String>,
+}
+
+/// The job `kind`, the path after `/jobs`, with the operation in `body` for the bulk
+/// ones. `Ok(None)` if there's no such kind, `Err` if the body doesn't fit it.
+fn parse_admin_job(kind: &str, body: &[u8]) -> Result<Option<AdminJobKind>, String> {
+    let kind = match kind {
+        "/reload" => AdminJobKind::Reload,
+        "/sounds" => {
+            let sounds: Vec<BulkSound> = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+            if sounds.len() > MAX_ADMIN_BULK {
+                return Err(format!("at most {MAX_ADMIN_BULK} sounds per job"));
+            }
+            AdminJobKind::SetSounds(sounds)
+        }
+        "/configs" => {
+            let configs: BTreeMap<GuildId, GuildConfig> =
+                serde_json::from_slice(body).map_err(|e| e.to_string())?;
+            if configs.len() > MAX_ADMIN_BULK {
+                return Err(format!("at most {MAX_ADMIN_BULK} configs per job"));
+            }
+            AdminJobKind::SetConfigs(configs)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(kind))
+}
+
+#[derive(Clone, Serialize)]
+struct AdminJob {
+    /// What the job does, the path it was submitted to without the `/jobs` prefix.
+    kind: String,
+    #[serde(flatten)]
+    status: AdminJobStatus,
+    /// Unix time it was submitted at.
+    submitted_at: u64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "status", content = "result", rename_all = "snake_case")]
+enum AdminJobStatus {
+    /// Waiting for one of the [`Downloads`] permits.
+    Queued,
+    Running,
+    Done(serde_json::Value),
+}
+
+/// Queues `kind` to run once there's a [`Downloads`] permit for it, returning its id
+/// and where its result will be sent, or `None` if there are [`MAX_PENDING_ADMIN_JOBS`]
+/// already.
+async fn submit_admin_job(
+    data: Arc<RwLock<TypeMap>>,
+    kind: AdminJobKind,
+) -> Option<(u64, tokio::sync::oneshot::Receiver<serde_json::Value>)> {
+    let (id, downloads) = {
+        let mut data = data.write().await;
+        let downloads = data.get::<Downloads>().expect("downloads are set").clone();
+        let jobs = data.get_mut::<AdminJobs>().expect("admin jobs are set");
+        let pending = jobs
+            .values()
+            .filter(|job| !matches!(job.status, AdminJobStatus::Done(_)))
+            .count();
+        if pending >= MAX_PENDING_ADMIN_JOBS {
+            return None;
+        }
+        while jobs.len() >= MAX_ADMIN_JOBS {
+            let oldest_done = jobs
+                .iter()
+                .find(|(_, job)| matches!(job.status, AdminJobStatus::Done(_)))
+                .map(|(id, _)| *id);
+            match oldest_done {
+                Some(id) => jobs.remove(&id),
+                None => break,
+            };
+        }
+        let id = jobs.keys().next_back().map_or(1, |id| id + 1);
+        let job = AdminJob {
+            kind: kind.name().to_string(),
+            status: AdminJobStatus::Queued,
+            submitted_at: unix_now(),
+        };
+        jobs.insert(id, job);
+        (id, downloads)
+    };
+
+    let (done, result) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let _permit = downloads
+            .acquire_owned()
+            .await
+            .expect("downloads are never closed");
+        set_admin_job_status(&data, id, AdminJobStatus::Running).await;
+        let name = kind.name();
+        let result = run_admin_job(&data, kind).await;
+        info!("Admin API job {id} ({name}) done");
+        set_admin_job_status(&data, id, AdminJobStatus::Done(result.clone())).await;
+        // nobody waits on jobs submitted under `/jobs`
+        let _ = done.send(result);
+    });
+    Some((id, result))
+}
+
+/// Runs `kind`, with the [`Downloads`] permit of the job already held.
+async fn run_admin_job(data: &RwLock<TypeMap>, kind: AdminJobKind) -> serde_json::Value {
+    let ctx = {
+        let data = data.read().await;
+        data.get::<DiscordContext>()
+            .expect("discord context is set")
+            .clone()
+    };
+    match (kind, ctx) {
+        (AdminJobKind::Reload, _) => reload_result(reload_guild_configs(data).await),
+        (AdminJobKind::SetSounds(sounds), Some(ctx)) => set_admin_sounds(&ctx, sounds).await,
+        (AdminJobKind::SetConfigs(configs), Some(ctx)) => set_admin_configs(&ctx, configs).await,
+        (_, None) => serde_json::json!({ "error": "not connected to Discord yet" }),
+    }
+}
+
+/// What a `/jobs/sounds` job does, see [`AdminJobKind::SetSounds`].
+async fn set_admin_sounds(ctx: &Context, sounds: Vec<BulkSound>) -> serde_json::Value {
+    let mut results = Vec::new();
+    for sound in sounds {
+        let gid = sound.guild;
+        let here = ctx.cache.guild_field(gid, |_| ()).await.is_some();
+        let result = if !here {
+            Err("not in this guild".to_string())
+        } else if is_draining(ctx).await {
+            Err(error_reply(&AudioError::Draining, Locale::En))
+        } else {
+            store_url(ctx, &sound.url, SoundKey::Guild(gid), sound.label)
+                .await
+                .map_err(|e| error_reply(&e, Locale::En))
+        };
+        results.push(match result {
+            Ok(label) => serde_json::json!({ "guild": gid, "label": label }),
+            Err(e) => {
+                warn!("Error setting a sound in guild {gid} for the admin API: {e}");
+                serde_json::json!({ "guild": gid, "error": e })
+            }
+        });
+    }
+    serde_json::json!({ "sounds": results })
+}
+
+/// What a `/jobs/configs` job does, see [`AdminJobKind::SetConfigs`].
+async fn set_admin_configs(
+    ctx: &Context,
+    configs: BTreeMap<GuildId, GuildConfig>,
+) -> serde_json::Value {
+    let mut results = serde_json::Map::new();
+    for (gid, imported) in configs {
+        let here = ctx.cache.guild_field(gid, |_| ()).await.is_some();
+        let result = if !here {
+            serde_json::json!({ "error": "not in this guild" })
+        } else if let Err(e) = validate_guild_config(ctx, gid, &imported).await {
+            serde_json::json!({ "error": e })
+        } else {
+            update_guild_config(ctx, gid, |guild_config| *guild_config = imported).await;
+            redecode_guild_sounds(&ctx.data, gid).await;
+            serde_json::json!("ok")
+        };
+        results.insert(gid.to_string(), result);
+    }
+    serde_json::json!({ "guilds": results })
+}
+
+async fn set_admin_job_status(data: &RwLock<TypeMap>, id: u64, status: AdminJobStatus) {
+    let mut data = data.write().await;
+    let jobs = data.get_mut::<AdminJobs>().expect("admin jobs are set");
+    if let Some(job) = jobs.get_mut(&id) {
+        job.status = status;
+    }
+}
+
+/// What `POST /reload` answers with, the settings that changed in each guild.
+fn reload_result(changed: BTreeMap<GuildId, Vec<String>>) -> serde_json::Value {
+    let guilds: serde_json::Map<String, serde_json::Value> = changed
+        .into_iter()
+        .map(|(gid, settings)| (gid.to_string(), settings.into()))
+        .collect();
+    serde_json::json!({ "guilds": guilds })
+}
+
+/// Bare bones HTTP API for tooling, only `POST /reload` (see [`reload_guild_configs`])
+/// answered once done. Every request must carry `Authorization: Bearer <ADMIN_TOKEN>`.
+///
+/// Every operation runs as a job, holding one of the [`Downloads`] permits. Submitted
+/// as `POST /jobs/reload`, `POST /jobs/sounds` (a JSON list of `guild`, `url` and
+/// maybe `label`) or `POST /jobs/configs` (a JSON object of configs by guild id), it's
+/// answered right away with a 202 and the job's id instead, and `GET /jobs/<id>` tells
+/// how it's going and what it returned once done.
+async fn admin_server(data: Arc<RwLock<TypeMap>>, addr: SocketAddr, token: String) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Error binding the admin API to {addr}: {e}");
+            return;
+        }
+    };
+    info!("Admin API listening on {addr}");
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Error accepting admin API connection: {e}");
+                continue;
+            }
+        };
+        let (data, token) = (data.clone(), token.clone());
+        tokio::spawn(async move {
+            if let Err(e) = admin_request(stream, data, &token).await {
+                debug!("Error serving admin API request from {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn admin_request(
+    mut stream: TcpStream,
+    data: Arc<RwLock<TypeMap>>,
+    token: &str,
+) -> io::Result<()> {
+    let read = read_admin_request(&mut stream, token);
+    let request = match tokio::time::timeout(ADMIN_READ_TIMEOUT, read).await {
+        Ok(Ok(Ok(request))) => request,
+        Ok(Ok(Err(status))) => return admin_response(&mut stream, status, "{}").await,
+        Ok(Err(e)) => return Err(e),
+        Err(_) => return admin_response(&mut stream, "408 Request Timeout", "{}").await,
+    };
+
+    let mut parts = request.line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        _ if !request.authorized => ("401 Unauthorized", "{}".to_string()),
+        (Some("POST"), Some("/reload")) => {
+            match submit_admin_job(data, AdminJobKind::Reload).await {
+                Some((_, result)) => match result.await {
+                    Ok(result) => ("200 OK", result.to_string()),
+                    Err(_) => ("500 Internal Server Error", "{}".to_string()),
+                },
+                None => ("429 Too Many Requests", "{}".to_string()),
+            }
+        }
+        (Some(_), Some("/reload")) => ("405 Method Not Allowed", "{}".to_string()),
+        (Some("POST"), Some(path)) if path.starts_with("/jobs/") => {
+            match parse_admin_job(&path["/jobs".len()..], &request.body) {
+                Ok(Some(kind)) => match submit_admin_job(data, kind).await {
+                    Some((id, _)) => (
+                        "202 Accepted",
+                        serde_json::json!({ "job": id, "status": format!("/jobs/{id}") })
+                            .to_string(),
+                    ),
+                    None => ("429 Too Many Requests", "{}".to_string()),
+                },
+                Ok(None) => (
+                    "404 Not Found",
+                    serde_json::json!({ "error": "unknown job kind" }).to_string(),
+                ),
+                Err(e) => (
+                    "400 Bad Request",
+                    serde_json::json!({ "error": e }).to_string(),
+                ),
+            }
+        }
+        (Some("GET"), Some(path)) if path.starts_with("/jobs/") => {
+            let job = match path["/jobs/".len()..].parse::<u64>() {
+                Ok(id) => {
+                    let data = data.read().await;
+                    let jobs = data.get::<AdminJobs>().expect("admin jobs are set");
+                    jobs.get(&id).cloned()
+                }
+                Err(_) => None,
+            };
+            match job {
+                Some(job) => (
+                    "200 OK",
+                    serde_json::to_string(&job).expect("admin job serializes"),
+                ),
+                None => ("404 Not Found", "{}".to_string()),
+            }
+        }
+        (Some(_), Some(path)) if path.starts_with("/jobs/") => {
+            ("405 Method Not Allowed", "{}".to_string())
+        }
+        _ => ("404 Not Found", "{}".to_string()),
+    };
+    admin_response(&mut stream, status, &body).await
+}
+
+struct AdminRequest {
+    line: String,
+    authorized: bool,
+    body: Vec<u8>,
+}
+
+/// Reads a whole request off `stream`, or the status to turn it away with when its
+/// head or body is too long. The body of an unauthorized request is never read.
+async fn read_admin_request(
+    stream: &mut TcpStream,
+    token: &str,
+) -> io::Result<Result<AdminRequest, &'static str>> {
+    // one byte past the limit tells a head that's too long from one that just fits
+    let limit = MAX_ADMIN_REQUEST_BYTES as u64 + 1;
+    let mut reader = BufReader::new(stream.take(limit));
+    let (line, authorized, length) = match read_admin_head(&mut reader, token).await? {
+        Some(head) => head,
+        None => return Ok(Err("431 Request Header Fields Too Large")),
+    };
+    if !authorized {
+        let body = Vec::new();
+        return Ok(Ok(AdminRequest {
+            line,
+            authorized,
+            body,
+        }));
+    }
+    if length > MAX_ADMIN_BODY_BYTES {
+        return Ok(Err("413 Payload Too Large"));
+    }
+
+    // the start of the body may have come in with the head, the rest is past the limit
+    let buffered = reader.buffer().len().min(length);
+    reader.get_mut().set_limit((length - buffered) as u64);
+    let mut body = Vec::with_capacity(length);
+    reader.take(length as u64).read_to_end(&mut body).await?;
+    Ok(Ok(AdminRequest {
+        line,
+        authorized,
+        body,
+    }))
+}
+
+/// The request line of the head `reader` holds, whether it carries `token` and its
+/// `Content-Length`, or `None` when it's longer than [`MAX_ADMIN_REQUEST_BYTES`].
+async fn read_admin_head(
+    mut reader: impl AsyncBufReadExt + Unpin,
+    token: &str,
+) -> io::Result<Option<(String, bool, usize)>> {
+    let mut request_line = String::new();
+    let mut authorized = false;
+    let mut length = 0;
+    let mut read = 0;
+    loop {
+        let mut line = String::new();
+        let n = reader.read_line(&mut line).await?;
+        read += n;
+        if read > MAX_ADMIN_REQUEST_BYTES {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if n == 0 || line.is_empty() {
+            break;
+        }
+        if request_line.is_empty() {
+            request_line = line.to_string();
+        } else if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("authorization") {
+                authorized = value
+                    .trim()
+                    .strip_prefix("Bearer ")
+                    .is_some_and(|given| same_token(given, token));
+            } else if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    Ok(Some((request_line, authorized, length)))
+}
+
+async fn admin_response(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Compares the whole token whatever the first difference, so response times don't
+/// give it away bit by bit.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+const SOUNDS_DIR: &str = "sounds";
+
+/// Where `check-store --fix` moves the persisted sounds that aren't audio, laid out
+/// like [`SOUNDS_DIR`].
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// Where `key`'s sounds are kept, each in a directory named after its label.
+fn sounds_dir(data_dir: &Path, key: SoundKey) -> PathBuf {
+    data_dir.join(SOUNDS_DIR).join(key.to_string())
+}
+
+async fn remove_dir_if_exists(dir: &Path) -> io::Result<()> {
+    match tokio::fs::remove_dir_all(dir).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Keeps the uploaded file as `<data dir>/sounds/<sound key>/<label>/<file name>`,
+/// replacing whatever was there before. With `config.compress_sounds` the file is
+/// zstd compressed and gets an extra `.zst` extension. It's uploaded to the
+/// `Config::object_store` too, if there's one.
+async fn persist_sound(
+    config: &Config,
+    key: SoundKey,
+    label: &str,
+    name: &str,
+    source: &Path,
+) -> io::Result<PathBuf> {
+    let dir = sounds_dir(&config.data_dir, key).join(label);
+    remove_persisted(config, &dir).await?;
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let path = dir.join(stored_name(name, config.redact_filenames));
+    let path = if config.compress_sounds {
+        let mut path = path.into_os_string();
+        path.push(".");
+        path.push(COMPRESSED_EXT);
+        let path = PathBuf::from(path);
+        let (source, tmp) = (source.to_owned(), path.with_extension("tmp"));
+        tokio::task::spawn_blocking(move || {
+            let source = std::fs::File::open(source)?;
+            zstd::stream::copy_encode(source, std::fs::File::create(&tmp)?, 0)?;
+            std::fs::rename(tmp, path.as_path()).map(|_| path)
+        })
+        .await??
+    } else {
+        let tmp = path.with_extension("tmp");
+        tokio::fs::copy(source, &tmp).await?;
+        tokio::fs::rename(tmp, &path).await?;
+        path
+    };
+    upload_persisted(config, &path).await;
+    Ok(path)
+}
+
+/// Removes the persisted sounds in `dir`, from the `Config::object_store` too.
+/// Whatever is left there would come back on the next start.
+async fn remove_persisted(config: &Config, dir: &Path) -> io::Result<()> {
+    remove_dir_if_exists(dir).await?;
+    let (store, prefix) = match (&config.object_store, object_key(config, dir)) {
+        (Some(store), Some(prefix)) => (store, prefix + "/"),
+        _ => return Ok(()),
+    };
+    let removed = async {
+        for key in store.list(&prefix).await? {
+            store.delete(&key).await?;
+        }
+        Ok::<_, String>(())
+    };
+    if let Err(e) = removed.await {
+        warn!("Error removing {prefix} from the object store, it may come back: {e}");
+    }
+    Ok(())
+}
+
+/// Uploads the persisted sound at `path` to the `Config::object_store`, if there's
+/// one. It's still kept locally when that fails.
+async fn upload_persisted(config: &Config, path: &Path) {
+    let (store, key) = match (&config.object_store, object_key(config, path)) {
+        (Some(store), Some(key)) => (store, key),
+        _ => return,
+    };
+    let uploaded = match tokio::fs::read(path).await {
+        Ok(content) => store.put(&key, content).await,
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = uploaded {
+        warn!("Error uploading {key} to the object store, it's only kept locally: {e}");
+    }
+}
+
+/// Where `path`, somewhere in the data directory, goes in the object store.
+fn object_key(config: &Config, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(&config.data_dir).ok()?;
+    let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+    Some(parts?.join("/"))
+}
+
+/// Downloads the persisted sounds in the `Config::object_store` that aren't in the
+/// data directory, so [`load_sounds`] finds them there. Those that fail to download
+/// are left out, like sounds that were never set.
+async fn fetch_persisted_sounds(config: &Config, store: &ObjectStore) {
+    let keys = match store.list(&format!("{SOUNDS_DIR}/")).await {
+        Ok(keys) => keys,
+        Err(e) => {
+            error!("Error listing the sounds in the object store, using the local ones: {e}");
+            return;
+        }
+    };
+
+    let (mut fetched, mut failed) = (0, 0);
+    for key in keys {
+        // whoever else writes to the bucket doesn't get to write outside the data dir
+        if !key
+            .split('/')
+            .all(|part| !matches!(part, "" | "." | "..") && !part.contains('\\'))
+        {
+            warn!("Skipping {key:?} in the object store, it's not a path in the data directory");
+            continue;
+        }
+        let path = key
+            .split('/')
+            .fold(config.data_dir.clone(), |path, part| path.join(part));
+        if tokio::fs::metadata(&path).await.is_ok() {
+            continue;
+        }
+        let written = async {
+            let content = store.get(&key).await?;
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            write_atomically(&path, &content)
+                .await
+                .map_err(|e| e.to_string())
+        };
+        match written.await {
+            Ok(()) => fetched += 1,
+            Err(e) => {
+                failed += 1;
+                warn!("Error fetching {key} from the object store: {e}");
+            }
+        }
+    }
+    if failed > 0 {
+        warn!("{failed} sounds couldn't be fetched from the object store and have to be set again");
+    }
+    info!(fetched, failed, "Fetched the sounds from the object store");
+}
+
+/// An S3 compatible bucket, where the persisted sounds are kept besides the data
+/// directory when it doesn't survive restarts. Requests are signed with AWS
+/// signature version 4 and address the bucket in the path, which AWS and the
+/// likes of MinIO all take. Only the sounds go there: guild configs and
+/// everything else still only live in the data directory, `BACKUP_PATH` is what
+/// keeps those.
+#[derive(Clone)]
+struct ObjectStore {
+    client: reqwest::Client,
+    /// Like `https://s3.us-east-1.amazonaws.com`.
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+/// Longest an object store request may take.
+const OBJECT_STORE_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl ObjectStore {
+    /// From `S3_ENDPOINT`, `S3_REGION`, `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY`, for the bucket in `S3_BUCKET`. None without the keys.
+    fn from_env(bucket: String) -> Option<Self> {
+        let keys = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        );
+        let (access_key, secret_key) = if let (Ok(access_key), Ok(secret_key)) = keys {
+            (access_key, secret_key)
+        } else {
+            error!(
+                "S3_BUCKET is set without AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, object store disabled"
+            );
+            return None;
+        };
+        let region = env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let endpoint = env::var("S3_ENDPOINT")
+            .unwrap_or_else(|_| format!("https://s3.{region}.amazonaws.com"))
+            .trim_end_matches('/')
+            .to_string();
+        Some(Self {
+            client: reqwest::Client::builder()
+                .timeout(OBJECT_STORE_TIMEOUT)
+                .build()
+                .expect("object store client builds"),
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+        })
+    }
+
+    async fn put(&self, key: &str, content: Vec<u8>) -> Result<(), String> {
+        self.request(reqwest::Method::PUT, key, &[], content)
+            .await
+            .map(|_| ())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        self.request(reqwest::Method::GET, key, &[], Vec::new())
+            .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        self.request(reqwest::Method::DELETE, key, &[], Vec::new())
+            .await
+            .map(|_| ())
+    }
+
+    /// Keys of every object starting with `prefix`.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let mut keys = Vec::new();
+        let mut continuation = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2".to_string()),
+                ("prefix", prefix.to_string()),
+            ];
+            if let Some(token) = continuation.take() {
+                query.push(("continuation-token", token));
+            }
+            let body = self
+                .request(reqwest::Method::GET, "", &query, Vec::new())
+                .await?;
+            let body = String::from_utf8_lossy(&body);
+            keys.extend(xml_values(&body, "Key"));
+            continuation = xml_values(&body, "NextContinuationToken")
+                .into_iter()
+                .next();
+            if continuation.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    /// Sends a signed request for the object `key`, or the bucket itself when it's
+    /// empty, and returns the response's body.
+    async fn request(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        query: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> Result<Vec<u8>, String> {
+        use hmac::{Hmac, Mac};
+
+        let hmac = |key: &[u8], data: &str| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes any key");
+            mac.update(data.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        };
+
+        let now = Utc::now();
+        let (date, timestamp) = (
+            now.format("%Y%m%d").to_string(),
+            now.format("%Y%m%dT%H%M%SZ").to_string(),
+        );
+        let path = match key {
+            "" => format!("/{}", self.bucket),
+            key => format!("/{}/{}", self.bucket, s3_encode(key, false)),
+        };
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (s3_encode(name, true), s3_encode(value, true)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let host = self
+            .endpoint
+            .split("://")
+            .last()
+            .unwrap_or_default()
+            .split('/')
+            .next()
+            .unwrap_or_default();
+        let payload_hash = hex(&Sha256::digest(&body));
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{timestamp}\n\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = ["s3", "aws4_request"].iter().fold(
+            hmac(
+                &hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date),
+                &self.region,
+            ),
+            |key, part| hmac(&key, part),
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+            self.access_key,
+            hex(&hmac(&signing_key, &string_to_sign))
+        );
+
+        let url = match query.as_str() {
+            "" => format!("{}{path}", self.endpoint),
+            query => format!("{}{path}?{query}", self.endpoint),
+        };
+        let response = self
+            .client
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", timestamp)
+            .header("authorization", authorization)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("{status}: {}", String::from_utf8_lossy(&body)));
+        }
+        Ok(body.to_vec())
+    }
+}
+
+/// Percent-encodes `s` the way AWS signatures expect, keeping `/` unless it's `query`.
+fn s3_encode(s: &str, query: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if !query => "/".to_string(),
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// The text of every `<tag>` in `xml`, unescaped. Good enough for S3's listings,
+/// which are flat and never nest a tag in one of the same name.
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    xml.split(&open)
+        .skip(1)
+        .filter_map(|rest| rest.split(&close).next())
+        .map(|value| {
+            value
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&")
+        })
+        .collect()
+}
+
+/// Extension of the persisted sounds compressed with zstd.
+const COMPRESSED_EXT: &str = "zst";
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == COMPRESSED_EXT)
+}
+
+/// Reads a persisted sound, decompressing it if it was compressed.
+async fn read_sound_file(path: &Path) -> io::Result<Vec<u8>> {
+    let content = tokio::fs::read(path).await?;
+    if is_compressed(path) {
+        tokio::task::spawn_blocking(move || zstd::decode_all(content.as_slice())).await?
+    } else {
+        Ok(content)
+    }
+}
+
+/// Name of the persisted sound at `path` as it was uploaded.
+fn sound_file_name(path: &Path) -> String {
+    let name = if is_compressed(path) {
+        path.file_stem()
+    } else {
+        path.file_name()
+    };
+    name.map_or_else(|| "audio".to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+/// Decodes a persisted sound. ffmpeg can't read compressed ones, so those are
+/// decoded from a decompressed temporary copy.
+async fn decode_persisted(
+    key: SoundKey,
+    path: &Path,
+    filter: Option<String>,
+    config: &Config,
+) -> Result<Decoded, AudioError> {
+    if !is_compressed(path) {
+        return decode(path, None, filter, config).await;
+    }
+
+    let io_error = |e| AudioError::Decode(InputError::Io(e));
+    let content = read_sound_file(path).await.map_err(io_error)?;
+    let tmp = decompressed_copy(key, path, &content)
+        .await
+        .map_err(io_error)?;
+
+    let decoded = decode(&tmp, None, filter, config).await;
+    if let Err(e) = tokio::fs::remove_file(&tmp).await {
+        warn!("Error deleting {tmp:?}: {e}");
+    }
+    decoded
+}
+
+/// Writes the decompressed `content` of `key`'s persisted sound at `path` to the temp
+/// dir for ffmpeg to read, see [`temp_path`]. The caller removes it.
+async fn decompressed_copy(key: SoundKey, path: &Path, content: &[u8]) -> io::Result<PathBuf> {
+    let tmp = temp_path(key, &sound_file_name(path));
+    tokio::fs::write(&tmp, content).await?;
+    Ok(tmp)
+}
+
+/// Probes a persisted sound, from a decompressed temporary copy if it's
+/// compressed, giving up after `Config::audio_timeout`.
+async fn probe_persisted(
+    key: SoundKey,
+    path: &Path,
+    content: &[u8],
+    config: &Config,
+) -> Result<Probe, InputError> {
+    let tmp = if is_compressed(path) {
+        Some(decompressed_copy(key, path, content).await?)
+    } else {
+        None
+    };
+
+    let probing = probe(tmp.as_deref().unwrap_or(path), config);
+    let probed = match tokio::time::timeout(config.audio_timeout, probing).await {
+        Ok(probed) => probed,
+        Err(_) => Err(InputError::Io(io::ErrorKind::TimedOut.into())),
+    };
+    if let Some(tmp) = tmp {
+        if let Err(e) = tokio::fs::remove_file(&tmp).await {
+            warn!("Error deleting {tmp:?}: {e}");
+        }
+    }
+    probed
+}
+
+/// Decodes the sounds persisted by `persist_sound` back into `sound_store`, taking
+/// those still the same from `cached_pcm` instead.
+async fn load_sounds(
+    config: &Config,
+    guild_configs: &HashMap<GuildId, GuildConfig>,
+    cached_pcm: &mut HashMap<(SoundKey, String), (CachedPcm, PcmAt)>,
+    sound_store: &mut HashMap<SoundKey, Vec<Sound>>,
+) {
+    let files = match persisted_files(config).await {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Error reading persisted sounds: {e}");
+            return;
+        }
+    };
+
+    let (mut loaded, mut from_cache) = (0, 0);
+    for (key, label, path) in files {
+        let filter = audio_filter(key, guild_configs);
+        let cached = match cached_pcm.remove(&(key, label.clone())) {
+            Some(cached) => restore_cached_pcm(&label, &path, &filter, cached).await,
+            None => None,
+        };
+        let sound = match cached {
+            Some(sound) => {
+                from_cache += 1;
+                Some(sound)
+            }
+            None => load_sound_file(key, label, path, filter, config).await,
+        };
+        if let Some(sound) = sound {
+            insert_sound(sound_store, key, sound);
+            loaded += 1;
+        }
+    }
+
+    info!("Loaded {loaded} persisted sounds, {from_cache} of them from the PCM cache");
+}
+
+/// The files `persist_sound` left in the data directory, with the key and label of
+/// each. None when there's no sounds directory yet.
+async fn persisted_files(config: &Config) -> io::Result<Vec<(SoundKey, String, PathBuf)>> {
+    let mut key_dirs = match tokio::fs::read_dir(config.data_dir.join(SOUNDS_DIR)).await {
+        Ok(key_dirs) => key_dirs,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut files = Vec::new();
+    while let Ok(Some(key_dir)) = key_dirs.next_entry().await {
+        let key = match key_dir.file_name().to_str().and_then(|n| n.parse().ok()) {
+            Some(key) => key,
+            None => continue,
+        };
+
+        let mut entries = match tokio::fs::read_dir(key_dir.path()).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Error reading persisted sounds of {key}: {e}");
+                continue;
+            }
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.file_type().await.is_ok_and(|t| t.is_dir()) {
+                let label = entry.file_name().to_string_lossy().into_owned();
+                if let Some(path) = first_file(&entry.path()).await {
+                    files.push((key, label, path));
+                }
+            } else {
+                // sounds used to be kept straight in the key's directory, one per key
+                let path = entry.path();
+                let label = label_from_filename(&entry.file_name().to_string_lossy());
+                files.push((key, label, path));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Starts every file written by [`write_pcm_cache`], followed by its
+/// [`PCM_CACHE_VERSION`].
+const PCM_CACHE_MAGIC: &[u8; 8] = b"dbot-pcm";
+
+/// Bumped whenever [`CachedPcm`] or how the sounds are decoded changes, so caches
+/// written by another version are decoded again instead of played wrong.
+const PCM_CACHE_VERSION: u32 = 1;
+
+/// What's written before the decoded audio of each sound in the `Config::pcm_cache`
+/// file. Each one is its length as a little endian u32, itself as json, and then
+/// the length of the audio as a little endian u64 and the audio itself, the raw
+/// samples songbird kept in its [`Memory`].
+#[derive(Serialize, Deserialize)]
+struct CachedPcm {
+    key: String,
+    label: String,
+    /// [`Sound::hash`] of the file it was decoded from, it's decoded again if the
+    /// file changed.
+    hash: ContentHash,
+    /// Decoded again if the guild's [`audio_filter`] changed too.
+    filter: Option<String>,
+    codec: Option<String>,
+    stereo: bool,
+    /// `f32` samples rather than `i16`.
+    float: bool,
+    sample_rate: Option<u32>,
+    channels: Option<u8>,
+    duration: Option<Duration>,
+}
+
+/// The longest [`CachedPcm`] read back, entries with a longer one are skipped.
+const MAX_CACHED_PCM_HEADER: u32 = 64 * 1024;
+
+/// Where a sound's audio is in the `Config::pcm_cache` file. It's only read once the
+/// sound turns out to be the same as when it was cached, so the cache is never in
+/// memory all at once.
+struct PcmAt {
+    path: PathBuf,
+    offset: u64,
+    len: u64,
+}
+
+impl PcmAt {
+    async fn read(self) -> io::Result<Vec<u8>> {
+        tokio::task::spawn_blocking(move || {
+            let mut file = std::fs::File::open(&self.path)?;
+            file.seek(SeekFrom::Start(self.offset))?;
+            let mut pcm = Vec::new();
+            file.take(self.len).read_to_end(&mut pcm)?;
+            if pcm.len() as u64 != self.len {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            Ok(pcm)
+        })
+        .await?
+    }
+}
+
+/// Writes every decoded sound that's kept in memory as raw PCM to `path`, returning
+/// how many. Evicted sounds and those cached as opus are left out.
+async fn write_pcm_cache(data: &RwLock<TypeMap>, path: &Path) -> io::Result<usize> {
+    let sound_store = {
+        let data = data.read().await;
+        data.get::<SoundStore>()
+            .expect("sound store is set")
+            .clone()
+    };
+    let sounds: Vec<(CachedPcm, Memory)> = sound_store
+        .read()
+        .await
+        .iter()
+        .flat_map(|(key, sounds)| {
+            sounds.iter().filter_map(move |sound| match &sound.memory {
+                Some(CachedAudio::Raw(memory))
+                    if sound.source.is_some() && matches!(memory.container, Container::Raw) =>
+                {
+                    let float = match memory.kind {
+                        CodecType::FloatPcm => true,
+                        CodecType::Pcm => false,
+                        _ => return None,
+                    };
+                    Some((
+                        CachedPcm {
+                            key: key.to_string(),
+                            label: sound.label.clone(),
+                            hash: sound.hash,
+                            filter: sound.filter.clone(),
+                            codec: sound.codec.clone(),
+                            stereo: memory.stereo,
+                            float,
+                            sample_rate: sound.metadata.sample_rate,
+                            channels: sound.metadata.channels,
+                            duration: sound.metadata.duration,
+                        },
+                        memory.clone(),
+                    ))
+                }
+                _ => None,
+            })
+        })
+        .collect();
+
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        let mut file = io::BufWriter::new(std::fs::File::create(&tmp)?);
+        file.write_all(PCM_CACHE_MAGIC)?;
+        file.write_all(&PCM_CACHE_VERSION.to_le_bytes())?;
+        for (cached, memory) in &sounds {
+            let mut pcm = Vec::new();
+            memory.raw.new_handle().read_to_end(&mut pcm)?;
+            let header = serde_json::to_vec(cached).expect("cached pcm serializes");
+            file.write_all(&(header.len() as u32).to_le_bytes())?;
+            file.write_all(&header)?;
+            file.write_all(&(pcm.len() as u64).to_le_bytes())?;
+            file.write_all(&pcm)?;
+        }
+        file.flush()?;
+        std::fs::rename(tmp, &path)?;
+        Ok(sounds.len())
+    })
+    .await?
+}
+
+/// Like [`write_pcm_cache`], logging how it went.
+async fn save_pcm_cache(data: &RwLock<TypeMap>, path: &Path) -> io::Result<usize> {
+    let started = Instant::now();
+    let written = write_pcm_cache(data, path).await;
+    match &written {
+        Ok(sounds) => info!(
+            sounds,
+            elapsed = ?started.elapsed(),
+            "Wrote the PCM cache to {path:?}"
+        ),
+        Err(e) => error!("Error writing the PCM cache to {path:?}: {e}"),
+    }
+    written
+}
+
+/// Reads back where the audio [`write_pcm_cache`] wrote to `path` is, nothing if it's
+/// missing or from another [`PCM_CACHE_VERSION`], and what could be read if it got
+/// cut short.
+async fn read_pcm_cache(path: &Path) -> HashMap<(SoundKey, String), (CachedPcm, PcmAt)> {
+    let owned_path = path.to_owned();
+    let read = tokio::task::spawn_blocking(move || {
+        let mut file = io::BufReader::new(std::fs::File::open(&owned_path)?);
+        let mut magic = [0; PCM_CACHE_MAGIC.len()];
+        let mut version = [0; 4];
+        file.read_exact(&mut magic)?;
+        file.read_exact(&mut version)?;
+        if &magic != PCM_CACHE_MAGIC || u32::from_le_bytes(version) != PCM_CACHE_VERSION {
+            return Ok((
+                HashMap::new(),
+                Some("written by another version".to_string()),
+            ));
+        }
+
+        let file_len = file.get_ref().metadata()?.len();
+        let mut offset = (magic.len() + version.len()) as u64;
+        let mut cached = HashMap::new();
+        loop {
+            let mut len = [0; 4];
+            match file.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok((cached, None)),
+                Err(e) => return Ok((cached, Some(e.to_string()))),
+            }
+            let header_len = u32::from_le_bytes(len);
+            let entry = (|| {
+                // only the headers are read, the audio is skipped over
+                let header = if header_len > MAX_CACHED_PCM_HEADER {
+                    file.seek_relative(header_len.into())?;
+                    None
+                } else {
+                    let mut header = vec![0; header_len as usize];
+                    file.read_exact(&mut header)?;
+                    Some(serde_json::from_slice::<CachedPcm>(&header)?)
+                };
+                let mut len = [0; 8];
+                file.read_exact(&mut len)?;
+                let len = u64::from_le_bytes(len);
+                offset += 4 + u64::from(header_len) + 8;
+                let skip = i64::try_from(len)
+                    .ok()
+                    .filter(|_| offset.saturating_add(len) <= file_len)
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+                file.seek_relative(skip)?;
+                let at = PcmAt {
+                    path: owned_path.clone(),
+                    offset,
+                    len,
+                };
+                offset += len;
+                Ok::<_, io::Error>(header.map(|header| (header, at)))
+            })();
+            match entry {
+                Ok(Some((header, at))) => {
+                    if let Ok(key) = header.key.parse() {
+                        cached.insert((key, header.label.clone()), (header, at));
+                    }
+                }
+                Ok(None) => warn!("Skipping a PCM cache entry with a {header_len} byte header"),
+                Err(e) => return Ok((cached, Some(e.to_string()))),
+            }
+        }
+    })
+    .await
+    .map_err(io::Error::from)
+    .and_then(|read| read);
+
+    match read {
+        Ok((cached, None)) => {
+            info!("Read {} sounds from the PCM cache {path:?}", cached.len());
+            cached
+        }
+        Ok((cached, Some(e))) => {
+            warn!(
+                "PCM cache {path:?} is unusable past {} sounds, decoding the rest: {e}",
+                cached.len()
+            );
+            cached
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+        Err(e) => {
+            warn!("Error reading the PCM cache {path:?}, decoding every sound: {e}");
+            HashMap::new()
+        }
+    }
+}
+
+/// The sound persisted at `path`, from the audio `cached` for it if it was decoded
+/// from the same file with the same `filter`.
+async fn restore_cached_pcm(
+    label: &str,
+    path: &Path,
+    filter: &Option<String>,
+    (cached, at): (CachedPcm, PcmAt),
+) -> Option<Sound> {
+    let content = read_sound_file(path).await.ok()?;
+    let hash = content_hash(&content);
+    if cached.hash != hash || &cached.filter != filter {
+        debug!("Cached PCM of {path:?} is stale, decoding it again");
+        return None;
+    }
+    let pcm = match at.read().await {
+        Ok(pcm) => pcm,
+        Err(e) => {
+            warn!("Error reading the cached PCM of {path:?}, decoding it again: {e}");
+            return None;
+        }
+    };
+
+    let metadata = Metadata {
+        sample_rate: cached.sample_rate,
+        channels: cached.channels,
+        duration: cached.duration,
+        ..Default::default()
+    };
+    let codec = if cached.float {
+        Codec::FloatPcm
+    } else {
+        Codec::Pcm
+    };
+    let input = Input::new(
+        cached.stereo,
+        Reader::from_memory(pcm),
+        codec,
+        Container::Raw,
+        Some(metadata),
+    );
+    let memory = match Memory::new(input) {
+        Ok(memory) => memory,
+        Err(e) => {
+            warn!("Error restoring the cached PCM of {path:?}, decoding it again: {e}");
+            return None;
+        }
+    };
+    let decoded = Decoded {
+        memory: CachedAudio::Raw(memory),
+        codec: cached.codec,
+        filter: cached.filter,
+    };
+    let mut sound = Sound::new(label.to_string(), decoded, hash, Some(path.to_owned()));
+    if let Ok(set_at) = tokio::fs::metadata(path)
+        .await
+        .and_then(|meta| meta.modified())
+    {
+        sound.set_at = set_at;
+    }
+    Some(sound)
+}
+
+/// Reads and decodes the sound file at `path`, logging what went wrong if it can't.
+async fn load_sound_file(
+    key: SoundKey,
+    label: String,
+    path: PathBuf,
+    filter: Option<String>,
+    config: &Config,
+) -> Option<Sound> {
+    let content = match read_sound_file(&path).await {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("Error reading sound file {path:?}: {e}");
+            return None;
+        }
+    };
+
+    let set_at = tokio::fs::metadata(&path)
+        .await
+        .and_then(|meta| meta.modified())
+        .unwrap_or_else(|_| SystemTime::now());
+    match decode_persisted(key, &path, filter, config).await {
+        Ok(decoded) => {
+            let hash = content_hash(&content);
+            let mut sound = Sound::new(label, decoded, hash, Some(path));
+            sound.set_at = set_at;
+            Some(sound)
+        }
+        Err(e) => {
+            warn!("Error decoding sound file {path:?}: {e}");
+            None
+        }
+    }
+}
+
+async fn first_file(dir: &Path) -> Option<PathBuf> {
+    let mut entries = tokio::fs::read_dir(dir).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_type().await.is_ok_and(|t| t.is_file()) {
+            return Some(entry.path());
+        }
+    }
+    None
+}
+
+/// Decodes the operator provided default sounds listed in `manifest`, guilds that
+/// already have a sound in `sound_store` keep it.
+async fn load_manifest(
+    manifest: &Path,
+    config: &Config,
+    guild_configs: &HashMap<GuildId, GuildConfig>,
+    sound_store: &mut HashMap<SoundKey, Vec<Sound>>,
+) {
+    let entries: HashMap<GuildId, PathBuf> = match tokio::fs::read(manifest).await {
+        Ok(content) => match serde_json::from_slice(&content) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Error parsing sound manifest {manifest:?}: {e}");
+                return;
+            }
+        },
+        Err(e) => {
+            error!("Error reading sound manifest {manifest:?}: {e}");
+            return;
+        }
+    };
+
+    for (gid, path) in entries {
+        let key = SoundKey::Guild(gid);
+        if sound_store.contains_key(&key) {
+            info!("Skipping manifest sound for guild {gid}, it already has one");
+            continue;
+        }
+
+        let label = path
+            .file_name()
+            .map(|name| label_from_filename(&name.to_string_lossy()))
+            .unwrap_or_else(|| sanitize_label(""));
+        if let Some(sound) = load_sound_file(
+            key,
+            label,
+            path.clone(),
+            audio_filter(key, guild_configs),
+            config,
+        )
+        .await
+        {
+            info!("Loaded manifest sound {path:?} for guild {gid}");
+            insert_sound(sound_store, key, sound);
+        }
+    }
+}
+
+/// Imports the files in `dir` named after `config.import_pattern` as guild sounds,
+/// persisting them like uploaded ones. Sounds already in `sound_store` are
+/// skipped, so importing the same folder again is harmless. Those of guilds with a
+/// `loudness_target` are measured for `agc` too.
+async fn import_sounds(
+    dir: &Path,
+    config: &Config,
+    guild_configs: &mut HashMap<GuildId, GuildConfig>,
+    sound_store: &mut HashMap<SoundKey, Vec<Sound>>,
+) {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Error reading import folder {dir:?}: {e}");
+            return;
+        }
+    };
+
+    let mut imported = 0;
+    let mut failed = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let stem = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy(),
+            None => continue,
+        };
+        let (gid, label) = match parse_import_name(&config.import_pattern, &stem) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        let key = SoundKey::Guild(gid);
+        let label = sanitize_label(&label);
+        let exists = sound_store
+            .get(&key)
+            .is_some_and(|sounds| sounds.iter().any(|s| s.label == label));
+        if exists {
+            continue;
+        }
+
+        let persisted = match persist_sound(config, key, &label, &name, &path).await {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                warn!("Error importing {path:?}: {e}");
+                failed.push(name);
+                continue;
+            }
+        };
+
+        match load_sound_file(
+            key,
+            label,
+            persisted.clone(),
+            audio_filter(key, guild_configs),
+            config,
+        )
+        .await
+        {
+            Some(sound) => {
+                if let Some(guild_config) = guild_configs.get_mut(&gid) {
+                    if guild_config.loudness_target.is_some() {
+                        let loudness = loudness(&persisted, config).await;
+                        let label = sound.label.clone();
+                        guild_config.sounds.entry(label).or_default().loudness = loudness;
+                        if let Err(e) = save_guild_config(&config.data_dir, gid, guild_config).await
+                        {
+                            warn!("Error saving the loudness of {path:?} for guild {gid}: {e}");
+                        }
+                    }
+                }
+                let activity = Activity {
+                    at: unix_now(),
+                    kind: ActivityKind::Set,
+                    label: Some(sound.label.clone()),
+                    user: None,
+                };
+                // nothing else runs yet to write the log meanwhile
+                if let Err(e) = append_activity(&config.data_dir, gid, &activity).await {
+                    warn!("Error recording the import of {path:?} for guild {gid}: {e}");
+                }
+                insert_sound(sound_store, key, sound);
+                imported += 1;
+            }
+            None => {
+                if let Some(dir) = persisted.parent() {
+                    if let Err(e) = remove_persisted(config, dir).await {
+                        warn!("Error removing {dir:?}: {e}");
+                    }
+                }
+                failed.push(name);
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        info!("Imported {imported} sounds from {dir:?}");
+    } else {
+        warn!(
+            "Imported {imported} sounds from {dir:?}, {} failed: {}",
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+}
+
+/// Matches a file name (without extension) against `pattern`, where `{guild}`
+/// stands for the guild id and `{label}` for the sound's name, e.g. the default
+/// `{guild}_{label}` matches `123456_airhorn`.
+fn parse_import_name(pattern: &str, name: &str) -> Option<(GuildId, String)> {
+    let mut pattern = pattern;
+    let mut name = name;
+    let mut gid = None;
+    let mut label = None;
+
+    while !pattern.is_empty() {
+        if let Some(rest) = pattern.strip_prefix("{guild}") {
+            let end = name
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(name.len());
+            gid = Some(GuildId(name[..end].parse().ok()?));
+            name = &name[end..];
+            pattern = rest;
+        } else if let Some(rest) = pattern.strip_prefix("{label}") {
+            // the label runs until whatever the pattern expects next
+            let literal_end = rest.find('{').unwrap_or(rest.len());
+            let end = match &rest[..literal_end] {
+                "" if rest.is_empty() => name.len(),
+                "" => name.find(|c: char| c.is_ascii_digit())?,
+                literal => name.find(literal)?,
+            };
+            label = Some(name[..end].to_string());
+            name = &name[end..];
+            pattern = rest;
+        } else {
+            let first = pattern.chars().next().map_or(0, char::len_utf8);
+            let literal_end = pattern[first..]
+                .find('{')
+                .map_or(pattern.len(), |i| i + first);
+            name = name.strip_prefix(&pattern[..literal_end])?;
+            pattern = &pattern[literal_end..];
+        }
+    }
+
+    match (gid, label) {
+        (Some(gid), Some(label)) if name.is_empty() && !label.is_empty() => Some((gid, label)),
+        _ => None,
+    }
+}
+
+/// SHA-256 of a file's content. Sounds are shared across guilds by it, so it has to
+/// hold up against somebody crafting a file to collide with another guild's.
+type ContentHash = [u8; 32];
+
+fn content_hash(content: &[u8]) -> ContentHash {
+    Sha256::digest(content).into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Replies to `msg`, backing off and retrying a few times when Discord rate limits
+/// us anyway.
+async fn respond(ctx: &Context, msg: &Message, content: impl fmt::Display) {
+    let content = content.to_string();
+    let mut backoff = Duration::from_secs(1);
+    for _ in 0..REPLY_ATTEMPTS {
+        match msg.reply(ctx, &content).await {
+            Err(serenity::Error::Http(e)) if is_rate_limited(&e) => {
+                warn!("Rate limited replying, retrying in {backoff:?}");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => {
+                warn!("Error replying: {e}");
+                return;
+            }
+            Ok(_) => return,
+        }
+    }
+    warn!("Error replying: still rate limited after {REPLY_ATTEMPTS} attempts");
+}
+
+const REPLY_ATTEMPTS: u32 = 3;
+
+/// Replies that a command worked, see [`acknowledge`].
+async fn confirm(ctx: &Context, msg: &Message, content: impl fmt::Display) {
+    acknowledge(ctx, msg, true, content).await;
+}
+
+/// Replies that a command didn't work, see [`acknowledge`].
+async fn refuse(ctx: &Context, msg: &Message, content: impl fmt::Display) {
+    acknowledge(ctx, msg, false, content).await;
+}
+
+/// [`confirm`]s what's `Ok` and [`refuse`]s what's `Err`.
+async fn answer<T: fmt::Display, E: fmt::Display>(
+    ctx: &Context,
+    msg: &Message,
+    reply: Result<T, E>,
+) {
+    match reply {
+        Ok(content) => confirm(ctx, msg, content).await,
+        Err(content) => refuse(ctx, msg, content).await,
+    }
+}
+
+/// Replies how a command went, `ok` or not, or only reacts if the guild's
+/// [`Verbosity`] doesn't want that reply.
+async fn acknowledge(ctx: &Context, msg: &Message, ok: bool, content: impl fmt::Display) {
+    if wants_reply(ctx, msg, ok).await {
+        respond(ctx, msg, content).await;
+        return;
+    }
+
+    let reaction = if ok { '✅' } else { '❌' };
+    if let Err(e) = msg.react(ctx, reaction).await {
+        // most likely missing Add Reactions, so answer in text instead
+        warn!("Error reacting: {e}");
+        respond(ctx, msg, content).await;
+    }
+}
+
+async fn wants_reply(ctx: &Context, msg: &Message, ok: bool) -> bool {
+    let verbosity = match msg.guild_id {
+        Some(gid) => guild_config(ctx, gid).await.verbosity,
+        None => Verbosity::Verbose,
+    };
+    match verbosity {
+        Verbosity::Verbose => true,
+        Verbosity::Quiet => !ok,
+        Verbosity::Silent => false,
+    }
+}
+
+fn is_rate_limited(e: &HttpError) -> bool {
+    matches!(e, HttpError::UnsuccessfulRequest(response) if response.status_code.as_u16() == 429)
+}
+
+fn is_forbidden(e: &HttpError) -> bool {
+    matches!(e, HttpError::UnsuccessfulRequest(response) if response.status_code.as_u16() == 403)
+}
+
+#[group]
+#[commands(
+    set,
+    set_mine,
+    set_base64,
+    set_pack,
+    set_unmute,
+    set_intro,
+    set_role,
+    sharing,
+    copy_from,
+    borrow,
+    nudge_unset,
+    remove,
+    remove_matching,
+    list,
+    weight,
+    category,
+    theme,
+    categories,
+    rotate,
+    rotate_next,
+    assign,
+    comeback,
+    milestone,
+    boost_sound,
+    platform,
+    schedule,
+    quiet_hours,
+    replay,
+    replay_last,
+    soundboard,
+    setup,
+    paused,
+    top,
+    reset_top,
+    preview,
+    latency,
+    export,
+    source_info,
+    min_members,
+    cooldown,
+    linger,
+    simulate_joins,
+    next_sound,
+    chance,
+    play_afk,
+    mode,
+    first_join,
+    reset_seen,
+    command_channel,
+    announce_channel,
+    transcript_channel,
+    caption,
+    block_channel,
+    unblock_channel,
+    block_user,
+    unblock_user,
+    silence,
+    unsilence,
+    allow_bot,
+    disallow_bot,
+    set_message,
+    farewell,
+    nickname,
+    bitrate,
+    pan,
+    eq,
+    eq_preview,
+    filter_preview,
+    fade_out,
+    profile,
+    volume,
+    agc,
+    boost,
+    loop_sound,
+    language,
+    verbosity,
+    max_duration,
+    status,
+    hold,
+    release,
+    stay,
+    follow_member,
+    sound_override,
+    config_export,
+    stats_export,
+    config_import
+)]
+#[checks(CommandChannel)]
+struct General;
+
+// Ignores commands sent outside the guild's command channel, pointing there
+// instead. `command-channel` itself works anywhere, so a deleted channel can't
+// lock the admins out.
+#[check]
+#[name = "CommandChannel"]
+async fn command_channel_check(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    options: &CommandOptions,
+) -> Result<(), Reason> {
+    let gid = match msg.guild_id {
+        Some(gid) => gid,
+        None => return Ok(()),
+    };
+    if options.names.contains(&"command-channel") {
+        return Ok(());
+    }
+
+    match guild_config(ctx, gid).await.command_channel {
+        Some(channel_id) if channel_id != msg.channel_id => {
+            let reply = format!("Aqui não, manda lá no {}", channel_id.mention());
+            refuse(ctx, msg, reply).await;
+            Err(Reason::Log(format!(
+                "Command sent outside the command channel of guild {gid}"
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[group]
+#[owners_only]
+#[commands(
+    duplicates,
+    mute,
+    stats,
+    memory,
+    purge_cache,
+    echo_test,
+    reload,
+    diagnose,
+    start_drain,
+    broadcast,
+    restore_snapshot,
+    save_cache,
+    check_store_command
+)]
+struct Owner;
+
+#[command]
+async fn set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    if is_blocked_user(ctx, msg).await {
+        return Ok(());
+    }
+
+    let (url, label) = match args.single::<String>().ok() {
+        Some(arg) if arg.starts_with("https://") || arg.starts_with("http://") => {
+            (Some(arg), args.single::<String>().ok())
+        }
+        label => (None, label),
+    };
+    let label = label.map(|l| sanitize_label(&l));
+    set_sound(ctx, msg, SoundKey::Guild(gid), url.map(Upload::Url), label).await;
+
+    Ok(())
+}
+
+/// Sets the guild's sound from base64 right in the message instead of a file, for
+/// scripts, like `set-base64 buzina T2dnUwACAAAA...`. The label is optional. A
+/// message only fits 2000 characters, about 1.4 KB of audio: a couple of seconds of
+/// low bitrate opus. Code blocks, line breaks and a `data:` URL prefix are all fine.
+#[command("set-base64")]
+#[only_in(guilds)]
+async fn set_base64(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    if is_blocked_user(ctx, msg).await {
+        return Ok(());
+    }
+
+    let rest = args.rest().trim().trim_matches('`').trim();
+    let mut words: Vec<&str> = rest.split_whitespace().collect();
+    // base64 never has spaces, but may come wrapped in lines longer than any label
+    let label = match words.first() {
+        Some(first) if words.len() > 1 && first.chars().count() <= MAX_LABEL_LEN => {
+            Some(sanitize_label(words.remove(0)))
+        }
+        _ => None,
+    };
+    let encoded = words.concat();
+    let encoded = match encoded.split_once(";base64,") {
+        Some((prefix, encoded)) if prefix.starts_with("data:") => encoded,
+        _ => &encoded,
+    };
+    if encoded.is_empty() {
+        refuse(
+            ctx,
+            msg,
+            "Tem que mandar o áudio em base64, tipo `set-base64 buzina T2dnUw...`",
+        )
+        .await;
+        return Ok(());
+    }
+    let content = match base64::decode(encoded) {
+        Ok(content) => content,
+        Err(e) => {
+            debug!("Invalid base64 from {}: {e}", msg.author.id);
+            refuse(ctx, msg, "Esse base64 tá quebrado, confere se veio inteiro").await;
+            return Ok(());
+        }
+    };
+    set_sound(
+        ctx,
+        msg,
+        SoundKey::Guild(gid),
+        Some(Upload::Inline(content)),
+        label,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Most sounds one guild or member can have.
+const MAX_SOUNDS: usize = 50;
+
+/// Largest zip `set-pack` takes, and the most it extracts out of one.
+const MAX_PACK_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Replaces the guild's sounds with the ones in an attached zip.
+#[command("set-pack")]
+#[only_in(guilds)]
+async fn set_pack(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    if is_blocked_user(ctx, msg).await {
+        return Ok(());
+    }
+
+    let attach = match msg.attachments.first() {
+        Some(attach) if attach.filename.to_lowercase().ends_with(".zip") => attach,
+        _ => {
+            refuse(ctx, msg, "Tem que anexar um `.zip` com os áudios").await;
+            return Ok(());
+        }
+    };
+    let key = SoundKey::Guild(gid);
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").clone()
+    };
+    let locale = locale(ctx, Some(gid)).await;
+
+    // held for the whole pack, every sound in it is decoded under it
+    let _permit = download_permit(ctx).await;
+    let zip_path = temp_path(key, &stored_name(&attach.filename, config.redact_filenames));
+    let downloaded = if is_draining(ctx).await {
+        Err(AudioError::Draining)
+    } else if attach.size > MAX_PACK_BYTES {
+        Err(AudioError::TooLarge {
+            size: attach.size,
+            limit: MAX_PACK_BYTES,
+        })
+    } else {
+        tokio::time::timeout(
+            config.audio_timeout,
+            download(&attach.url, &zip_path, MAX_PACK_BYTES),
+        )
+        .await
+        .map_err(|_| AudioError::Timeout)
+        .and_then(|downloaded| downloaded)
+    };
+    if let Err(e) = downloaded {
+        warn!("Error downloading sound pack for {key}: {e}");
+        refuse(ctx, msg, error_reply(&e, locale)).await;
+        return Ok(());
+    }
+
+    let extracted = {
+        let (zip_path, max_bytes) = (zip_path.clone(), config.max_sound_bytes);
+        let redact = config.redact_filenames;
+        tokio::task::spawn_blocking(move || extract_pack(&zip_path, key, max_bytes, redact, locale))
+            .await
+    };
+    if let Err(e) = tokio::fs::remove_file(&zip_path).await {
+        warn!("Error deleting {zip_path:?}: {e}");
+    }
+    let (files, mut skipped) = match extracted {
+        Ok(Ok(extracted)) => extracted,
+        Ok(Err(e)) => {
+            warn!("Error reading sound pack for {key}: {e}");
+            refuse(ctx, msg, "Não consegui abrir esse zip").await;
+            return Ok(());
+        }
+        Err(e) => {
+            error!("Error extracting sound pack for {key}: {e}");
+            refuse(ctx, msg, "Deu pau").await;
+            return Ok(());
+        }
+    };
+
+    let mut added = Vec::new();
+    for (name, path, hash) in files {
+        if added.len() < MAX_SOUNDS {
+            // the first sound replaces the old ones, the rest join it
+            let replace_all = added.is_empty();
+            let stored =
+                store_audio(ctx, key, None, replace_all, &name, &path, hash, &config).await;
+            match stored {
+                Ok(label) => {
+                    let set = Some(label.clone());
+                    record_activity(ctx, gid, ActivityKind::Set, set, msg.author.id).await;
+                    added.push(format!("`{label}`"));
+                }
+                Err(e) => {
+                    let logged = stored_name(&name, config.redact_filenames);
+                    warn!("Error setting {logged:?} from sound pack for {key}: {e}");
+                    skipped.push((name, error_reply(&e, locale)));
+                }
+            }
+        } else {
+            skipped.push((name, format!("passou do máximo de {MAX_SOUNDS} áudios")));
+        }
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            warn!("Error deleting {path:?}: {e}");
+        }
+    }
+
+    let mut reply = if added.is_empty() {
+        "Nenhum áudio desse zip deu certo, deixei os que já tinha".to_string()
+    } else {
+        format!("Blz, agora os áudios são {}!!", added.join(", "))
+    };
+    for (name, why) in skipped {
+        reply.push_str(&format!("\n`{name}`: {why}"));
+    }
+    if reply.chars().count() > DISCORD_MESSAGE_LEN {
+        reply = reply.chars().take(DISCORD_MESSAGE_LEN - 1).collect();
+        reply.push('…');
+    }
+    acknowledge(ctx, msg, !added.is_empty(), reply).await;
+
+    Ok(())
+}
+
+type ExtractedPack = (Vec<(String, PathBuf, ContentHash)>, Vec<(String, String)>);
+
+/// Extracts the files in the zip at `path` to temporary files, giving the name, path
+/// and content hash of each, and the names of the ones skipped along with why.
+/// Stops extracting at [`MAX_PACK_BYTES`] in total, or once there are [`MAX_SOUNDS`]
+/// files, however small. With `redact` the names are left out of the logs and
+/// temporary files, see [`stored_name`].
+fn extract_pack(
+    path: &Path,
+    key: SoundKey,
+    max_bytes: u64,
+    redact: bool,
+    locale: Locale,
+) -> zip::result::ZipResult<ExtractedPack> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let (mut files, mut skipped) = (Vec::new(), Vec::new());
+    let mut total = 0;
+    for i in 0..archive.len() {
+        if files.len() >= MAX_SOUNDS {
+            let rest = format!("e os outros {} arquivos", archive.len() - i);
+            skipped.push((rest, format!("passou do máximo de {MAX_SOUNDS} áudios")));
+            break;
+        }
+        let mut entry = archive.by_index(i)?;
+        let name = entry
+            .enclosed_name()
+            .and_then(|path| Some(path.file_name()?.to_str()?.to_string()));
+        // macOS leaves `._` files next to every real one
+        let name = match name {
+            Some(name) if entry.is_file() && !name.starts_with('.') => name,
+            _ => continue,
+        };
+
+        let too_large = |size| {
+            let e = AudioError::TooLarge {
+                size,
+                limit: max_bytes,
+            };
+            error_reply(&e, locale)
+        };
+        if entry.size() > max_bytes {
+            skipped.push((name, too_large(entry.size())));
+            continue;
+        }
+        if total + entry.size() > MAX_PACK_BYTES {
+            skipped.push((name, "o zip tem áudio demais no total".to_string()));
+            continue;
+        }
+
+        // the sizes in the zip are only what it claims
+        let mut content = Vec::new();
+        if let Err(e) = (&mut entry).take(max_bytes + 1).read_to_end(&mut content) {
+            let logged = stored_name(&name, redact);
+            warn!("Error extracting {logged:?} from sound pack for {key}: {e}");
+            skipped.push((name, "não consegui extrair".to_string()));
+            continue;
+        }
+        if content.len() as u64 > max_bytes {
+            skipped.push((name, too_large(content.len() as u64)));
+            continue;
+        }
+        total += content.len() as u64;
+
+        let tmp = temp_path(key, &stored_name(&name, redact));
+        std::fs::write(&tmp, &content)?;
+        files.push((name, tmp, content_hash(&content)));
+    }
+    Ok((files, skipped))
+}
+
+/// Sets the guild's intro, a short sting played before each sound played for a join
+/// or an unmute, or takes it out with `off`.
+#[command("set-intro")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn set_intro(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let key = SoundKey::Intro(gid);
+    let url = match args.single::<String>().ok() {
+        Some(arg) if arg == "off" => {
+            clear_key_sound(ctx, key).await;
+            confirm(ctx, msg, "Blz, os áudios tocam sem intro agora!!").await;
+            return Ok(());
+        }
+        url => url,
+    };
+    set_sound(ctx, msg, key, url.map(Upload::Url), None).await;
+
+    Ok(())
+}
+
+/// Takes out every sound of `key`, loaded and persisted, for the `off` of the
+/// commands setting a single sound.
+async fn clear_key_sound(ctx: &Context, key: SoundKey) {
+    clear_key_sound_in(&ctx.data, key).await;
+}
+
+async fn clear_key_sound_in(data: &RwLock<TypeMap>, key: SoundKey) {
+    let _lock = lock_sounds_in(data, key).await;
+    {
+        let sound_store = sound_store_in(data).await;
+        let mut sound_store = sound_store.write().await;
+        sound_store.remove(&key);
+    }
+    let config = {
+        let data = data.read().await;
+        Arc::clone(data.get::<Config>().expect("config is set"))
+    };
+    if let Err(e) = remove_persisted(&config, &sounds_dir(&config.data_dir, key)).await {
+        warn!("Error removing the sounds of {key}: {e}");
+    }
+}
+
+#[command("set-unmute")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn set_unmute(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let key = SoundKey::Unmute(gid);
+    let url = match args.single::<String>().ok() {
+        Some(arg) if arg == "off" => {
+            clear_key_sound(ctx, key).await;
+            confirm(
+                ctx,
+                msg,
+                "Blz, não toco mais nada quando tirarem o mute de alguém!!",
+            )
+            .await;
+            return Ok(());
+        }
+        url => url,
+    };
+    set_sound(ctx, msg, key, url.map(Upload::Url), None).await;
+
+    Ok(())
+}
+
+#[command("set-role")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn set_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let rid = match args.single::<RoleId>() {
+        Ok(rid) => rid,
+        Err(_) => {
+            refuse(ctx, msg, "Tem que me dizer de qual cargo é o áudio").await;
+            return Ok(());
+        }
+    };
+    let in_guild = ctx
+        .cache
+        .guild_field(gid, |guild| guild.roles.contains_key(&rid))
+        .await;
+    if in_guild == Some(false) {
+        refuse(ctx, msg, "Esse cargo não é daqui").await;
+        return Ok(());
+    }
+
+    let key = SoundKey::Role(rid);
+    let url = match args.single::<String>().ok() {
+        Some(arg) if arg == "off" => {
+            clear_key_sound(ctx, key).await;
+            confirm(ctx, msg, "Blz, esse cargo não tem mais áudio!!").await;
+            return Ok(());
+        }
+        url => url,
+    };
+    set_sound(ctx, msg, key, url.map(Upload::Url), None).await;
+
+    Ok(())
+}
+
+/// Sets the author's personal sound, or with `here` the one for this guild only.
+#[command("set-mine")]
+async fn set_mine(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    if is_blocked_user(ctx, msg).await {
+        return Ok(());
+    }
+
+    let key = match (args.single::<String>().ok().as_deref(), msg.guild_id) {
+        (Some("here"), Some(gid)) => SoundKey::Member(gid, msg.author.id),
+        (Some("here"), None) => {
+            refuse(ctx, msg, "Só dá pra usar `here` num servidor").await;
+            return Ok(());
+        }
+        _ => SoundKey::User(msg.author.id),
+    };
+    set_sound(ctx, msg, key, None, None).await;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn sharing(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match args
+        .single::<String>()
+        .ok()
+        .as_deref()
+        .and_then(parse_toggle)
+    {
+        Some(allow_sharing) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.allow_sharing = allow_sharing
+            })
+            .await;
+            if allow_sharing {
+                Ok("Blz, quem é admin aqui pode copiar os áudios pra outros servidores!!")
+            } else {
+                Ok("Blz, ninguém mais copia os áudios daqui!!")
+            }
+        }
+        None => Err("Tem que me dizer `on` ou `off`"),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Copies the sounds of another guild, or just the one with the given label, to this
+/// one. The decoded audio is shared rather than decoded again.
+#[command("copy-from")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn copy_from(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let source = match args.single::<u64>() {
+        Ok(source) if source != gid.0 => GuildId(source),
+        _ => {
+            refuse(ctx, msg, "Tem que me dizer o id do outro servidor").await;
+            return Ok(());
+        }
+    };
+    let label = args
+        .single::<String>()
+        .ok()
+        .map(|label| sanitize_label(&label));
+
+    if !guild_config(ctx, source).await.allow_sharing {
+        refuse(ctx, msg, "Esse servidor não compartilha os áudios dele").await;
+        return Ok(());
+    }
+    if !can_manage(ctx, source, msg.author.id).await {
+        refuse(ctx, msg, "Tu não é admin no outro servidor").await;
+        return Ok(());
+    }
+
+    let reply = match copy_sounds(ctx, source, gid, label.as_deref()).await {
+        Ok(0) => Err("Não achei nenhum áudio pra copiar".to_string()),
+        Ok(1) => Ok("Blz, copiei o áudio!!".to_string()),
+        Ok(copied) => Ok(format!("Blz, copiei {copied} áudios!!")),
+        Err(e) => Err(error_reply(&e, locale(ctx, Some(gid)).await)),
+    };
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Makes joins to one of the guild's voice channels play another guild's sounds, like
+/// `borrow #amigos 123456789012345678`. The other guild has to allow `sharing` and
+/// whoever links them has to manage both. Borrowed sounds come before anything this
+/// guild has, overrides included, see [`SoundKey`]. `borrow #amigos off` unlinks it.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn borrow(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let channel_id = match args.single::<ChannelId>() {
+        Ok(channel_id)
+            if ctx
+                .cache
+                .guild_field(gid, |guild| guild.channels.contains_key(&channel_id))
+                .await
+                == Some(true) =>
+        {
+            channel_id
+        }
+        Ok(channel_id) => {
+            refuse(
+                ctx,
+                msg,
+                format!("O canal {} não é daqui", channel_id.mention()),
+            )
+            .await;
+            return Ok(());
+        }
+        Err(_) => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o canal e o id do outro servidor, tipo `borrow #amigos 123456789012345678`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    let source = match args.single::<String>().ok().as_deref() {
+        Some("off") => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.borrowed_channels.remove(&channel_id);
+            })
+            .await;
+            confirm(
+                ctx,
+                msg,
+                format!(
+                    "Blz, o {} volta a tocar os áudios daqui!!",
+                    channel_id.mention()
+                ),
+            )
+            .await;
+            return Ok(());
+        }
+        Some(source) => match source.parse::<u64>() {
+            Ok(source) if source != gid.0 => GuildId(source),
+            _ => {
+                refuse(ctx, msg, "Tem que me dizer o id do outro servidor").await;
+                return Ok(());
+            }
+        },
+        None => {
+            refuse(ctx, msg, "Tem que me dizer o id do outro servidor").await;
+            return Ok(());
+        }
+    };
+
+    if !guild_config(ctx, source).await.allow_sharing {
+        refuse(ctx, msg, "Esse servidor não compartilha os áudios dele").await;
+        return Ok(());
+    }
+    if !can_manage(ctx, source, msg.author.id).await {
+        refuse(ctx, msg, "Tu não é admin no outro servidor").await;
+        return Ok(());
+    }
+
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.borrowed_channels.insert(channel_id, source);
+    })
+    .await;
+    confirm(
+        ctx,
+        msg,
+        format!(
+            "Blz, quem entrar no {} vai ouvir os áudios do outro servidor!!",
+            channel_id.mention()
+        ),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Whether `uid` has Manage Server in `gid`. Guilds the bot isn't in don't count.
+async fn can_manage(ctx: &Context, gid: GuildId, uid: UserId) -> bool {
+    let guild = match ctx.cache.guild(gid).await {
+        Some(guild) => guild,
+        None => return false,
+    };
+    match guild.member_permissions(ctx, uid).await {
+        Ok(permissions) => permissions.manage_guild(),
+        Err(e) => {
+            warn!("Error fetching the permissions of {uid} in guild {gid}: {e}");
+            false
+        }
+    }
+}
+
+/// Copies `from`'s sounds (only the one labeled `label`, if given) to `to`, along with
+/// their persisted files. Returns how many were copied, none at all if they'd take
+/// `to` past [`MAX_SOUNDS`].
+async fn copy_sounds(
+    ctx: &Context,
+    from: GuildId,
+    to: GuildId,
+    label: Option<&str>,
+) -> Result<usize, AudioError> {
+    let config = {
+        let data = ctx.data.read().await;
+        Arc::clone(data.get::<Config>().expect("config is set"))
+    };
+    let key = SoundKey::Guild(to);
+
+    let copies: Vec<Sound> = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        let sounds = match sound_store.get(&SoundKey::Guild(from)) {
+            Some(sounds) => sounds,
+            None => return Ok(0),
+        };
+        sounds
+            .iter()
+            .filter(|sound| label.is_none_or(|label| sound.label == label))
+            .map(|sound| Sound {
+                label: sound.label.clone(),
+                memory: sound.memory.clone(),
+                codec: sound.codec.clone(),
+                metadata: sound.metadata.clone(),
+                hash: sound.hash,
+                filter: sound.filter.clone(),
+                source: sound.source.clone(),
+                last_used: Instant::now(),
+                set_at: SystemTime::now(),
+            })
+            .collect()
+    };
+
+    let filter = key_filter(ctx, key).await;
+    let lock = lock_sounds(ctx, key).await;
+    {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        let sounds = sound_store.get(&key).map_or(&[][..], |sounds| sounds);
+        // copies of sounds `to` already has replace them, like sets of the same label
+        let added = copies
+            .iter()
+            .filter(|copy| sounds.iter().all(|sound| sound.label != copy.label))
+            .count();
+        if sounds.len() + added > MAX_SOUNDS {
+            return Err(AudioError::TooManySounds(MAX_SOUNDS));
+        }
+    }
+    let mut copied = Vec::new();
+    for mut sound in copies {
+        // the copy must survive the original being removed
+        if let Some(source) = sound.source.take() {
+            let dir = sounds_dir(&config.data_dir, key).join(&sound.label);
+            let path = dir.join(source.file_name().unwrap_or_else(|| "audio".as_ref()));
+            let result = async {
+                remove_persisted(&config, &dir).await?;
+                tokio::fs::create_dir_all(&dir).await?;
+                tokio::fs::copy(&source, &path).await
+            };
+            match result.await {
+                Ok(_) => {
+                    upload_persisted(&config, &path).await;
+                    sound.source = Some(path);
+                }
+                Err(e) => warn!("Error copying {source:?} to guild {to}: {e}"),
+            }
+        }
+        // decoded with the other guild's pan, decode it again with ours when played
+        if sound.filter != filter && sound.source.is_some() {
+            sound.memory = None;
+            sound.filter = filter.clone();
+        }
+        // without a file or audio in memory there would be nothing to play
+        if sound.source.is_some() || sound.memory.is_some() {
+            copied.push(sound);
+        }
+    }
+
+    let count = copied.len();
+    let labels: Vec<String> = copied.iter().map(|sound| sound.label.clone()).collect();
+    {
+        let sound_store = sound_store(ctx).await;
+        let mut sound_store = sound_store.write().await;
+        for sound in copied {
+            insert_sound(&mut sound_store, key, sound);
+        }
+    }
+    drop(lock);
+
+    // without a target nothing's measured, there's no need to wait for a permit
+    let measuring = guild_config(ctx, to).await.loudness_target.is_some();
+    for label in &labels {
+        let _permit = if measuring {
+            Some(download_permit(ctx).await)
+        } else {
+            None
+        };
+        measure_loudness(ctx, to, label).await;
+    }
+    Ok(count)
+}
+
+/// Sets the audio at `url`, or else the one attached to `msg` or to the message it
+/// replies to, as `key`'s sound and replies how it went. With a `label` the audio
+/// joins `key`'s other sounds, otherwise it replaces all of them.
+async fn set_sound(
+    ctx: &Context,
+    msg: &Message,
+    key: SoundKey,
+    upload: Option<Upload>,
+    label: Option<String>,
+) {
+    let adding = label.is_some();
+    let result = match save_from_message(ctx, msg, key, upload, label).await {
+        Some(result) => result,
+        None => return,
+    };
+
+    if let (Ok(label), SoundKey::Guild(gid)) = (&result, key) {
+        record_activity(
+            ctx,
+            gid,
+            ActivityKind::Set,
+            Some(label.clone()),
+            msg.author.id,
+        )
+        .await;
+    }
+
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").clone()
+    };
+    if config.react_on_set {
+        let reaction = if result.is_ok() { '✅' } else { '❌' };
+        match msg.react(ctx, reaction).await {
+            Ok(_) => return,
+            // most likely missing Add Reactions, so answer in text instead
+            Err(e) => warn!("Error reacting: {e}"),
+        }
+    }
+
+    let locale = locale(ctx, msg.guild_id).await;
+    let set_message = match key {
+        SoundKey::Guild(gid) => guild_config(ctx, gid).await.set_message,
+        SoundKey::User(_)
+        | SoundKey::Member(..)
+        | SoundKey::Role(_)
+        | SoundKey::Override(_)
+        | SoundKey::Unmute(_)
+        | SoundKey::Intro(_) => None,
+    };
+    let waveform = match &result {
+        Ok(label) if config.waveform_on_set => waveform(ctx, key, label, &config).await,
+        _ => None,
+    };
+    let clipped = match (&result, config.clip_warn_percent) {
+        (Ok(label), Some(threshold)) => clipped_percent(ctx, key, label)
+            .await
+            .filter(|&percent| percent >= threshold),
+        _ => None,
+    };
+    let ok = result.is_ok();
+    let mut reply = match result {
+        Ok(label) => match set_message {
+            Some(template) => template.replace("{label}", &label),
+            None if adding => tr(locale, Msg::SoundAdded, &[("label", &label)]),
+            None => tr(locale, Msg::SoundSet, &[]),
+        },
+        Err(e) => error_reply(&e, locale),
+    };
+    if let Some(percent) = clipped {
+        let percent = format!("{percent:.1}");
+        reply += "\n";
+        reply += &tr(locale, Msg::Clipping, &[("percent", &percent)]);
+    }
+    if !wants_reply(ctx, msg, ok).await {
+        acknowledge(ctx, msg, ok, reply).await;
+        return;
+    }
+
+    if let Some(png) = waveform {
+        let file = AttachmentType::Bytes {
+            data: png.into(),
+            filename: "waveform.png".to_string(),
+        };
+        let sent = msg
+            .channel_id
+            .send_files(ctx, [file], |m| m.content(&reply).reference_message(msg))
+            .await;
+        match sent {
+            Ok(_) => return,
+            Err(e) => warn!("Error sending the waveform, replying without it: {e}"),
+        }
+    }
+
+    respond(ctx, msg, reply).await;
+}
+
+/// Samples at least this loud, of 1 being full scale, count as clipped.
+const CLIP_LEVEL: f32 = 0.999;
+
+/// Percent of the samples of `key`'s sound labeled `label` that are clipped, read
+/// from the audio already decoded. `None` for sounds cached as opus, there are no
+/// samples to look at without decoding them again.
+async fn clipped_percent(ctx: &Context, key: SoundKey, label: &str) -> Option<f64> {
+    let memory = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        match &find_sound(&sound_store, key, Some(label))?.memory {
+            Some(CachedAudio::Raw(memory)) => memory.clone(),
+            _ => return None,
+        }
+    };
+
+    tokio::task::spawn_blocking(move || {
+        let mut pcm = Vec::new();
+        memory.raw.new_handle().read_to_end(&mut pcm).ok()?;
+        let (samples, clipped) = match memory.kind {
+            CodecType::FloatPcm => {
+                let samples = pcm
+                    .chunks_exact(4)
+                    .map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]]).abs());
+                (pcm.len() / 4, samples.filter(|&s| s >= CLIP_LEVEL).count())
+            }
+            CodecType::Pcm => {
+                let samples = pcm
+                    .chunks_exact(2)
+                    .map(|s| (i16::from_le_bytes([s[0], s[1]]) as f32 / i16::MAX as f32).abs());
+                (pcm.len() / 2, samples.filter(|&s| s >= CLIP_LEVEL).count())
+            }
+            _ => return None,
+        };
+        (samples > 0).then(|| clipped as f64 * 100.0 / samples as f64)
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Size of the picture attached by `waveform_on_set`.
+const WAVEFORM_SIZE: &str = "640x120";
+
+/// Draws the waveform of `key`'s sound labeled `label` as a png with ffmpeg. `None`
+/// when that fails, which isn't worth bothering anyone about.
+async fn waveform(ctx: &Context, key: SoundKey, label: &str, config: &Config) -> Option<Vec<u8>> {
+    let source = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        find_sound(&sound_store, key, Some(label))?.source.clone()?
+    };
+
+    // ffmpeg can't read compressed sounds, those are piped in decompressed
+    let content = if is_compressed(&source) {
+        match read_sound_file(&source).await {
+            Ok(content) => Some(content),
+            Err(e) => {
+                warn!("Error reading {source:?} for its waveform: {e}");
+                return None;
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut command = tokio::process::Command::new(&config.ffmpeg_path);
+    command.arg("-i");
+    match content {
+        Some(_) => command.arg("-").stdin(Stdio::piped()),
+        None => command.arg(&source).stdin(Stdio::null()),
+    };
+    let child = command
+        .args([
+            "-filter_complex",
+            &format!("showwavespic=s={WAVEFORM_SIZE}"),
+        ])
+        .args(["-frames:v", "1", "-c:v", "png", "-f", "image2pipe", "-"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Error running ffmpeg for a waveform: {e}");
+            return None;
+        }
+    };
+
+    let stdin = child.stdin.take();
+    let feed = async {
+        if let (Some(mut stdin), Some(content)) = (stdin, content) {
+            // ffmpeg quitting early closes the pipe, the exit status tells why
+            let _ = stdin.write_all(&content).await;
+        }
+    };
+    let drawing = async { tokio::join!(feed, child.wait_with_output()).1 };
+    match tokio::time::timeout(config.audio_timeout, drawing).await {
+        Ok(Ok(output)) if output.status.success() && !output.stdout.is_empty() => {
+            Some(output.stdout)
+        }
+        Ok(Ok(output)) => {
+            warn!(
+                "ffmpeg failed drawing the waveform of {source:?}: {}",
+                output.status
+            );
+            None
+        }
+        Ok(Err(e)) => {
+            warn!("Error drawing the waveform of {source:?}: {e}");
+            None
+        }
+        Err(_) => {
+            warn!("Timed out drawing the waveform of {source:?}");
+            None
+        }
+    }
+}
+
+/// Integrated loudness of the audio at `source` in LUFS, as ffmpeg's ebur128
+/// measures it. `None` when that fails, which isn't worth bothering anyone about.
+async fn loudness(source: &Path, config: &Config) -> Option<f32> {
+    // ffmpeg can't read compressed sounds, those are piped in decompressed
+    let content = if is_compressed(source) {
+        match read_sound_file(source).await {
+            Ok(content) => Some(content),
+            Err(e) => {
+                warn!("Error reading {source:?} for its loudness: {e}");
+                return None;
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut command = tokio::process::Command::new(&config.ffmpeg_path);
+    command.args(["-hide_banner", "-nostats", "-i"]);
+    match content {
+        Some(_) => command.arg("-").stdin(Stdio::piped()),
+        None => command.arg(source).stdin(Stdio::null()),
+    };
+    let child = command
+        .args(["-vn", "-af", "ebur128", "-f", "null", "-"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Error running ffmpeg to measure loudness: {e}");
+            return None;
+        }
+    };
+
+    let stdin = child.stdin.take();
+    let feed = async {
+        if let (Some(mut stdin), Some(content)) = (stdin, content) {
+            // ffmpeg quitting early closes the pipe, the exit status tells why
+            let _ = stdin.write_all(&content).await;
+        }
+    };
+    let measuring = async { tokio::join!(feed, child.wait_with_output()).1 };
+    let output = match tokio::time::timeout(config.audio_timeout, measuring).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            warn!("Error measuring the loudness of {source:?}: {e}");
+            return None;
+        }
+        Err(_) => {
+            warn!("Timed out measuring the loudness of {source:?}");
+            return None;
+        }
+    };
+    let loudness = integrated_loudness(&String::from_utf8_lossy(&output.stderr));
+    if loudness.is_none() {
+        warn!(
+            "ffmpeg measured no loudness for {source:?}: {}",
+            output.status
+        );
+    }
+    loudness
+}
+
+/// The integrated loudness in the summary ffmpeg's ebur128 filter ends its `stderr`
+/// with, where it has a line of its own, like `I: -18.4 LUFS`. The lines for each
+/// frame have one too, but after the filter's name.
+fn integrated_loudness(stderr: &str) -> Option<f32> {
+    stderr
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix("I:"))
+        .and_then(|loudness| loudness.trim().trim_end_matches("LUFS").trim().parse().ok())
+}
+
+/// Stores the [`SoundConfig::loudness`] of the guild sound labeled `label`, for `agc`.
+/// Runs ffmpeg, so the caller holds a [`Downloads`] permit. Without a
+/// `loudness_target` nothing is measured, and what the label measured before is
+/// forgotten since it may be another sound now, `agc` measures it when turned on.
+async fn measure_loudness(ctx: &Context, gid: GuildId, label: &str) {
+    let guild_config = guild_config(ctx, gid).await;
+    if guild_config.loudness_target.is_none() {
+        let measured = guild_config.sounds.get(label);
+        if measured.is_some_and(|config| config.loudness.is_some()) {
+            update_guild_config(ctx, gid, |guild_config| {
+                if let Some(config) = guild_config.sounds.get_mut(label) {
+                    config.loudness = None;
+                }
+            })
+            .await;
+        }
+        return;
+    }
+
+    let source = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        find_sound(&sound_store, SoundKey::Guild(gid), Some(label)).and_then(|s| s.source.clone())
+    };
+    let loudness = match source {
+        Some(source) => {
+            let config = {
+                let data = ctx.data.read().await;
+                Arc::clone(data.get::<Config>().expect("config is set"))
+            };
+            loudness(&source, &config).await
+        }
+        None => None,
+    };
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config
+            .sounds
+            .entry(label.to_string())
+            .or_default()
+            .loudness = loudness;
+    })
+    .await;
+}
+
+/// Audio given in the command itself rather than attached.
+enum Upload {
+    /// A link for yt-dlp, see [`save_url`].
+    Url(String),
+    /// The file's content, see `set-base64`.
+    Inline(Vec<u8>),
+}
+
+/// Saves the `upload`, or else the audio attached to `msg` or to the message it
+/// replies to, as `key`'s sound. `None` when there's no audio at all, which was
+/// already answered.
+async fn save_from_message(
+    ctx: &Context,
+    msg: &Message,
+    key: SoundKey,
+    upload: Option<Upload>,
+    label: Option<String>,
+) -> Option<Result<String, AudioError>> {
+    let result = match upload {
+        Some(Upload::Url(url)) => save_url(ctx, &url, key, label).await,
+        Some(Upload::Inline(content)) => save_inline(ctx, content, key, label).await,
+        None => {
+            let previous;
+            let attachments = match &msg.referenced_message {
+                Some(referenced) if msg.attachments.is_empty() => &referenced.attachments,
+                None if msg.attachments.is_empty() => {
+                    previous = previous_voice_message(ctx, msg).await;
+                    previous
+                        .as_ref()
+                        .map_or(&msg.attachments, |m| &m.attachments)
+                }
+                _ => &msg.attachments,
+            };
+
+            if attachments.is_empty() {
+                let locale = locale(ctx, msg.guild_id).await;
+                refuse(ctx, msg, tr(locale, Msg::NoAudio, &[])).await;
+                return None;
+            }
+
+            if attachments.len() > 1 {
+                let locale = locale(ctx, msg.guild_id).await;
+                respond(ctx, msg, tr(locale, Msg::OnlyFirstAttachment, &[])).await;
+            }
+
+            let attach = attachments.first().expect("already checked size");
+            save_audio(ctx, attach, key, label).await
+        }
+    };
+    if let Err(e) = &result {
+        warn!("Error setting sound for {key}: {e}");
+    }
+    Some(result)
+}
+
+/// Whether `attach` is a message recorded in the Discord client, which are ogg/opus
+/// files always named like this.
+fn is_voice_message(attach: &Attachment) -> bool {
+    attach.filename == "voice-message.ogg"
+        && attach
+            .content_type
+            .as_deref()
+            .is_some_and(|content_type| content_type.starts_with("audio/ogg"))
+}
+
+/// The message right before `msg` in its channel, if it's a voice message by the same
+/// author. Voice messages can't have text, so `set` sent right after one means it.
+async fn previous_voice_message(ctx: &Context, msg: &Message) -> Option<Message> {
+    let previous = match msg
+        .channel_id
+        .messages(ctx, |retriever| retriever.before(msg.id).limit(1))
+        .await
+    {
+        Ok(messages) => messages.into_iter().next()?,
+        Err(e) => {
+            warn!("Error fetching the message before {}: {e}", msg.id);
+            return None;
+        }
+    };
+
+    let is_voice = previous.author.id == msg.author.id
+        && previous.attachments.len() == 1
+        && previous.attachments.iter().all(is_voice_message);
+    is_voice.then_some(previous)
+}
+
+/// The language to reply in: the guild's pick, else its Discord locale if it's one
+/// we have, else `Config::locale`.
+async fn locale(ctx: &Context, gid: Option<GuildId>) -> Locale {
+    if let Some(gid) = gid {
+        if let Some(locale) = guild_config(ctx, gid).await.locale {
+            return locale;
+        }
+        let discord_locale = ctx
+            .cache
+            .guild_field(gid, |guild| guild.preferred_locale.clone())
+            .await;
+        if let Some(locale) = discord_locale.and_then(|l| l.parse().ok()) {
+            return locale;
+        }
+    }
+    let data = ctx.data.read().await;
+    data.get::<Config>().expect("config is set").locale
+}
+
+/// Replies that are translated, see [`Msg::template`].
+#[derive(Clone, Copy)]
+enum Msg {
+    SoundSet,
+    SoundAdded,
+    NoAudio,
+    OnlyFirstAttachment,
+    TooLarge,
+    Download,
+    Format,
+    TooShort,
+    TooManySounds,
+    DecodedTooLarge,
+    Silent,
+    Timeout,
+    TooLong,
+    LinksDisabled,
+    Fetch,
+    Failed,
+    Unreadable,
+    Uncacheable,
+    Draining,
+    TempFile,
+    Duplicate,
+    Clipping,
+}
+
+impl Msg {
+    /// The reply in `locale`, `{name}` standing for what [`tr`] fills in.
+    fn template(self, locale: Locale) -> &'static str {
+        match (self, locale) {
+            (Msg::SoundSet, Locale::PtBr) => "Blz, vou tocar esse áudio aí!!",
+            (Msg::SoundSet, Locale::En) => "Cool, I'll play that one!!",
+            (Msg::SoundAdded, Locale::PtBr) => "Blz, adicionei o áudio `{label}`!!",
+            (Msg::SoundAdded, Locale::En) => "Cool, added the sound `{label}`!!",
+            (Msg::NoAudio, Locale::PtBr) => "Cadê o áudio carai??",
+            (Msg::NoAudio, Locale::En) => "Where's the audio??",
+            (Msg::OnlyFirstAttachment, Locale::PtBr) => {
+                "Vou usar só o primeiro arquivo que tu mandou, o resto eu to ignorando!!"
+            }
+            (Msg::OnlyFirstAttachment, Locale::En) => {
+                "I'll only use the first file you sent, ignoring the rest!!"
+            }
+            (Msg::TooLarge, Locale::PtBr) => "Esse arquivo é grande demais, o máximo é {mb} MB",
+            (Msg::TooLarge, Locale::En) => "That file is too big, the limit is {mb} MB",
+            (Msg::Download, Locale::PtBr) => "Não consegui baixar o arquivo, tenta de novo",
+            (Msg::Download, Locale::En) => "Couldn't download the file, try again",
+            (Msg::Format, Locale::PtBr) => "Isso aí não é áudio não",
+            (Msg::Format, Locale::En) => "That's not audio",
+            (Msg::TooShort, Locale::PtBr) => "Esse áudio é curto demais, nem dá pra ouvir",
+            (Msg::TooShort, Locale::En) => "That sound is too short to even hear",
+            (Msg::TooManySounds, Locale::PtBr) => {
+                "Já tem áudio demais, o máximo é {limit}, tira algum antes"
+            }
+            (Msg::TooManySounds, Locale::En) => {
+                "There are too many sounds already, the limit is {limit}, remove one first"
+            }
+            (Msg::DecodedTooLarge, Locale::PtBr) => {
+                "O arquivo é pequeno mas o áudio é comprido demais, aberto passa de {mb} MB"
+            }
+            (Msg::DecodedTooLarge, Locale::En) => {
+                "The file is small but the audio is too long, decoded it takes over {mb} MB"
+            }
+            (Msg::Silent, Locale::PtBr) => "Esse áudio é só silêncio, não dá pra ouvir nada",
+            (Msg::Silent, Locale::En) => "That sound is all silence, there's nothing to hear",
+            (Msg::Timeout, Locale::PtBr) => "Demorou demais pra processar esse áudio",
+            (Msg::Timeout, Locale::En) => "That sound took too long to process",
+            (Msg::TooLong, Locale::PtBr) => "Esse áudio é longo demais, o máximo é {secs}s",
+            (Msg::TooLong, Locale::En) => "That sound is too long, the limit is {secs}s",
+            (Msg::LinksDisabled, Locale::PtBr) => "Aqui não dá pra usar link, manda o arquivo",
+            (Msg::LinksDisabled, Locale::En) => "Links don't work here, send the file",
+            (Msg::Fetch, Locale::PtBr) => "Não consegui baixar o áudio desse link",
+            (Msg::Fetch, Locale::En) => "Couldn't get the audio from that link",
+            (Msg::Failed, Locale::PtBr) => "Deu pau",
+            (Msg::Failed, Locale::En) => "Something broke",
+            (Msg::Unreadable, Locale::PtBr) => "Não consegui ler esse áudio",
+            (Msg::Unreadable, Locale::En) => "Couldn't read that sound",
+            (Msg::Uncacheable, Locale::PtBr) => {
+                "Li o áudio mas não consegui guardar ele, tenta outro formato"
+            }
+            (Msg::Uncacheable, Locale::En) => {
+                "Read the sound but couldn't keep it, try another format"
+            }
+            (Msg::Draining, Locale::PtBr) => "To reiniciando, tenta de novo daqui a pouco",
+            (Msg::Draining, Locale::En) => "I'm restarting, try again in a bit",
+            (Msg::TempFile, Locale::PtBr) => {
+                "Não consegui salvar o arquivo pra ler, avisa quem cuida do bot"
+            }
+            (Msg::TempFile, Locale::En) => {
+                "Couldn't save the file to read it, tell whoever runs the bot"
+            }
+            (Msg::Duplicate, Locale::PtBr) => "Já configurei esse mesmo agora há pouco",
+            (Msg::Duplicate, Locale::En) => "I just set that same one",
+            (Msg::Clipping, Locale::PtBr) => {
+                "Mas esse áudio tá estourado, {percent}% dele no talo, considera normalizar"
+            }
+            (Msg::Clipping, Locale::En) => {
+                "But that sound is clipping, {percent}% of it maxed out, consider normalizing it"
+            }
+        }
+    }
+}
+
+/// `msg` in `locale`, with its `{name}` placeholders replaced by `args`.
+fn tr(locale: Locale, msg: Msg, args: &[(&str, &dyn fmt::Display)]) -> String {
+    args.iter()
+        .fold(msg.template(locale).to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), &value.to_string())
+        })
+}
+
+fn error_reply(e: &AudioError, locale: Locale) -> String {
+    let mb = |bytes: u64| format!("{:.1}", bytes as f64 / (1024.0 * 1024.0));
+    match e {
+        AudioError::TooLarge { limit, .. } => tr(locale, Msg::TooLarge, &[("mb", &mb(*limit))]),
+        AudioError::Download(_) => tr(locale, Msg::Download, &[]),
+        AudioError::Format => tr(locale, Msg::Format, &[]),
+        AudioError::TooShort(_) => tr(locale, Msg::TooShort, &[]),
+        AudioError::TooManySounds(limit) => tr(locale, Msg::TooManySounds, &[("limit", limit)]),
+        AudioError::DecodedTooLarge { limit, .. } => {
+            tr(locale, Msg::DecodedTooLarge, &[("mb", &mb(*limit))])
+        }
+        AudioError::Silent { .. } => tr(locale, Msg::Silent, &[]),
+        AudioError::Timeout => tr(locale, Msg::Timeout, &[]),
+        AudioError::TooLong { limit, .. } => {
+            tr(locale, Msg::TooLong, &[("secs", &limit.as_secs())])
+        }
+        AudioError::LinksDisabled => tr(locale, Msg::LinksDisabled, &[]),
+        AudioError::Fetch(_) => tr(locale, Msg::Fetch, &[]),
+        AudioError::Draining => tr(locale, Msg::Draining, &[]),
+        AudioError::TempFile(_) => tr(locale, Msg::TempFile, &[]),
+        AudioError::Duplicate => tr(locale, Msg::Duplicate, &[]),
+        AudioError::Decode(InputError::Io(_)) => tr(locale, Msg::Failed, &[]),
+        AudioError::Decode(_) => tr(locale, Msg::Unreadable, &[]),
+        AudioError::Cache(_) => tr(locale, Msg::Uncacheable, &[]),
+    }
+}
+
+/// Returns the label the sound was saved with.
+async fn save_audio(
+    ctx: &Context,
+    attach: &Attachment,
+    key: SoundKey,
+    label: Option<String>,
+) -> Result<String, AudioError> {
+    if is_draining(ctx).await {
+        return Err(AudioError::Draining);
+    }
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").clone()
+    };
+
+    if attach.size > config.max_sound_bytes {
+        return Err(AudioError::TooLarge {
+            size: attach.size,
+            limit: config.max_sound_bytes,
+        });
+    }
+
+    // a `set` sent twice by accident would download, decode and reply twice
+    let recent = (key, label.clone(), attach.filename.clone(), attach.size);
+    if !claim_recent_set(ctx, recent.clone()).await {
+        return Err(AudioError::Duplicate);
+    }
+
+    let stored = async {
+        // a burst of sets waits its turn rather than downloading and decoding all at once
+        let _permit = download_permit(ctx).await;
+
+        let path = temp_path(key, &stored_name(&attach.filename, config.redact_filenames));
+        let downloaded = tokio::time::timeout(
+            config.audio_timeout,
+            download(&attach.url, &path, config.max_sound_bytes),
+        )
+        .await
+        .map_err(|_| AudioError::Timeout)
+        .and_then(|downloaded| downloaded);
+        let hash = match downloaded {
+            Ok(hash) => hash,
+            Err(e) => {
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    warn!("Error deleting partial download {path:?}: {e}");
+                }
+                return Err(e);
+            }
+        };
+
+        let replace_all = label.is_none();
+        store_audio(
+            ctx,
+            key,
+            label,
+            replace_all,
+            &attach.filename,
+            &path,
+            hash,
+            &config,
+        )
+        .await
+    }
+    .await;
+    // trying the same file again after it failed is not a duplicate
+    if stored.is_err() {
+        let mut data = ctx.data.write().await;
+        let recent_sets = data.get_mut::<RecentSets>().expect("recent sets are set");
+        recent_sets.remove(&recent);
+    }
+    stored
+}
+
+struct Downloads;
+
+impl TypeMapKey for Downloads {
+    /// One permit per `Config::max_downloads`, held while a sound is downloaded and
+    /// decoded or an admin API job runs.
+    type Value = Arc<Semaphore>;
+}
+
+/// Waits for one of the [`Downloads`] permits, for running ffmpeg or yt-dlp.
+async fn download_permit(ctx: &Context) -> OwnedSemaphorePermit {
+    let downloads = {
+        let data = ctx.data.read().await;
+        data.get::<Downloads>().expect("downloads are set").clone()
+    };
+    downloads
+        .acquire_owned()
+        .await
+        .expect("downloads are never closed")
+}
+
+/// How long the same file set again under the same key and label counts as sent
+/// twice by accident.
+const DUPLICATE_SET_WINDOW: Duration = Duration::from_secs(30);
+
+struct RecentSets;
+
+impl TypeMapKey for RecentSets {
+    /// When each key last got each file under what label. The file goes by the name
+    /// and size Discord gives for it, all there is before downloading it.
+    type Value = HashMap<(SoundKey, Option<String>, String, u64), Instant>;
+}
+
+/// Records that `recent` is being set, unless it already was within
+/// [`DUPLICATE_SET_WINDOW`].
+async fn claim_recent_set(ctx: &Context, recent: (SoundKey, Option<String>, String, u64)) -> bool {
+    let now = Instant::now();
+    let mut data = ctx.data.write().await;
+    let recent_sets = data.get_mut::<RecentSets>().expect("recent sets are set");
+    recent_sets.retain(|_, set_at| now.saturating_duration_since(*set_at) < DUPLICATE_SET_WINDOW);
+    if recent_sets.contains_key(&recent) {
+        return false;
+    }
+    recent_sets.insert(recent, now);
+    true
+}
+
+/// Where an incoming sound is kept while it's decoded. `name` comes from whoever
+/// uploaded it, so only its harmless characters are kept: nothing like `../` gets to
+/// pick where the file is written. A random part keeps two uploads of the same name
+/// from writing over each other.
+fn temp_path(key: SoundKey, name: &str) -> PathBuf {
+    let name: String = upload_name(name)
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() => c,
+            '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .take(MAX_TEMP_NAME_LEN)
+        .collect();
+    env::temp_dir().join(format!("{key}-{:08x}-{name}", rand::random::<u32>()))
+}
+
+/// The file name part of an upload's `name`, or `audio` when there's none: uploads
+/// may come with an empty name, or one that's just dots or a directory.
+fn upload_name(name: &str) -> &str {
+    let name = name.trim();
+    // `dir/` still has a file name to `Path`, `dir`
+    if name.ends_with('/') {
+        return "audio";
+    }
+    match Path::new(name).file_name().and_then(|name| name.to_str()) {
+        Some(name) if !name.trim_matches('.').is_empty() => name,
+        _ => "audio",
+    }
+}
+
+/// What an upload named `name` is kept as, in the temp dir and the data directory.
+/// With `redact` that's a short hash of the name, still the same for the same name so
+/// log lines can be matched up, plus its extension to go by when decoding.
+fn stored_name(name: &str, redact: bool) -> String {
+    let name = upload_name(name);
+    if !redact {
+        return name.to_string();
+    }
+    let hash = content_hash(name.as_bytes());
+    let hash = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
+    match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.len() <= MAX_EXT_LEN && ext.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            format!("{hash:08x}.{ext}")
+        }
+        _ => format!("{hash:08x}"),
+    }
+}
+
+/// Longest extension [`stored_name`] keeps, `.flac` and the like. Anything longer is
+/// more likely part of the name.
+const MAX_EXT_LEN: usize = 5;
+
+/// Longest part of an upload's name kept in its temp file's, well under any
+/// filesystem's limit.
+const MAX_TEMP_NAME_LEN: usize = 64;
+
+/// Failing to write to the temp dir is the host's problem rather than the sound's,
+/// so it's logged as an error for whoever runs the bot.
+fn temp_file_error(path: &Path, e: io::Error) -> AudioError {
+    error!("Cannot write temp file {path:?}, check permissions and free disk space: {e}");
+    AudioError::TempFile(e)
+}
+
+/// Streams the file at `url` to `path`, so it's never all in memory at once. Gives
+/// up as soon as it gets bigger than `limit`, the size Discord reports for an
+/// attachment isn't something to rely on. Returns the hash of the content.
+async fn download(url: &str, path: &Path, limit: u64) -> Result<ContentHash, AudioError> {
+    let io_error = |e| temp_file_error(path, e);
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| AudioError::Download(e.into()))?;
+
+    let mut file = File::create(path).await.map_err(io_error)?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| AudioError::Download(e.into()))?
+    {
+        size += chunk.len() as u64;
+        if size > limit {
+            return Err(AudioError::TooLarge { size, limit });
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(io_error)?;
+    }
+    file.flush().await.map_err(io_error)?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// Fetches the media at `url` with yt-dlp and saves it like `save_audio` does.
+async fn save_url(
+    ctx: &Context,
+    url: &str,
+    key: SoundKey,
+    label: Option<String>,
+) -> Result<String, AudioError> {
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").clone()
+    };
+    if is_draining(ctx).await {
+        return Err(AudioError::Draining);
+    }
+    if config.ytdl_path.is_none() {
+        return Err(AudioError::LinksDisabled);
+    }
+
+    let _permit = download_permit(ctx).await;
+    store_url(ctx, url, key, label).await
+}
+
+/// Downloads the link with yt-dlp and keeps it like `save_content` does, for callers
+/// already holding a [`Downloads`] permit.
+async fn store_url(
+    ctx: &Context,
+    url: &str,
+    key: SoundKey,
+    label: Option<String>,
+) -> Result<String, AudioError> {
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").clone()
+    };
+    let ytdl_path = config.ytdl_path.as_ref().ok_or(AudioError::LinksDisabled)?;
+    let limit = duration_limit(ctx, key, &config).await;
+    let (name, content) =
+        tokio::time::timeout(config.audio_timeout, ytdl(ytdl_path, url, limit, &config))
+            .await
+            .map_err(|_| AudioError::Timeout)??;
+
+    save_content(ctx, &name, content, key, label).await
+}
+
+/// Saves the file given inline, see [`Upload::Inline`], like `save_audio` does.
+async fn save_inline(
+    ctx: &Context,
+    content: Vec<u8>,
+    key: SoundKey,
+    label: Option<String>,
+) -> Result<String, AudioError> {
+    if is_draining(ctx).await {
+        return Err(AudioError::Draining);
+    }
+    let limit = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").max_sound_bytes
+    };
+    let size = content.len() as u64;
+    if size > limit {
+        return Err(AudioError::TooLarge { size, limit });
+    }
+    let _permit = download_permit(ctx).await;
+    save_content(ctx, "audio", content, key, label).await
+}
+
+/// Saves `content`, a whole file named `name`, like `save_audio` does. The caller
+/// holds a [`Downloads`] permit.
+async fn save_content(
+    ctx: &Context,
+    name: &str,
+    content: Vec<u8>,
+    key: SoundKey,
+    label: Option<String>,
+) -> Result<String, AudioError> {
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").clone()
+    };
+    let path = temp_path(key, &stored_name(name, config.redact_filenames));
+    tokio::fs::write(&path, &content)
+        .await
+        .map_err(|e| temp_file_error(&path, e))?;
+    let hash = content_hash(&content);
+    drop(content);
+
+    let replace_all = label.is_none();
+    store_audio(ctx, key, label, replace_all, name, &path, hash, &config).await
+}
+
+/// Downloads the audio of the media at `url`, returning a file name for it and its
+/// content. Long media is refused before downloading anything.
+async fn ytdl(
+    ytdl_path: &Path,
+    url: &str,
+    max_duration: Duration,
+    config: &Config,
+) -> Result<(String, Vec<u8>), AudioError> {
+    let ytdl = |args: &[&str]| {
+        tokio::process::Command::new(ytdl_path)
+            .args(["--no-playlist", "-f", "bestaudio"])
+            .args(args)
+            .arg("--")
+            .arg(url)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+    };
+
+    let out = ytdl(&["-J"])
+        .await
+        .map_err(|e| AudioError::Fetch(e.to_string()))?;
+    if !out.status.success() {
+        return Err(AudioError::Fetch(
+            String::from_utf8_lossy(&out.stderr).into_owned(),
+        ));
+    }
+    let info: serde_json::Value =
+        serde_json::from_slice(&out.stdout).map_err(|e| AudioError::Fetch(e.to_string()))?;
+
+    // live streams have no duration, and no end either
+    let duration = info["duration"]
+        .as_f64()
+        .map(Duration::from_secs_f64)
+        .unwrap_or(Duration::MAX);
+    if duration > max_duration {
+        return Err(AudioError::TooLong {
+            duration,
+            limit: max_duration,
+        });
+    }
+
+    let max_filesize = config.max_sound_bytes.to_string();
+    let out = ytdl(&["--max-filesize", &max_filesize, "-o", "-"])
+        .await
+        .map_err(|e| AudioError::Fetch(e.to_string()))?;
+    if !out.status.success() || out.stdout.is_empty() {
+        return Err(AudioError::Fetch(
+            String::from_utf8_lossy(&out.stderr).into_owned(),
+        ));
+    }
+    if out.stdout.len() as u64 > config.max_sound_bytes {
+        return Err(AudioError::TooLarge {
+            size: out.stdout.len() as u64,
+            limit: config.max_sound_bytes,
+        });
+    }
+
+    let title = info["title"].as_str().unwrap_or("audio");
+    let ext = info["ext"].as_str().unwrap_or("audio");
+    Ok((format!("{}.{ext}", sanitize_label(title)), out.stdout))
+}
+
+/// Decodes the file at `path` (unless a sound with the same `hash` is already loaded),
+/// persists it and adds it to `key`'s sounds, or replaces all of them with it. Without
+/// a `label` one is made up from its tags or else its file `name`. Returns the label
+/// the sound was saved with. Runs ffmpeg, so the caller holds a [`Downloads`] permit.
+#[allow(clippy::too_many_arguments)]
+async fn store_audio(
+    ctx: &Context,
+    key: SoundKey,
+    label: Option<String>,
+    replace_all: bool,
+    name: &str,
+    path: &Path,
+    hash: ContentHash,
+    config: &Config,
+) -> Result<String, AudioError> {
+    let filter = key_filter(ctx, key).await;
+    let limit = duration_limit(ctx, key, config).await;
+    let shared = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        sound_store
+            .values()
+            .flatten()
+            .find(|sound| sound.hash == hash && sound.filter == filter)
+            .and_then(|sound| {
+                let memory = sound.memory.clone()?;
+                let codec = sound.codec.clone();
+                let filter = sound.filter.clone();
+                Some(Decoded {
+                    memory,
+                    codec,
+                    filter,
+                })
+            })
+    };
+
+    let decoded = match shared {
+        // decoded for a guild that may allow longer sounds than this one
+        Some(decoded) => match decoded.memory.metadata().duration {
+            Some(duration) if duration > limit => {
+                return Err(AudioError::TooLong { duration, limit });
+            }
+            _ => decoded,
+        },
+        None => {
+            let decoded = tokio::time::timeout(
+                config.audio_timeout,
+                decode(path, Some(limit), filter, config),
+            )
+            .await
+            .map_err(|_| AudioError::Timeout)?;
+            if let Err(AudioError::Decode(_) | AudioError::Cache(_)) = &decoded {
+                record_decode_failure(ctx.data.clone()).await;
+            }
+            decoded?
+        }
+    };
+
+    let label = label
+        .or_else(|| label_from_tags(decoded.memory.metadata()))
+        .unwrap_or_else(|| label_from_filename(name));
+    // decoding is the slow part and doesn't need the lock
+    let label = keep_sound(
+        &ctx.data,
+        key,
+        label,
+        decoded,
+        hash,
+        replace_all,
+        name,
+        path,
+        config,
+    )
+    .await?;
+
+    // under the caller's download permit
+    if let SoundKey::Guild(gid) = key {
+        measure_loudness(ctx, gid, &label).await;
+    }
+    Ok(label)
+}
+
+/// What [`store_audio`] does under `key`'s lock once the sound is decoded: checks
+/// [`MAX_SOUNDS`], persists the file and adds the sound, or replaces all of them with
+/// it. Returns its label.
+#[allow(clippy::too_many_arguments)]
+async fn keep_sound(
+    data: &RwLock<TypeMap>,
+    key: SoundKey,
+    label: String,
+    decoded: Decoded,
+    hash: ContentHash,
+    replace_all: bool,
+    name: &str,
+    path: &Path,
+    config: &Config,
+) -> Result<String, AudioError> {
+    let lock = lock_sounds_in(data, key).await;
+    if !replace_all {
+        let sound_store = sound_store_in(data).await;
+        let sound_store = sound_store.read().await;
+        let sounds = sound_store.get(&key).map_or(&[][..], |sounds| sounds);
+        if sounds.len() >= MAX_SOUNDS && sounds.iter().all(|sound| sound.label != label) {
+            return Err(AudioError::TooManySounds(MAX_SOUNDS));
+        }
+    }
+
+    if replace_all {
+        if let Err(e) = remove_persisted(config, &sounds_dir(&config.data_dir, key)).await {
+            warn!("Error removing old sounds of {key}: {e}");
+        }
+    }
+    let source = match persist_sound(config, key, &label, name, path).await {
+        Ok(source) => Some(source),
+        Err(e) => {
+            warn!("Error persisting sound for {key}: {e}");
+            None
+        }
+    };
+
+    {
+        let sound_store = sound_store_in(data).await;
+        let mut sound_store = sound_store.write().await;
+        if replace_all {
+            sound_store.remove(&key);
+        }
+        insert_sound(
+            &mut sound_store,
+            key,
+            Sound::new(label.clone(), decoded, hash, source),
+        );
+    }
+    drop(lock);
+    Ok(label)
+}
+
+use songbird::input::error::Error as InputError;
+
+/// Clips shorter than this are rejected, they'd be over before anyone notices.
+const MIN_CLIP_DURATION: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+enum AudioError {
+    /// The attachment is bigger than `Config::max_sound_bytes`.
+    TooLarge { size: u64, limit: u64 },
+    /// Discord wouldn't give us the attachment.
+    Download(serenity::Error),
+    /// The file has no audio stream.
+    Format,
+    /// ffmpeg couldn't turn the file into audio.
+    Decode(InputError),
+    /// The decoded audio couldn't be kept in memory.
+    Cache(InputError),
+    /// The clip is shorter than [`MIN_CLIP_DURATION`].
+    TooShort(Duration),
+    /// Downloading or decoding took longer than `Config::audio_timeout`.
+    Timeout,
+    /// Adding it would take the key past [`MAX_SOUNDS`].
+    TooManySounds(usize),
+    /// Decoded, the clip would take more memory than `Config::max_decoded_bytes`.
+    DecodedTooLarge { size: u64, limit: u64 },
+    /// The clip never gets louder than `Config::silence_db`.
+    Silent { peak: f64 },
+    /// The clip is longer than `Config::max_duration`, or the guild's own limit.
+    TooLong { duration: Duration, limit: Duration },
+    /// A link was given but `Config::ytdl_path` isn't set.
+    LinksDisabled,
+    /// yt-dlp couldn't get the audio out of a link.
+    Fetch(String),
+    /// The bot is shutting down, see [`drain`].
+    Draining,
+    /// The upload couldn't be written to the temp dir to decode it from.
+    TempFile(io::Error),
+    /// The same file was just set, see [`DUPLICATE_SET_WINDOW`].
+    Duplicate,
+}
+
+impl From<InputError> for AudioError {
+    fn from(e: InputError) -> Self {
+        AudioError::Decode(e)
+    }
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioError::TooLarge { size, limit } => {
+                write!(f, "file has {size} bytes, limit is {limit}")
+            }
+            AudioError::Download(e) => write!(f, "downloading attachment failed: {e}"),
+            AudioError::Format => write!(f, "file has no audio stream"),
+            AudioError::Decode(e) => write!(f, "decoding audio failed: {e}"),
+            AudioError::Cache(e) => write!(f, "caching decoded audio failed: {e}"),
+            AudioError::TooShort(duration) => write!(f, "clip is too short: {duration:?}"),
+            AudioError::TooManySounds(limit) => write!(f, "already has {limit} sounds"),
+            AudioError::DecodedTooLarge { size, limit } => {
+                write!(f, "decoded clip takes {size} bytes, limit is {limit}")
+            }
+            AudioError::Silent { peak } => write!(f, "clip is silent, peaks at {peak} dB"),
+            AudioError::Timeout => write!(f, "timed out"),
+            AudioError::TooLong { duration, limit } => {
+                write!(f, "clip lasts {duration:?}, limit is {limit:?}")
+            }
+            AudioError::LinksDisabled => write!(f, "setting sounds from links is disabled"),
+            AudioError::Fetch(e) => write!(f, "fetching link failed: {e}"),
+            AudioError::Draining => write!(f, "shutting down"),
+            AudioError::TempFile(e) => {
+                write!(f, "cannot write temp file, check permissions/disk: {e}")
+            }
+            AudioError::Duplicate => write!(f, "same file was just set"),
+        }
+    }
+}
+
+/// A decoded sound, along with what's only known while decoding it.
+#[derive(Clone)]
+struct Decoded {
+    /// Also keeps the source's sample rate, channels and duration in its metadata.
+    memory: CachedAudio,
+    codec: Option<String>,
+    filter: Option<String>,
+}
+
+/// Decoded audio ready to play, in the `Config::cache_format` it was decoded with.
+#[derive(Clone)]
+enum CachedAudio {
+    Raw(Memory),
+    Opus(Compressed),
+}
+
+impl CachedAudio {
+    fn metadata(&self) -> &Metadata {
+        match self {
+            CachedAudio::Raw(memory) => &memory.metadata,
+            CachedAudio::Opus(compressed) => &compressed.metadata,
+        }
+    }
+
+    /// A new input playing the audio from the start.
+    fn input(&self) -> Result<Input, InputError> {
+        match self {
+            CachedAudio::Raw(memory) => memory.new_handle().try_into(),
+            CachedAudio::Opus(compressed) => Ok(compressed.new_handle().into()),
+        }
+    }
+}
+
+/// Clips longer than `max_duration`, taking more than `Config::max_decoded_bytes`
+/// once decoded, or silent are rejected. Sounds that were already accepted once skip
+/// the checks. `filter` is an ffmpeg audio filter to run the sound through, see
+/// [`audio_filter`].
+///
+/// ffprobe and ffmpeg only run for what [`decode_natively`] can't do, files it doesn't
+/// know and filters. There the checks come before decoding, see [`probe_for_ffmpeg`].
+async fn decode(
+    path: &Path,
+    max_duration: Option<Duration>,
+    filter: Option<String>,
+    config: &Config,
+) -> Result<Decoded, AudioError> {
+    // the duration can be missing or wrong, so decoding also stops at the limit
+    let max_bytes = max_duration.map(|_| config.max_decoded_bytes);
+    let started = Instant::now();
+
+    // filters are up to ffmpeg
+    let native = match filter {
+        Some(_) => None,
+        None => {
+            let owned_path = path.to_owned();
+            tokio::task::spawn_blocking(move || {
+                decode_natively(&owned_path, max_duration, max_bytes)
+            })
+            .await
+            .map_err(|e| AudioError::Decode(InputError::Io(e.into())))?
+        }
+    };
+    let (native, probe) = match native {
+        Some(native) => {
+            let native = native?;
+            if let (Some(_), Some(threshold)) = (max_duration, config.silence_db) {
+                let peak = peak_db(&native.pcm);
+                if peak < threshold {
+                    return Err(AudioError::Silent { peak });
+                }
+            }
+            let probe = Probe {
+                metadata: native.metadata.clone(),
+                codec: native.codec.clone(),
+            };
+            (Some(native), probe)
+        }
+        None => (None, probe_for_ffmpeg(path, max_duration, config).await?),
+    };
+
+    // spawning ffmpeg and filling the first chunk of the cache both block
+    let (owned_path, ffmpeg_path) = (path.to_owned(), config.ffmpeg_path.clone());
+    let (cache_format, bitrate) = (config.cache_format, opus_bitrate(config));
+    let af = filter.clone();
+    let metadata = probe.metadata;
+    let (memory, decoder) = tokio::task::spawn_blocking(move || {
+        let (track_input, decoder) = match native {
+            Some(native) => (native_input(native.pcm, metadata)?, native.decoder),
+            None => {
+                let input = ffmpeg(
+                    &owned_path,
+                    metadata,
+                    af.as_deref(),
+                    max_bytes,
+                    &ffmpeg_path,
+                )?;
+                (input, "ffmpeg")
+            }
+        };
+        let cached = match cache_format {
+            CacheFormat::Raw => Memory::new(track_input).map(CachedAudio::Raw),
+            CacheFormat::Opus => Compressed::new(track_input, bitrate).map(CachedAudio::Opus),
+        };
+        let cached = cached.map_err(|e| {
+            warn!("Error caching the decoded audio of {owned_path:?}: {e}");
+            AudioError::Cache(e)
+        })?;
+        Ok::<_, AudioError>((cached, decoder))
+    })
+    .await
+    .map_err(|e| AudioError::Decode(InputError::Io(e.into())))??;
+
+    let elapsed = started.elapsed();
+    let duration = memory.metadata().duration;
+    if elapsed >= config.slow_decode {
+        warn!(?path, ?duration, codec = ?probe.codec, decoder, ?elapsed, "Slow decode");
+    } else {
+        debug!(?path, ?duration, decoder, ?elapsed, "Decoded");
+    }
+    Ok(Decoded {
+        memory,
+        codec: probe.codec,
+        filter,
+    })
+}
+
+/// Probes a file ffmpeg is about to decode, rejecting it beforehand if ffprobe's
+/// duration or ffmpeg's volumedetect already tell it breaks the limits of [`decode`].
+async fn probe_for_ffmpeg(
+    path: &Path,
+    max_duration: Option<Duration>,
+    config: &Config,
+) -> Result<Probe, AudioError> {
+    let probe = match probe(path, config).await {
+        Ok(probe) => probe,
+        Err(InputError::Streams) => return Err(AudioError::Format),
+        Err(e) => {
+            warn!("Error probing {path:?}, decoding it anyway: {e}");
+            Probe {
+                metadata: Metadata::default(),
+                codec: None,
+            }
+        }
+    };
+
+    if let Some(duration) = probe.metadata.duration {
+        if duration < MIN_CLIP_DURATION {
+            return Err(AudioError::TooShort(duration));
+        }
+        match max_duration {
+            Some(limit) if duration > limit => {
+                return Err(AudioError::TooLong { duration, limit });
+            }
+            _ => {}
+        }
+        let size = (duration.as_secs_f64() * DECODED_BYTES_PER_SEC) as u64;
+        if max_duration.is_some() && size > config.max_decoded_bytes {
+            return Err(AudioError::DecodedTooLarge {
+                size,
+                limit: config.max_decoded_bytes,
+            });
+        }
+    }
+    if let (Some(_), Some(threshold)) = (max_duration, config.silence_db) {
+        match peak_volume(path, config).await {
+            Ok(Some(peak)) if peak < threshold => return Err(AudioError::Silent { peak }),
+            Ok(_) => {}
+            Err(e) => warn!("Error measuring the volume of {path:?}, decoding it anyway: {e}"),
+        }
+    }
+    Ok(probe)
+}
+
+/// Loudest sample of native `pcm` in dBFS, `-inf` for digital silence, like
+/// [`peak_volume`] but without ffmpeg.
+fn peak_db(pcm: &[u8]) -> f64 {
+    let peak = pcm
+        .chunks_exact(4)
+        .map(|sample| f32::from_le_bytes(sample.try_into().expect("4 bytes")).abs())
+        .fold(0.0, f32::max);
+    20.0 * f64::from(peak).log10()
+}
+
+/// Loudest sample of the audio at `path` in dBFS, `-inf` for digital silence, as
+/// ffmpeg's volumedetect reports it. `None` if it reported nothing.
+async fn peak_volume(path: &Path, config: &Config) -> io::Result<Option<f64>> {
+    let out = tokio::process::Command::new(&config.ffmpeg_path)
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(path)
+        .args(["-vn", "-af", "volumedetect", "-f", "null", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    Ok(stderr
+        .lines()
+        .find_map(|line| line.split("max_volume:").nth(1))
+        .and_then(|peak| peak.trim().trim_end_matches("dB").trim().parse().ok()))
+}
+
+/// Bitrate sounds cached as opus are encoded with, the global voice bitrate.
+fn opus_bitrate(config: &Config) -> Bitrate {
+    match config.bitrate_kbps {
+        Some(kbps) => Bitrate::BitsPerSecond(kbps as i32 * 1000),
+        None => Bitrate::Auto,
+    }
+}
+
+/// Same as `songbird::input::ffmpeg`, but running `ffmpeg_path` (see
+/// `Config::ffmpeg_path`) instead of whatever is first on `PATH`. Whatever the
+/// source, the output is always resampled to songbird's native 48kHz stereo, so mono
+/// or 44.1kHz files don't play at the wrong speed. `filter` is passed on as `-af`.
+///
+/// With `max_bytes` the output is read in full right away, to reject it as soon as
+/// it gets past them, songbird's cache would only fill up in the background and
+/// keep whatever it got. Otherwise it's streamed.
+fn ffmpeg(
+    path: &Path,
+    metadata: Metadata,
+    filter: Option<&str>,
+    max_bytes: Option<u64>,
+    ffmpeg_path: &Path,
+) -> Result<Input, AudioError> {
+    let mut command = Command::new(ffmpeg_path);
+    command.arg("-i").arg(path);
+    if let Some(filter) = filter {
+        command.arg("-af").arg(filter);
+    }
+    let mut child = command
+        .args([
+            "-f",
+            "f32le",
+            "-ac",
+            "2",
+            "-ar",
+            "48000",
+            "-acodec",
+            "pcm_f32le",
+            "-",
+        ])
+        .stderr(Stdio::null())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(InputError::from)?;
+
+    if let Some(limit) = max_bytes {
+        let mut pcm = Vec::new();
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let read = stdout
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut pcm)
+            .map_err(InputError::from);
+        // it's only waiting for the rest to be read, if not done already
+        let _ = child.kill();
+        let _ = child.wait();
+        read?;
+        if pcm.len() as u64 > limit {
+            return Err(AudioError::DecodedTooLarge {
+                size: pcm.len() as u64,
+                limit,
+            });
+        }
+        return native_input(pcm, metadata);
+    }
+
+    Ok(Input::new(
+        true,
+        children_to_reader::<f32>(vec![child]),
+        Codec::FloatPcm,
+        Container::Raw,
+        Some(metadata),
+    ))
+}
+
+/// Most samples per channel an opus packet decodes to, 120ms at 48kHz.
+const OPUS_MAX_FRAME: usize = 5760;
+
+/// Decodes the audio at `path` to songbird's native 48kHz stereo, as little endian
+/// f32 samples, without ffmpeg, if it's in a format symphonia knows (wav, flac, mp3,
+/// vorbis) or opus. Also returns which of them did it. `None` for anything else or
+/// a file that turns out broken, so ffmpeg gets a go at it, minimal containers may
+/// not have it at all.
+///
+/// Stops with an error as soon as the audio gets longer than `max_duration` or
+/// would decode to more than `max_bytes`, ffprobe doesn't run to check that
+/// beforehand.
+fn decode_natively(
+    path: &Path,
+    max_duration: Option<Duration>,
+    max_bytes: Option<u64>,
+) -> Option<Result<Native, AudioError>> {
+    let file = std::fs::File::open(path).ok()?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?
+        .format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)?;
+    let (track_id, params) = (track.id, track.codec_params.clone());
+    let metadata = Metadata {
+        channels: params.channels.map(|channels| channels.count() as u8),
+        sample_rate: params.sample_rate,
+        ..Metadata::default()
+    };
+
+    if params.codec == CODEC_TYPE_OPUS {
+        // opus always decodes at 48kHz, to as many channels as asked for
+        let mut decoder = OpusDecoder::new(SampleRate::Hz48000, OpusChannels::Stereo).ok()?;
+        let mut frame = vec![0.0; OPUS_MAX_FRAME * 2];
+        let mut pcm = Vec::new();
+        while let Some(packet) = next_packet(&mut *format, track_id).ok()? {
+            let len = decoder
+                .decode_float(Some(&packet.data[..]), &mut frame[..], false)
+                .ok()?;
+            pcm.extend(
+                frame[..len * 2]
+                    .iter()
+                    .flat_map(|sample| sample.to_le_bytes()),
+            );
+            if let Err(e) = within_limits(pcm.len() as u64 / 8, 48000, max_duration, max_bytes) {
+                return Some(Err(e));
+            }
+        }
+        // the encoder's lookahead, silence before the actual audio
+        let pre_skip = params.delay.unwrap_or(0) as usize * 8;
+        pcm.drain(..pre_skip.min(pcm.len()));
+        return Some(Ok(Native {
+            pcm,
+            decoder: "opus",
+            codec: Some("opus".to_string()),
+            metadata,
+        }));
+    }
+
+    let rate = params.sample_rate?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&params, &DecoderOptions::default())
+        .ok()?;
+    let mut channels = params.channels.map(|channels| channels.count());
+    let mut samples = Vec::new();
+    while let Some(packet) = next_packet(&mut *format, track_id).ok()? {
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // a damaged packet only loses its own bit of the audio
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(_) => return None,
+        };
+        let spec = *decoded.spec();
+        channels = Some(spec.channels.count());
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buffer.samples());
+        let frames = (samples.len() / spec.channels.count().max(1)) as u64;
+        if let Err(e) = within_limits(frames, rate, max_duration, max_bytes) {
+            return Some(Err(e));
+        }
+    }
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|codec| codec.short_name.to_string());
+    Some(Ok(Native {
+        pcm: to_native_pcm(&samples, channels?, rate),
+        decoder: "symphonia",
+        codec,
+        metadata,
+    }))
+}
+
+/// What [`decode_natively`] got out of a file.
+struct Native {
+    pcm: Vec<u8>,
+    /// Which of symphonia or opus did it.
+    decoder: &'static str,
+    /// Codec of the audio, named like ffprobe does.
+    codec: Option<String>,
+    /// The source's sample rate and channels, what ffprobe would have found.
+    metadata: Metadata,
+}
+
+/// Checks that `frames` at `rate` are no longer than `max_duration` and don't make
+/// more than `max_bytes` once turned into native 48kHz stereo f32.
+fn within_limits(
+    frames: u64,
+    rate: u32,
+    max_duration: Option<Duration>,
+    max_bytes: Option<u64>,
+) -> Result<(), AudioError> {
+    if rate == 0 {
+        return Ok(());
+    }
+    let duration = Duration::from_secs_f64(frames as f64 / rate as f64);
+    match max_duration {
+        Some(limit) if duration > limit => return Err(AudioError::TooLong { duration, limit }),
+        _ => {}
+    }
+    let size = frames.saturating_mul(48000) / rate as u64 * 8;
+    match max_bytes {
+        Some(limit) if size > limit => Err(AudioError::DecodedTooLarge { size, limit }),
+        _ => Ok(()),
+    }
+}
+
+/// The next packet of track `track_id`, `None` once the file ends.
+fn next_packet(
+    format: &mut dyn FormatReader,
+    track_id: u32,
+) -> Result<Option<MediaPacket>, SymphoniaError> {
+    loop {
+        match format.next_packet() {
+            Ok(packet) if packet.track_id() == track_id => return Ok(Some(packet)),
+            Ok(_) => {}
+            Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Turns interleaved `samples` with any number of `channels` at any `rate` into
+/// 48kHz stereo, like ffmpeg's `-ac 2 -ar 48000`, as little endian bytes. Mono plays
+/// on both sides and channels past the second are dropped. Resamples linearly, good
+/// enough for entrance sounds.
+fn to_native_pcm(samples: &[f32], channels: usize, rate: u32) -> Vec<u8> {
+    if channels == 0 || rate == 0 {
+        return Vec::new();
+    }
+    let frames: Vec<[f32; 2]> = samples
+        .chunks_exact(channels)
+        .map(|frame| [frame[0], frame[channels.min(2) - 1]])
+        .collect();
+    let last = match frames.len().checked_sub(1) {
+        Some(last) => last,
+        None => return Vec::new(),
+    };
+    let len = (frames.len() as u64 * 48000 / rate as u64) as usize;
+    let step = rate as f64 / 48000.0;
+    (0..len)
+        .flat_map(|i| {
+            let at = i as f64 * step;
+            let (j, t) = (at as usize, at.fract() as f32);
+            let (a, b) = (frames[j.min(last)], frames[(j + 1).min(last)]);
+            let (left, right) = (a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t);
+            left.to_le_bytes().into_iter().chain(right.to_le_bytes())
+        })
+        .collect()
+}
+
+/// The input for native `pcm` decoded by [`decode_natively`] or read from [`ffmpeg`],
+/// which already kept it within the limits. Without ffprobe there may have been no
+/// duration to check beforehand.
+fn native_input(pcm: Vec<u8>, metadata: Metadata) -> Result<Input, AudioError> {
+    let duration = Duration::from_secs_f64(pcm.len() as f64 / 8.0 / 48000.0);
+    if duration < MIN_CLIP_DURATION {
+        return Err(AudioError::TooShort(duration));
+    }
+    let metadata = Metadata {
+        duration: Some(duration),
+        channels: metadata.channels.or(Some(2)),
+        sample_rate: metadata.sample_rate.or(Some(48000)),
+        ..metadata
+    };
+    Ok(Input::new(
+        true,
+        Reader::from_memory(pcm),
+        Codec::FloatPcm,
+        Container::Raw,
+        Some(metadata),
+    ))
+}
+
+/// What ffprobe found out about a file.
+struct Probe {
+    metadata: Metadata,
+    /// Codec of the audio stream.
+    codec: Option<String>,
+}
+
+async fn probe(path: &Path, config: &Config) -> Result<Probe, InputError> {
+    let out = tokio::process::Command::new(config.ffprobe_path())
+        .args([
+            "-v",
+            "quiet",
+            "-of",
+            "json",
+            "-show_format",
+            "-show_streams",
+            "-i",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+
+    let value = serde_json::from_slice(&out.stdout).map_err(|error| InputError::Json {
+        error,
+        parsed_text: String::from_utf8_lossy(&out.stdout).into_owned(),
+    })?;
+    let metadata = Metadata::from_ffprobe_json(&value);
+    let codec = value["streams"].as_array().and_then(|streams| {
+        streams
+            .iter()
+            .find(|stream| stream["codec_type"] == "audio")
+            .and_then(|stream| stream["codec_name"].as_str())
+            .map(str::to_string)
+    });
+
+    match metadata.channels {
+        Some(_) => Ok(Probe { metadata, codec }),
+        None => Err(InputError::Streams),
+    }
+}
+
+#[command]
+#[aliases(again)]
+async fn replay(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let guild_config = guild_config(ctx, gid).await;
+    if let Some(refusal) = held_back(&guild_config) {
+        refuse(ctx, msg, refusal).await;
+        return Ok(());
+    }
+    let channel_id = if let Some(channel_id) = voice_channel_of(ctx, gid, msg.author.id).await {
+        channel_id
+    } else {
+        refuse(ctx, msg, "Entra num canal de voz primeiro!!").await;
+        return Ok(());
+    };
+
+    if let Some((label, sound)) = pick_labeled_sound(ctx, SoundKey::Guild(gid), None, None).await {
+        let volume = playback_volume(&guild_config, Some(&label));
+        play(ctx, gid, channel_id, &sound, volume).await;
+    } else {
+        refuse(ctx, msg, "Não tem nenhum áudio configurado!!").await;
+    }
+
+    Ok(())
+}
+
+/// Plays again whatever the guild played last, which with random picks, overrides
+/// and the rest isn't necessarily what `replay` would.
+#[command("replay-last")]
+#[only_in(guilds)]
+async fn replay_last(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    if let Some(refusal) = held_back(&guild_config(ctx, gid).await) {
+        refuse(ctx, msg, refusal).await;
+        return Ok(());
+    }
+    let channel_id = if let Some(channel_id) = voice_channel_of(ctx, gid, msg.author.id).await {
+        channel_id
+    } else {
+        refuse(ctx, msg, "Entra num canal de voz primeiro!!").await;
+        return Ok(());
+    };
+
+    let last = {
+        let data = ctx.data.read().await;
+        let last_sounds = data.get::<LastSounds>().expect("last sounds are set");
+        last_sounds.get(&gid).cloned()
+    };
+    if let Some((sound, volume)) = last {
+        play(ctx, gid, channel_id, &sound, volume).await;
+    } else {
+        refuse(ctx, msg, "Ainda não toquei nada desde que liguei!!").await;
+    }
+
+    Ok(())
+}
+
+/// Sounds per soundboard page, four rows of five buttons. The fifth row is left for
+/// the page buttons.
+const SOUNDBOARD_PAGE_LEN: usize = 20;
+
+/// Custom id prefix of the soundboard buttons that play a sound, the label follows.
+const SOUNDBOARD_PLAY_ID: &str = "soundboard:play:";
+
+/// Custom id prefix of the soundboard buttons that turn the page, the page follows.
+const SOUNDBOARD_PAGE_ID: &str = "soundboard:page:";
+
+/// Posts a button for each of the guild's sounds, see [`soundboard_click`].
+#[command]
+#[only_in(guilds)]
+async fn soundboard(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let labels = soundboard_labels(ctx, gid).await;
+    if labels.is_empty() {
+        refuse(ctx, msg, "Não tem nenhum áudio configurado!!").await;
+        return Ok(());
+    }
+
+    let sent = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            m.content(soundboard_title(&labels, 0))
+                .reference_message(msg)
+                .components(|c| soundboard_page(c, &labels, 0))
+        })
+        .await;
+    if let Err(e) = sent {
+        warn!("Error sending the soundboard of guild {gid}: {e}");
+    }
+
+    Ok(())
+}
+
+async fn soundboard_labels(ctx: &Context, gid: GuildId) -> Vec<String> {
+    let sound_store = sound_store(ctx).await;
+    let sound_store = sound_store.read().await;
+    let mut labels: Vec<String> = sound_store
+        .get(&SoundKey::Guild(gid))
+        .map_or_else(Vec::new, |sounds| {
+            sounds.iter().map(|sound| sound.label.clone()).collect()
+        });
+    labels.sort();
+    labels
+}
+
+fn soundboard_title(labels: &[String], page: usize) -> String {
+    let pages = labels.len().div_ceil(SOUNDBOARD_PAGE_LEN);
+    format!("Clica pra tocar no teu canal ({}/{pages})", page + 1)
+}
+
+/// The buttons of the sounds on `page`, and to turn the page if there's more than one.
+fn soundboard_page<'a>(
+    components: &'a mut CreateComponents,
+    labels: &[String],
+    page: usize,
+) -> &'a mut CreateComponents {
+    let pages = labels.len().div_ceil(SOUNDBOARD_PAGE_LEN);
+    let start = (page * SOUNDBOARD_PAGE_LEN).min(labels.len());
+    let end = (start + SOUNDBOARD_PAGE_LEN).min(labels.len());
+    for row in labels[start..end].chunks(5) {
+        components.create_action_row(|r| {
+            for label in row {
+                r.create_button(|b| {
+                    b.style(ButtonStyle::Secondary)
+                        .label(label)
+                        .custom_id(format!("{SOUNDBOARD_PLAY_ID}{label}"))
+                });
+            }
+            r
+        });
+    }
+    if pages > 1 {
+        components.create_action_row(|r| {
+            r.create_button(|b| {
+                b.style(ButtonStyle::Primary)
+                    .label("◀")
+                    .custom_id(format!("{SOUNDBOARD_PAGE_ID}{}", page.saturating_sub(1)))
+                    .disabled(page == 0)
+            })
+            .create_button(|b| {
+                b.style(ButtonStyle::Primary)
+                    .label("▶")
+                    .custom_id(format!("{SOUNDBOARD_PAGE_ID}{}", page + 1))
+                    .disabled(page + 1 >= pages)
+            })
+        });
+    }
+    components
+}
+
+/// Plays the sound of the soundboard button clicked in the clicker's channel, or turns
+/// the soundboard's page. The sounds may have changed since it was posted, so pages
+/// are rebuilt from the current ones.
+async fn soundboard_click(ctx: &Context, component: &MessageComponentInteraction) {
+    let gid = if let Some(gid) = component.guild_id {
+        gid
+    } else {
+        return;
+    };
+    let custom_id = component.data.custom_id.as_str();
+
+    let result = if let Some(page) = custom_id.strip_prefix(SOUNDBOARD_PAGE_ID) {
+        let labels = soundboard_labels(ctx, gid).await;
+        let pages = labels.len().div_ceil(SOUNDBOARD_PAGE_LEN);
+        let page = page.parse().unwrap_or(0).min(pages.saturating_sub(1));
+        component
+            .create_interaction_response(ctx, |r| {
+                r.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| {
+                        d.content(soundboard_title(&labels, page))
+                            .components(|c| soundboard_page(c, &labels, page))
+                    })
+            })
+            .await
+    } else if let Some(label) = custom_id.strip_prefix(SOUNDBOARD_PLAY_ID) {
+        let guild_config = guild_config(ctx, gid).await;
+        let channel_id = if is_muted(ctx).await {
+            Err("Tô no mudo agora")
+        } else if let Some(refusal) = held_back(&guild_config) {
+            Err(refusal)
+        } else {
+            voice_channel_of(ctx, gid, component.user.id)
+                .await
+                .ok_or("Entra num canal de voz primeiro!!")
+        };
+        match channel_id {
+            Ok(channel_id) => {
+                // has to be answered within seconds, joining can take longer
+                let acked = component
+                    .create_interaction_response(ctx, |r| {
+                        r.kind(InteractionResponseType::DeferredUpdateMessage)
+                    })
+                    .await;
+                // clicking away shouldn't play more than joins would
+                let cooldown = guild_cooldown(ctx, &guild_config).await;
+                let refusal = match fetch_sound(ctx, SoundKey::Guild(gid), label).await {
+                    Some(_) if cooling_down(ctx, gid, cooldown).await => {
+                        Some("Calma lá, acabou de tocar um áudio")
+                    }
+                    Some(sound) => {
+                        let volume = playback_volume(&guild_config, Some(label));
+                        play(ctx, gid, channel_id, &sound, volume).await;
+                        None
+                    }
+                    None => Some("Esse áudio não existe mais"),
+                };
+                if let Some(refusal) = refusal {
+                    let followup = component
+                        .create_followup_message(ctx, |f| {
+                            f.content(refusal)
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                        .await;
+                    if let Err(e) = followup {
+                        warn!("Error answering soundboard click in guild {gid}: {e}");
+                    }
+                }
+                acked
+            }
+            Err(refusal) => {
+                component
+                    .create_interaction_response(ctx, |r| {
+                        r.kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|d| {
+                                d.content(refusal).flags(
+                                    InteractionApplicationCommandCallbackDataFlags::EPHEMERAL,
+                                )
+                            })
+                    })
+                    .await
+            }
+        }
+    } else {
+        return;
+    };
+
+    if let Err(e) = result {
+        warn!("Error answering soundboard click in guild {gid}: {e}");
+    }
+}
+
+/// How long `setup` waits for each answer before giving up.
+const SETUP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Custom id prefix of `setup`'s buttons and menu, what was picked follows for buttons.
+const SETUP_ID: &str = "setup:";
+
+/// Channels `setup` offers to take commands from, a select menu has room for 25
+/// options and one is for any channel.
+const SETUP_MAX_CHANNELS: usize = 24;
+
+/// Walks an admin through the main settings with menus and buttons: the channel
+/// commands are taken from, the volume, the cooldown and whether joins play at all.
+/// Nothing changes until the last answer, and only whoever started it can answer.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn setup(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let mut channels: Vec<(String, String)> =
+        vec![("any".to_string(), "Qualquer canal".to_string())];
+    let text_channels = ctx
+        .cache
+        .guild_field(gid, |guild| {
+            let mut channels: Vec<_> = guild
+                .channels
+                .values()
+                .filter(|channel| channel.kind == ChannelType::Text)
+                .map(|channel| (channel.position, channel.id, channel.name.clone()))
+                .collect();
+            channels.sort();
+            channels
+        })
+        .await
+        .unwrap_or_default();
+    channels.extend(
+        text_channels
+            .into_iter()
+            .take(SETUP_MAX_CHANNELS)
+            .map(|(_, channel_id, name)| (channel_id.to_string(), format!("#{name}"))),
+    );
+    let volumes: Vec<(String, String)> = [25, 50, 75, 100, 125, 150, 200]
+        .iter()
+        .map(|percent| (percent.to_string(), format!("{percent}%")))
+        .collect();
+    let cooldowns: Vec<(String, String)> = [0, 10, 30, 60, 300, 600]
+        .iter()
+        .map(|secs| (secs.to_string(), format!("{secs} segundos")))
+        .collect();
+
+    let sent = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            m.content("1/4: De qual canal eu pego os comandos?")
+                .reference_message(msg)
+                .components(|c| setup_menu(c, &channels))
+        })
+        .await;
+    let mut wizard = match sent {
+        Ok(wizard) => wizard,
+        Err(e) => {
+            warn!("Error sending the setup of guild {gid}: {e}");
+            return Ok(());
+        }
+    };
+
+    let (answer, command_channel) = match setup_answer(ctx, &wizard, msg.author.id).await {
+        Some(answer) => answer,
+        None => return setup_expired(ctx, &mut wizard).await,
+    };
+    let command_channel = command_channel.parse::<ChannelId>().ok();
+    setup_step(ctx, &answer, "2/4: Qual o volume dos áudios?", |c| {
+        setup_menu(c, &volumes)
+    })
+    .await;
+
+    let (answer, volume) = match setup_answer(ctx, &wizard, msg.author.id).await {
+        Some(answer) => answer,
+        None => return setup_expired(ctx, &mut wizard).await,
+    };
+    let volume = volume.parse::<u32>().unwrap_or(100);
+    setup_step(
+        ctx,
+        &answer,
+        "3/4: Quanto tempo espero entre um áudio e outro?",
+        |c| setup_menu(c, &cooldowns),
+    )
+    .await;
+
+    let (answer, cooldown_secs) = match setup_answer(ctx, &wizard, msg.author.id).await {
+        Some(answer) => answer,
+        None => return setup_expired(ctx, &mut wizard).await,
+    };
+    let cooldown_secs = cooldown_secs.parse::<u64>().unwrap_or(0);
+    setup_step(
+        ctx,
+        &answer,
+        "4/4: Toco os áudios quando alguém entra?",
+        |c| {
+            c.create_action_row(|r| {
+                r.create_button(|b| {
+                    b.style(ButtonStyle::Success)
+                        .label("Sim")
+                        .custom_id(format!("{SETUP_ID}on"))
+                })
+                .create_button(|b| {
+                    b.style(ButtonStyle::Danger)
+                        .label("Não")
+                        .custom_id(format!("{SETUP_ID}off"))
+                })
+            })
+        },
+    )
+    .await;
+
+    let (answer, enabled) = match setup_answer(ctx, &wizard, msg.author.id).await {
+        Some(answer) => answer,
+        None => return setup_expired(ctx, &mut wizard).await,
+    };
+    let paused = enabled == "off";
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.command_channel = command_channel;
+        guild_config.volume = (volume != 100).then_some(volume);
+        guild_config.cooldown_secs = Some(cooldown_secs);
+        guild_config.paused = paused;
+    })
+    .await;
+
+    let summary = format!(
+        "Blz, tá configurado!!\nComandos: {}\nVolume: {volume}%\nCooldown: {cooldown_secs} segundos\nÁudios: {}",
+        command_channel.map_or_else(|| "qualquer canal".to_string(), |c| c.mention().to_string()),
+        if paused { "desligados" } else { "ligados" },
+    );
+    setup_step(ctx, &answer, &summary, |c| c).await;
+
+    Ok(())
+}
+
+/// A select menu with `options`, pairs of what's picked and what's shown.
+fn setup_menu<'a>(
+    components: &'a mut CreateComponents,
+    options: &[(String, String)],
+) -> &'a mut CreateComponents {
+    components.create_action_row(|r| {
+        r.create_select_menu(|menu| {
+            menu.custom_id(SETUP_ID).options(|o| {
+                for (value, label) in options {
+                    o.create_option(|option| option.value(value).label(label));
+                }
+                o
+            })
+        })
+    })
+}
+
+/// Waits for `admin` to answer the wizard's current step, telling anybody else
+/// answering that they can't. Returns the answer and what was picked, `None` once it
+/// times out.
+async fn setup_answer(
+    ctx: &Context,
+    wizard: &Message,
+    admin: UserId,
+) -> Option<(Arc<MessageComponentInteraction>, String)> {
+    loop {
+        let answer = wizard
+            .await_component_interaction(ctx)
+            .timeout(SETUP_TIMEOUT)
+            .await?;
+        if answer.user.id != admin {
+            let refused = answer
+                .create_interaction_response(ctx, |r| {
+                    r.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| {
+                            d.content("Só quem chamou o `setup` pode responder")
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                })
+                .await;
+            if let Err(e) = refused {
+                warn!("Error refusing a setup answer: {e}");
+            }
+            continue;
+        }
+
+        let picked = match answer.data.values.first() {
+            Some(value) => value.clone(),
+            None => answer
+                .data
+                .custom_id
+                .strip_prefix(SETUP_ID)
+                .unwrap_or_default()
+                .to_string(),
+        };
+        return Some((answer, picked));
+    }
+}
+
+/// Answers `answer` by turning the wizard into its next step.
+async fn setup_step(
+    ctx: &Context,
+    answer: &MessageComponentInteraction,
+    content: &str,
+    components: impl FnOnce(&mut CreateComponents) -> &mut CreateComponents,
+) {
+    let updated = answer
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| d.content(content).components(components))
+        })
+        .await;
+    if let Err(e) = updated {
+        warn!("Error updating the setup: {e}");
+    }
+}
+
+async fn setup_expired(ctx: &Context, wizard: &mut Message) -> CommandResult {
+    let edited = wizard
+        .edit(ctx, |m| {
+            m.content("Demorou demais, não mudei nada. Chama o `setup` de novo!!")
+                .components(|c| c)
+        })
+        .await;
+    if let Err(e) = edited {
+        warn!("Error ending the setup: {e}");
+    }
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn preview(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let channel_id = if let Some(channel_id) = voice_channel_of(ctx, gid, msg.author.id).await {
+        channel_id
+    } else {
+        refuse(ctx, msg, "Entra num canal de voz primeiro!!").await;
+        return Ok(());
+    };
+    // it plays on its own queue, not through `play_with_intro`
+    if is_muted(ctx).await {
+        refuse(ctx, msg, "Tô no mudo agora").await;
+        return Ok(());
+    }
+
+    let key = SoundKey::Guild(gid);
+    let labels = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        sound_store.get(&key).map_or_else(Vec::new, |sounds| {
+            sounds.iter().map(|sound| sound.label.clone()).collect()
+        })
+    };
+
+    let mut inputs = Vec::new();
+    for label in labels {
+        let memory = match fetch_sound(ctx, key, &label).await {
+            Some(memory) => memory,
+            None => continue,
+        };
+        match memory.input() {
+            Ok(input) => inputs.push((label, input)),
+            Err(e) => error!("Error building the input of sound {label} for guild {gid}: {e}"),
+        }
+    }
+
+    if inputs.is_empty() {
+        refuse(ctx, msg, "Não tem nenhum áudio configurado!!").await;
+        return Ok(());
+    }
+
+    let call = if let Some(call) = join(ctx, gid, channel_id).await {
+        call
+    } else {
+        return Ok(());
+    };
+
+    let mut disconnect = Some(disconnect(ctx, gid, &call).await);
+    let fade = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").crossfade
+    };
+    let mut call = call.lock().await;
+    call.queue().stop();
+    call.stop();
+    let queue = call.queue().clone();
+
+    let last = inputs.len() - 1;
+    for (i, (label, input)) in inputs.into_iter().enumerate() {
+        // crossfaded sounds are already told apart
+        if i > 0 && fade.is_zero() {
+            call.enqueue_source(preview_gap());
+        }
+
+        let (track, handle) = songbird::create_player(input);
+        let announce = Announce {
+            http: ctx.http.clone(),
+            channel_id: msg.channel_id,
+            label,
+        };
+        if let Err(e) = handle.add_event(Event::Track(TrackEvent::Play), announce) {
+            warn!("Error registering the preview announcement for guild {gid}: {e}");
+        }
+        if i == last {
+            let disconnect = disconnect.take().expect("only the last track leaves");
+            if let Err(e) = handle.add_event(Event::Track(TrackEvent::End), disconnect) {
+                error!("Error registering the disconnect for guild {gid}: {e}");
+            }
+        } else {
+            crossfade(&handle, &queue, fade);
+        }
+        call.enqueue(track);
+    }
+
+    Ok(())
+}
+
+/// Half a second of silence, to tell the previewed sounds apart.
+fn preview_gap() -> Input {
+    let bytes = 48_000 / 2 * 2 * std::mem::size_of::<f32>();
+    Input::new(
+        true,
+        Reader::from_memory(vec![0; bytes]),
+        Codec::FloatPcm,
+        Container::Raw,
+        None,
+    )
+}
+
+/// How often a crossfade changes the volume.
+const FADE_STEP: Duration = Duration::from_millis(20);
+
+/// Unless `fade` is zero, fades `handle` out over its last `fade` while the track
+/// queued after it starts early and fades in, instead of cutting from one to the
+/// other as the queue would. Tracks of unknown length still cut.
+fn crossfade(handle: &TrackHandle, queue: &TrackQueue, fade: Duration) {
+    if fade.is_zero() {
+        return;
+    }
+    let duration = match handle.metadata().duration {
+        Some(duration) => duration,
+        None => return,
+    };
+
+    let crossfade = Crossfade {
+        queue: queue.clone(),
+        fade,
+    };
+    let at = Event::Delayed(duration.saturating_sub(fade));
+    if let Err(e) = handle.add_event(at, crossfade) {
+        warn!("Error registering a crossfade: {e}");
+    }
+}
+
+struct Crossfade {
+    queue: TrackQueue,
+    fade: Duration,
+}
+
+#[async_trait]
+impl VoiceEventHandler for Crossfade {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let current = match ctx {
+            EventContext::Track(tracks) => tracks.first()?.1.clone(),
+            _ => return None,
+        };
+
+        // the queue plays the next track when this one ends, which is a no-op once
+        // it's already playing
+        let tracks = self.queue.current_queue();
+        let next = tracks
+            .iter()
+            .position(|track| track.uuid() == current.uuid())
+            .and_then(|i| tracks.get(i + 1));
+        // each fades from or to the volume it was set to play at
+        let volume = |handle: TrackHandle| async move {
+            handle.get_info().await.map_or(1.0, |info| info.volume)
+        };
+        if let Some(next) = next {
+            let to = volume(next.clone()).await;
+            if next.set_volume(0.0).is_ok() && next.play().is_ok() {
+                tokio::spawn(fade_volume(next.clone(), 0.0, to, self.fade));
+            }
+        }
+        let from = volume(current.clone()).await;
+        tokio::spawn(fade_volume(current, from, 0.0, self.fade));
+
+        None
+    }
+}
+
+/// Moves `handle`'s volume from `from` to `to` over `fade`.
+async fn fade_volume(handle: TrackHandle, from: f32, to: f32, fade: Duration) {
+    let steps = (fade.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+    for step in 1..=steps {
+        tokio::time::sleep(fade / steps).await;
+        let volume = from + (to - from) * step as f32 / steps as f32;
+        // the track is gone, nothing left to fade
+        if handle.set_volume(volume).is_err() {
+            return;
+        }
+    }
+}
+
+/// Says which sound a preview is playing.
+struct Announce {
+    http: Arc<Http>,
+    channel_id: ChannelId,
+    label: String,
+}
+
+#[async_trait]
+impl VoiceEventHandler for Announce {
+    async fn act(&self, _: &EventContext<'_>) -> Option<Event> {
+        let text = format!("Tocando `{}`", self.label);
+        if let Err(e) = self.channel_id.say(&self.http, text).await {
+            warn!("Error announcing preview: {e}");
+        }
+        None
+    }
+}
+
+/// Largest file a bot can upload to a guild without boosts.
+const DISCORD_UPLOAD_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// Sends back the file of one of the guild's sounds, `export buzina`, as it was
+/// uploaded or converted to some [`ExportFormat`] with `export buzina mp3`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn export(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let args: Vec<&str> = args.raw().collect();
+    // `export mp3` means the first sound, unless one is named like that
+    let (label, mut format) = match args.as_slice() {
+        [] => (None, None),
+        [label] => (Some(*label), None),
+        [label, format, ..] => match format.parse::<ExportFormat>() {
+            Ok(format) => (Some(*label), Some(format)),
+            Err(()) => {
+                refuse(ctx, msg, "Só sei converter pra `mp3`, `ogg` ou `wav`").await;
+                return Ok(());
+            }
+        },
+    };
+    let source = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        let mut sound = find_sound(&sound_store, SoundKey::Guild(gid), label);
+        let only_format = label
+            .filter(|_| format.is_none())
+            .and_then(|label| label.parse().ok());
+        if let (None, Some(only_format)) = (sound, only_format) {
+            format = Some(only_format);
+            sound = find_sound(&sound_store, SoundKey::Guild(gid), None);
+        }
+        sound.map(|sound| sound.source.clone())
+    };
+
+    let reply = match source {
+        None => "Não tem nenhum áudio configurado!!",
+        Some(None) => "Não guardei o arquivo desse áudio, manda de novo com o set",
+        Some(Some(path)) => {
+            let file = match format {
+                Some(format) => convert_sound(ctx, &path, format).await.map(|content| {
+                    let name = Path::new(&sound_file_name(&path)).with_extension(format.ext());
+                    (content, name.to_string_lossy().into_owned())
+                }),
+                None => read_sound_file(&path)
+                    .await
+                    .map(|content| (content, sound_file_name(&path))),
+            };
+            match file {
+                Ok((content, _)) if content.len() as u64 > DISCORD_UPLOAD_LIMIT => {
+                    "O arquivo é grande demais pro Discord"
+                }
+                Ok((content, filename)) => {
+                    let file = AttachmentType::Bytes {
+                        data: content.into(),
+                        filename,
+                    };
+                    if let Err(e) = msg.channel_id.send_files(ctx, [file], |m| m).await {
+                        warn!("Error sending sound file: {e}");
+                        "Deu pau"
+                    } else {
+                        return Ok(());
+                    }
+                }
+                Err(e) => {
+                    warn!("Error exporting sound file {path:?}: {e}");
+                    "Deu pau"
+                }
+            }
+        }
+    };
+
+    refuse(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// What `export` can convert sounds to.
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Mp3,
+    Ogg,
+    Wav,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mp3" => Ok(ExportFormat::Mp3),
+            "ogg" => Ok(ExportFormat::Ogg),
+            "wav" => Ok(ExportFormat::Wav),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ExportFormat {
+    fn ext(self) -> &'static str {
+        match self {
+            ExportFormat::Mp3 => "mp3",
+            ExportFormat::Ogg => "ogg",
+            ExportFormat::Wav => "wav",
+        }
+    }
+
+    /// ffmpeg's output options for it.
+    fn ffmpeg_args(self) -> &'static [&'static str] {
+        match self {
+            ExportFormat::Mp3 => &["-f", "mp3", "-c:a", "libmp3lame", "-q:a", "2"],
+            ExportFormat::Ogg => &["-f", "ogg", "-c:a", "libopus", "-b:a", "128k"],
+            ExportFormat::Wav => &["-f", "wav", "-c:a", "pcm_s16le"],
+        }
+    }
+}
+
+/// The persisted sound at `path` converted to `format`. ffmpeg stops writing past
+/// [`DISCORD_UPLOAD_LIMIT`], one that big got cut short and only fits the check.
+async fn convert_sound(ctx: &Context, path: &Path, format: ExportFormat) -> io::Result<Vec<u8>> {
+    let ffmpeg_path = {
+        let data = ctx.data.read().await;
+        data.get::<Config>()
+            .expect("config is set")
+            .ffmpeg_path
+            .clone()
+    };
+    let key = SoundKey::Guild(GuildId(0));
+    // compressed sounds have to be unpacked for ffmpeg, and some containers can't
+    // be read from a pipe
+    let input = temp_path(key, &sound_file_name(path));
+    let output = temp_path(key, &format!("export.{}", format.ext()));
+    tokio::fs::write(&input, read_sound_file(path).await?).await?;
+
+    let converted = tokio::process::Command::new(ffmpeg_path)
+        .args(["-hide_banner", "-nostats", "-y", "-i"])
+        .arg(&input)
+        .arg("-vn")
+        .args(format.ffmpeg_args())
+        .arg("-fs")
+        .arg(DISCORD_UPLOAD_LIMIT.to_string())
+        .arg(&output)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await;
+    let content = match converted {
+        Ok(status) if status.success() => tokio::fs::read(&output).await,
+        Ok(status) => Err(io::Error::other(format!("ffmpeg exited with {status}"))),
+        Err(e) => Err(e),
+    };
+
+    for tmp in [&input, &output] {
+        if let Err(e) = tokio::fs::remove_file(tmp).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Error deleting {tmp:?}: {e}");
+            }
+        }
+    }
+    content
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    if is_blocked_user(ctx, msg).await {
+        return Ok(());
+    }
+
+    let label = match args.single::<String>() {
+        Ok(label) => label,
+        Err(_) => {
+            refuse(ctx, msg, "Qual áudio? Tipo `remove buzina`").await;
+            return Ok(());
+        }
+    };
+
+    let reply = if remove_sound(ctx, gid, &label, msg.author.id).await {
+        Ok(format!("Blz, tirei o áudio `{label}`!!"))
+    } else {
+        Err(format!("Não tem nenhum áudio `{label}`"))
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Removes the guild's sound labeled `label`, its file and its settings, on behalf of
+/// `uid`. Whether there was one.
+async fn remove_sound(ctx: &Context, gid: GuildId, label: &str, uid: UserId) -> bool {
+    let key = SoundKey::Guild(gid);
+    let lock = lock_sounds(ctx, key).await;
+    let removed = {
+        let sound_store = sound_store(ctx).await;
+        let mut sound_store = sound_store.write().await;
+        let sounds = sound_store.entry(key).or_default();
+        let before = sounds.len();
+        sounds.retain(|s| s.label != label);
+        let removed = sounds.len() < before;
+        if sounds.is_empty() {
+            sound_store.remove(&key);
+        }
+        removed
+    };
+    if !removed {
+        return false;
+    }
+
+    let config = Arc::clone(
+        ctx.data
+            .read()
+            .await
+            .get::<Config>()
+            .expect("config is set"),
+    );
+    if let Err(e) = remove_persisted(&config, &sounds_dir(&config.data_dir, key).join(label)).await
+    {
+        warn!("Error removing sound {label} of {key}: {e}");
+    }
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.sounds.remove(label);
+    })
+    .await;
+    drop(lock);
+    record_activity(ctx, gid, ActivityKind::Remove, Some(label.to_string()), uid).await;
+    true
+}
+
+/// How long `remove-matching` waits for the removal to be confirmed.
+const REMOVE_CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Custom id prefix of the `remove-matching` buttons.
+const REMOVE_MATCHING_ID: &str = "remove-matching:";
+
+/// Most labels `remove-matching` lists while asking, the rest are only counted.
+const REMOVE_LISTED: usize = 20;
+
+/// Removes every guild sound whose label matches a pattern, like `remove-matching
+/// meme-*` or `*-velho` (without a `*` it's a prefix), once confirmed with a button.
+#[command("remove-matching")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn remove_matching(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let pattern = match args.single::<String>() {
+        Ok(pattern) if !pattern.trim_matches('*').is_empty() => pattern,
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o começo ou o padrão, tipo `remove-matching meme-*`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+    let mut labels: Vec<String> = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        sound_store
+            .get(&SoundKey::Guild(gid))
+            .map_or(&[][..], |sounds| sounds)
+            .iter()
+            .map(|sound| sound.label.clone())
+            .filter(|label| label_matches(label, &pattern))
+            .collect()
+    };
+    labels.sort_unstable();
+    if labels.is_empty() {
+        refuse(ctx, msg, format!("Nenhum áudio bate com `{pattern}`")).await;
+        return Ok(());
+    }
+
+    let mut listed: Vec<String> = labels
+        .iter()
+        .take(REMOVE_LISTED)
+        .map(|label| format!("`{label}`"))
+        .collect();
+    if labels.len() > REMOVE_LISTED {
+        listed.push(format!("e mais {}", labels.len() - REMOVE_LISTED));
+    }
+    let question = format!(
+        "Tiro esses {} áudios? Não tem volta\n{}",
+        labels.len(),
+        listed.join(", ")
+    );
+    let sent = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            m.content(question).reference_message(msg).components(|c| {
+                c.create_action_row(|r| {
+                    r.create_button(|b| {
+                        b.style(ButtonStyle::Danger)
+                            .label(format!("Tirar {}", labels.len()))
+                            .custom_id(format!("{REMOVE_MATCHING_ID}yes"))
+                    })
+                    .create_button(|b| {
+                        b.style(ButtonStyle::Secondary)
+                            .label("Cancelar")
+                            .custom_id(format!("{REMOVE_MATCHING_ID}no"))
+                    })
+                })
+            })
+        })
+        .await;
+    let question = match sent {
+        Ok(question) => question,
+        Err(e) => {
+            warn!("Error asking to remove the sounds matching {pattern} in guild {gid}: {e}");
+            return Ok(());
+        }
+    };
+
+    let answer = question
+        .await_component_interaction(ctx)
+        .author_id(msg.author.id)
+        .timeout(REMOVE_CONFIRM_TIMEOUT)
+        .await;
+    let confirmed = answer
+        .as_ref()
+        .is_some_and(|answer| answer.data.custom_id == format!("{REMOVE_MATCHING_ID}yes"));
+    let mut removed = 0;
+    if confirmed {
+        for label in &labels {
+            if remove_sound(ctx, gid, label, msg.author.id).await {
+                removed += 1;
+            }
+        }
+        info!(
+            "Removed {removed} sounds matching {pattern} in guild {gid} for {}",
+            msg.author.id
+        );
+    }
+
+    let content = match (&answer, confirmed) {
+        (None, _) => "Demorou demais, não tirei nada".to_string(),
+        (Some(_), false) => "Blz, não tirei nada".to_string(),
+        (Some(_), true) => format!("Blz, tirei {removed} áudios!!"),
+    };
+    let updated = match &answer {
+        Some(answer) => {
+            answer
+                .create_interaction_response(ctx, |r| {
+                    r.kind(InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|d| d.content(&content).components(|c| c))
+                })
+                .await
+        }
+        None => {
+            let mut question = question;
+            question
+                .edit(ctx, |m| m.content(&content).components(|c| c))
+                .await
+        }
+    };
+    if let Err(e) = updated {
+        warn!("Error answering the removal of the sounds matching {pattern}: {e}");
+    }
+
+    Ok(())
+}
+
+/// Whether `label` matches `pattern`, where each `*` stands for anything. Without
+/// any, `pattern` is a prefix.
+fn label_matches(label: &str, pattern: &str) -> bool {
+    if !pattern.contains('*') {
+        return label.starts_with(pattern);
+    }
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    let fits = label.len() >= first.len() + last.len();
+    if !fits || !label.starts_with(first) || !label.ends_with(last) {
+        return false;
+    }
+    let mut rest = &label[first.len()..label.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[command]
+#[only_in(guilds)]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let sound_configs = guild_config(ctx, gid).await.sounds;
+    let lines = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+
+        let mut sounds: Vec<&Sound> = sound_store
+            .get(&SoundKey::Guild(gid))
+            .map_or_else(Vec::new, |sounds| sounds.iter().collect());
+        sounds.sort_by(|a, b| a.label.cmp(&b.label).then(a.set_at.cmp(&b.set_at)));
+        sounds
+            .into_iter()
+            .map(|sound| {
+                let weight = sound_configs
+                    .get(&sound.label)
+                    .map_or(DEFAULT_WEIGHT, |config| config.weight);
+                format!("`{}` (peso {weight})", sound.label)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if lines.is_empty() {
+        respond(ctx, msg, "Não tem nenhum áudio configurado!!").await;
+        return Ok(());
+    }
+
+    let pages = paginate("Áudios:", &lines);
+    if pages.len() == 1 {
+        respond(ctx, msg, &pages[0]).await;
+        return Ok(());
+    }
+    show_pages(ctx, msg, &pages).await;
+
+    Ok(())
+}
+
+/// Most lines on a page of a listing.
+const PAGE_LINES: usize = 20;
+
+/// Splits `lines` into pages under `header`, each short enough for a message.
+fn paginate(header: &str, lines: &[String]) -> Vec<String> {
+    // leaves room for the page counter under Discord's 2000 characters
+    const MAX_PAGE_LEN: usize = 1900;
+
+    let mut pages = Vec::new();
+    let mut page = header.to_string();
+    let mut page_lines = 0;
+    for line in lines {
+        if page_lines == PAGE_LINES || page.len() + 1 + line.len() > MAX_PAGE_LEN {
+            pages.push(page);
+            page = header.to_string();
+            page_lines = 0;
+        }
+        page.push('\n');
+        page.push_str(line);
+        page_lines += 1;
+    }
+    pages.push(page);
+    pages
+}
+
+const PREVIOUS_PAGE: char = '◀';
+const NEXT_PAGE: char = '▶';
+
+/// How long a listing keeps answering to page turns.
+const PAGES_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Replies with the first of `pages`, then lets the author flip through them by
+/// reacting.
+async fn show_pages(ctx: &Context, msg: &Message, pages: &[String]) {
+    let page_text = |page: usize| format!("{}\n\nPágina {}/{}", pages[page], page + 1, pages.len());
+
+    let mut listing = match msg.reply(ctx, page_text(0)).await {
+        Ok(listing) => listing,
+        Err(e) => {
+            warn!("Error replying: {e}");
+            return;
+        }
+    };
+    for reaction in [PREVIOUS_PAGE, NEXT_PAGE] {
+        if let Err(e) = listing.react(ctx, reaction).await {
+            warn!("Error reacting: {e}");
+            return;
+        }
+    }
+
+    let mut reactions = listing
+        .await_reactions(ctx)
+        .author_id(msg.author.id)
+        .removed(true)
+        .timeout(PAGES_TIMEOUT)
+        .await;
+    let mut page: usize = 0;
+    while let Some(action) = reactions.next().await {
+        let emoji = &action.as_inner_ref().emoji;
+        let turned = if emoji.unicode_eq(&PREVIOUS_PAGE.to_string()) {
+            page.checked_sub(1)
+        } else if emoji.unicode_eq(&NEXT_PAGE.to_string()) {
+            Some(page + 1).filter(|&next| next < pages.len())
+        } else {
+            None
+        };
+
+        if let Some(turned) = turned {
+            page = turned;
+            if let Err(e) = listing.edit(ctx, |m| m.content(page_text(page))).await {
+                warn!("Error turning page: {e}");
+            }
+        }
+    }
+}
+
+/// Puts one of the guild's sounds in a category, like `category buzina zoeira`, see
+/// `theme`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn category(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match (args.single::<String>(), args.single::<String>()) {
+        (Ok(label), Ok(category)) => {
+            let exists = {
+                let sound_store = sound_store(ctx).await;
+                let sound_store = sound_store.read().await;
+                find_sound(&sound_store, SoundKey::Guild(gid), Some(&label)).is_some()
+            };
+            let category = (category != "off").then(|| sanitize_label(&category));
+            if exists {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config
+                        .sounds
+                        .entry(label.clone())
+                        .or_default()
+                        .category = category.clone()
+                })
+                .await;
+                match category {
+                    Some(category) => Ok(format!(
+                        "Blz, o áudio `{label}` agora é da categoria `{category}`!!"
+                    )),
+                    None => Ok(format!("Blz, o áudio `{label}` não tem mais categoria!!")),
+                }
+            } else {
+                Err(format!("Não tem nenhum áudio `{label}`"))
+            }
+        }
+        _ => Err(
+            "Tem que me dizer o áudio e a categoria, tipo `category buzina zoeira`, ou `off`"
+                .to_string(),
+        ),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Makes only the sounds of one category play, or all of them again with `off`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn theme(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let category = match args.single::<String>().ok() {
+        Some(category) if category == "off" => None,
+        Some(category) => Some(sanitize_label(&category)),
+        None => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer a categoria, tipo `theme zoeira`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+    let guild_config = guild_config(ctx, gid).await;
+    if let Some(category) = &category {
+        let used = guild_config
+            .sounds
+            .values()
+            .any(|config| config.category.as_ref() == Some(category));
+        if !used {
+            let reply = format!("Nenhum áudio é da categoria `{category}`, usa o `category` antes");
+            refuse(ctx, msg, reply).await;
+            return Ok(());
+        }
+    }
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.active_category = category.clone()
+    })
+    .await;
+
+    let reply = match category {
+        Some(category) => format!("Blz, agora só toco os áudios da categoria `{category}`!!"),
+        None => "Blz, voltei a tocar os áudios de todas as categorias!!".to_string(),
+    };
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Lists the guild's categories and how many sounds each has.
+#[command]
+#[only_in(guilds)]
+async fn categories(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let guild_config = guild_config(ctx, gid).await;
+    let labels: HashSet<String> = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        sound_store
+            .get(&SoundKey::Guild(gid))
+            .map_or_else(HashSet::new, |sounds| {
+                sounds.iter().map(|sound| sound.label.clone()).collect()
+            })
+    };
+    let mut counts: BTreeMap<&String, usize> = BTreeMap::new();
+    for (label, sound_config) in &guild_config.sounds {
+        if let (Some(category), true) = (&sound_config.category, labels.contains(label)) {
+            *counts.entry(category).or_default() += 1;
+        }
+    }
+
+    if counts.is_empty() {
+        respond(ctx, msg, "Nenhum áudio tem categoria ainda").await;
+        return Ok(());
+    }
+    let lines: Vec<_> = counts
+        .into_iter()
+        .map(|(category, count)| {
+            let active = if guild_config.active_category.as_ref() == Some(category) {
+                " (ativa)"
+            } else {
+                ""
+            };
+            format!("`{category}`: {count} áudios{active}")
+        })
+        .collect();
+    let pages = paginate("Categorias:", &lines);
+    if pages.len() == 1 {
+        respond(ctx, msg, &pages[0]).await;
+        return Ok(());
+    }
+    show_pages(ctx, msg, &pages).await;
+
+    Ok(())
+}
+
+/// Plays a sound for members joining after some time away, like `comeback volta 7d`,
+/// instead of their usual one. `off` stops.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn comeback(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let (label, span) = (args.single::<String>().ok(), args.single::<String>().ok());
+    let comeback = match (label.as_deref(), span.as_deref()) {
+        (Some("off"), None) => None,
+        (Some(label), Some(span)) => match parse_span(span) {
+            Some(after_secs) => Some(Comeback {
+                label: label.to_string(),
+                after_secs,
+            }),
+            None => {
+                refuse(
+                    ctx,
+                    msg,
+                    "Não entendi, tem que ser tipo `12h`, `7d` ou `30d`",
+                )
+                .await;
+                return Ok(());
+            }
+        },
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o áudio e depois de quanto tempo, tipo `comeback volta 7d`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    if let Some(comeback) = &comeback {
+        let exists = {
+            let sound_store = sound_store(ctx).await;
+            let sound_store = sound_store.read().await;
+            find_sound(&sound_store, SoundKey::Guild(gid), Some(&comeback.label)).is_some()
+        };
+        if !exists {
+            let reply = format!("Não tem nenhum áudio `{}`", comeback.label);
+            refuse(ctx, msg, reply).await;
+            return Ok(());
+        }
+    }
+    let reply = match (&comeback, span) {
+        (Some(comeback), Some(span)) => format!(
+            "Blz, quem voltar depois de {span} sem aparecer ganha o `{}`!!",
+            comeback.label
+        ),
+        _ => "Blz, quem volta agora ganha o áudio de sempre!!".to_string(),
+    };
+    update_guild_config(ctx, gid, |guild_config| {
+        // nobody's coming back from anywhere without it
+        if comeback.is_none() {
+            guild_config.last_seen.clear();
+        }
+        guild_config.comeback = comeback;
+    })
+    .await;
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Plays a sound for whoever makes a voice channel reach some number of members,
+/// the joiner included, like `milestone fanfarra 5` for the fifth. `off` stops.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn milestone(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let (label, members) = (args.single::<String>().ok(), args.single::<String>().ok());
+    let milestone = match (label.as_deref(), members.as_deref()) {
+        (Some("off"), None) => None,
+        (Some(label), Some(members)) => match members.parse::<usize>() {
+            Ok(members) if (1..=MAX_MILESTONE_MEMBERS).contains(&members) => Some(Milestone {
+                label: label.to_string(),
+                members,
+            }),
+            _ => {
+                let reply = format!("Tem que ser de 1 a {MAX_MILESTONE_MEMBERS} pessoas");
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+        },
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o áudio e em quantas pessoas, tipo `milestone fanfarra 5`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    if let Some(milestone) = &milestone {
+        let exists = {
+            let sound_store = sound_store(ctx).await;
+            let sound_store = sound_store.read().await;
+            find_sound(&sound_store, SoundKey::Guild(gid), Some(&milestone.label)).is_some()
+        };
+        if !exists {
+            let reply = format!("Não tem nenhum áudio `{}`", milestone.label);
+            refuse(ctx, msg, reply).await;
+            return Ok(());
+        }
+    }
+    let reply = match &milestone {
+        Some(milestone) => format!(
+            "Blz, quem fizer o canal chegar em {} pessoas ganha o `{}`!!",
+            milestone.members, milestone.label
+        ),
+        None => "Blz, sem áudio especial por lotar o canal!!".to_string(),
+    };
+    update_guild_config(ctx, gid, |guild_config| guild_config.milestone = milestone).await;
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Plays one of the guild's sounds for whoever boosts the server, like
+/// `boost-sound fanfarra`, or stops with `off`.
+#[command("boost-sound")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn boost_sound(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let label = match args.single::<String>().ok() {
+        Some(label) if label == "off" => None,
+        Some(label) => Some(label),
+        None => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o áudio, tipo `boost-sound fanfarra`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    if let Some(label) = &label {
+        let exists = {
+            let sound_store = sound_store(ctx).await;
+            let sound_store = sound_store.read().await;
+            find_sound(&sound_store, SoundKey::Guild(gid), Some(label)).is_some()
+        };
+        if !exists {
+            let reply = format!("Não tem nenhum áudio `{label}`");
+            refuse(ctx, msg, reply).await;
+            return Ok(());
+        }
+    }
+    let reply = match &label {
+        Some(label) => format!("Blz, quem der boost no server ganha o `{label}`!!"),
+        None => "Blz, sem áudio pra boost!!".to_string(),
+    };
+    update_guild_config(ctx, gid, |guild_config| guild_config.boost_sound = label).await;
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Plays the `GuildConfig::boost_sound` for whoever boosted the server with `msg`,
+/// Discord's system message for it, in their voice channel. Somebody that isn't in
+/// one gets a thank you in the channel the message is in instead.
+async fn celebrate_boost(ctx: &Context, gid: GuildId, msg: &Message) {
+    let guild_config = guild_config(ctx, gid).await;
+    let label = match &guild_config.boost_sound {
+        Some(label) if held_back(&guild_config).is_none() => label,
+        _ => return,
+    };
+    if is_muted(ctx).await {
+        return;
+    }
+
+    let booster = msg.author.id;
+    let channel_id = voice_channel_of(ctx, gid, booster)
+        .await
+        .filter(|channel_id| !guild_config.blocked_channels.contains(channel_id));
+    let sound = fetch_sound(ctx, SoundKey::Guild(gid), label).await;
+    match (channel_id, sound) {
+        (Some(channel_id), Some(sound)) => {
+            info!("Playing the boost sound for {booster} in guild {gid}");
+            let volume = playback_volume(&guild_config, Some(label));
+            play(ctx, gid, channel_id, &sound, volume).await;
+        }
+        (_, None) => warn!("Boost sound {label} of guild {gid} is gone, not playing it"),
+        (None, Some(_)) => {
+            let thanks = format!("Valeu pelo boost, {}!! 🎉", booster.mention());
+            if let Err(e) = msg.channel_id.say(ctx, thanks).await {
+                warn!("Error thanking {booster} for boosting guild {gid}: {e}");
+            }
+        }
+    }
+}
+
+/// Plays the same sound for every join, moving on to the next one every given span,
+/// or goes back to random picks with `off`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn rotate(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let span = args.single::<String>().ok();
+    let every_secs = match span.as_deref() {
+        Some("off") => None,
+        Some(span) => match parse_span(span) {
+            Some(every_secs) => Some(every_secs),
+            None => {
+                refuse(
+                    ctx,
+                    msg,
+                    "Não entendi, tem que ser tipo `30m`, `12h` ou `1d`",
+                )
+                .await;
+                return Ok(());
+            }
+        },
+        None => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer de quanto em quanto tempo, tipo `rotate 1d`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    if every_secs.is_some_and(|secs| secs < MIN_ROTATION_SECS) {
+        let reply = format!("Cada áudio tem que ficar pelo menos {MIN_ROTATION_SECS}s no rodízio");
+        refuse(ctx, msg, reply).await;
+        return Ok(());
+    }
+    update_guild_config(ctx, gid, |guild_config| {
+        // the current sound keeps its turn, just with the new span
+        let current = guild_config.rotation.take();
+        guild_config.rotation = every_secs.map(|every_secs| match current {
+            Some(rotation) => Rotation {
+                every_secs,
+                ..rotation
+            },
+            None => Rotation {
+                every_secs,
+                label: None,
+                since: 0,
+            },
+        });
+    })
+    .await;
+
+    let reply = match (every_secs, span) {
+        (Some(_), Some(span)) => format!("Blz, agora troco o áudio a cada {span}!!"),
+        _ => "Blz, voltei a sortear o áudio a cada entrada!!".to_string(),
+    };
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Skips the rest of the current sound's turn in the rotation.
+#[command("rotate-next")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn rotate_next(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = if guild_config(ctx, gid).await.rotation.is_none() {
+        Err("O rodízio ta desligado, liga com o `rotate` antes".to_string())
+    } else {
+        match advance_rotation(&ctx.data, gid).await {
+            Some(label) => Ok(format!("Blz, agora é a vez do `{label}`!!")),
+            None => Err("Não tem nenhum áudio pra entrar no rodízio".to_string()),
+        }
+    };
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// What a member is using Discord on.
+///
+/// Voice states don't say, so it's guessed from the member's presence, which is only
+/// there with `Config::presences` (the privileged presence intent, see [`intents`]).
+/// A presence is per member rather than per connection: somebody online on more than
+/// one platform at once could have joined from any of them, so they get no platform
+/// and the unassigned sounds, same as when there's no presence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Platform {
+    Desktop,
+    Mobile,
+    Web,
+}
+
+impl FromStr for Platform {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "desktop" | "pc" => Ok(Platform::Desktop),
+            "mobile" | "celular" => Ok(Platform::Mobile),
+            "web" | "browser" => Ok(Platform::Web),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Desktop => write!(f, "desktop"),
+            Platform::Mobile => write!(f, "mobile"),
+            Platform::Web => write!(f, "web"),
+        }
+    }
+}
+
+/// The platform `uid` is on, see [`Platform`] for when it's `None`.
+async fn platform_of(ctx: &Context, gid: GuildId, uid: UserId) -> Option<Platform> {
+    let status = ctx
+        .cache
+        .guild_field(gid, |guild| {
+            guild.presences.get(&uid)?.client_status.clone()
+        })
+        .await
+        .flatten()?;
+    let platforms: Vec<Platform> = [
+        (Platform::Desktop, status.desktop),
+        (Platform::Mobile, status.mobile),
+        (Platform::Web, status.web),
+    ]
+    .into_iter()
+    .filter(|(_, status)| status.is_some_and(|status| status != OnlineStatus::Offline))
+    .map(|(platform, _)| platform)
+    .collect();
+    match platforms.as_slice() {
+        [platform] => Some(*platform),
+        _ => None,
+    }
+}
+
+/// Makes a sound play for members joining from one platform, like `platform buzina
+/// mobile`, instead of the unassigned sounds. `off` plays it for everybody again.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn platform(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let (label, platform) = (args.single::<String>().ok(), args.single::<String>().ok());
+    let (label, platform) = match (label, platform.as_deref()) {
+        (Some(label), Some("off")) => (label, None),
+        (Some(label), Some(platform)) => match platform.parse::<Platform>() {
+            Ok(platform) => (label, Some(platform)),
+            Err(()) => {
+                refuse(
+                    ctx,
+                    msg,
+                    "A plataforma tem que ser `desktop`, `mobile` ou `web`",
+                )
+                .await;
+                return Ok(());
+            }
+        },
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o áudio e a plataforma, tipo `platform buzina mobile`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    let exists = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        find_sound(&sound_store, SoundKey::Guild(gid), Some(&label)).is_some()
+    };
+    let presences = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").presences
+    };
+    let reply = if !exists {
+        Err(format!("Não tem nenhum áudio `{label}`"))
+    } else {
+        update_guild_config(ctx, gid, |guild_config| {
+            guild_config
+                .sounds
+                .entry(label.clone())
+                .or_default()
+                .platform = platform;
+        })
+        .await;
+        match platform {
+            Some(platform) if presences => Ok(format!(
+                "Blz, o áudio `{label}` agora toca pra quem entrar pelo {platform}!!"
+            )),
+            Some(platform) => Ok(format!(
+                "Blz, o áudio `{label}` fica pro {platform}, mas só vai tocar quando quem cuida \
+                    do bot ligar o `PRESENCES`"
+            )),
+            None => Ok(format!(
+                "Blz, o áudio `{label}` toca em qualquer plataforma de novo!!"
+            )),
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Most members one sound can be assigned to.
+const MAX_ASSIGNED: usize = 25;
+
+/// Makes a sound play only for the members mentioned, like `assign buzina @fulano`,
+/// and for them instead of the unassigned sounds. `off` plays it for everybody again.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn assign(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let label = match args.single::<String>() {
+        Ok(label) => label,
+        Err(_) => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o áudio e pra quem, tipo `assign buzina @fulano`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+    let rest: Vec<&str> = args.raw().collect();
+    let only_for: HashSet<UserId> = if rest == ["off"] {
+        HashSet::new()
+    } else {
+        match rest.iter().map(|arg| arg.parse::<UserId>()).collect() {
+            Ok(only_for) => only_for,
+            Err(_) => {
+                refuse(ctx, msg, "Tem que me dizer os membros, pelo id ou marcando").await;
+                return Ok(());
+            }
+        }
+    };
+
+    let exists = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        find_sound(&sound_store, SoundKey::Guild(gid), Some(&label)).is_some()
+    };
+    let reply = if !exists {
+        Err(format!("Não tem nenhum áudio `{label}`"))
+    } else if rest.is_empty() {
+        Err("Tem que me dizer pra quem, ou `off`".to_string())
+    } else if only_for.len() > MAX_ASSIGNED {
+        Err(format!(
+            "Calma lá, o máximo é {MAX_ASSIGNED} membros por áudio"
+        ))
+    } else {
+        let members: Vec<String> = only_for
+            .iter()
+            .map(|uid| uid.mention().to_string())
+            .collect();
+        update_guild_config(ctx, gid, |guild_config| {
+            guild_config
+                .sounds
+                .entry(label.clone())
+                .or_default()
+                .only_for = only_for;
+        })
+        .await;
+        if members.is_empty() {
+            Ok(format!(
+                "Blz, o áudio `{label}` toca pra todo mundo de novo!!"
+            ))
+        } else {
+            Ok(format!(
+                "Blz, o áudio `{label}` agora só toca pra {}!!",
+                members.join(", ")
+            ))
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Heaviest weight a sound can have, plenty to make the others rare.
+const MAX_WEIGHT: u32 = 1000;
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn weight(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match (args.single::<String>(), args.single::<u32>()) {
+        (Ok(label), Ok(weight)) if weight <= MAX_WEIGHT => {
+            let exists = {
+                let sound_store = sound_store(ctx).await;
+                let sound_store = sound_store.read().await;
+                find_sound(&sound_store, SoundKey::Guild(gid), Some(&label)).is_some()
+            };
+            if exists {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.sounds.entry(label.clone()).or_default().weight = weight;
+                })
+                .await;
+                Ok(format!("Blz, o áudio `{label}` agora tem peso {weight}!!"))
+            } else {
+                Err(format!("Não tem nenhum áudio `{label}`"))
+            }
+        }
+        (Ok(_), Ok(_)) => Err(format!("Calma lá, o peso máximo é {MAX_WEIGHT}")),
+        _ => Err("Tem que me dizer o áudio e o peso, tipo `weight buzina 5`".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Makes a sound play only on some days and/or hours, like `schedule buzina sex 18-23
+/// America/Sao_Paulo`. While some scheduled sound is active only those play, other
+/// times only the unscheduled ones do.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn schedule(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let args: Vec<&str> = args.raw().collect();
+    let (label, args) = match args.split_first() {
+        Some((label, args)) if !args.is_empty() => (label.to_string(), args),
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o áudio e quando, tipo `schedule buzina sex 18-23` ou `schedule buzina off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    let exists = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        find_sound(&sound_store, SoundKey::Guild(gid), Some(&label)).is_some()
+    };
+    if !exists {
+        refuse(ctx, msg, format!("Não tem nenhum áudio `{label}`")).await;
+        return Ok(());
+    }
+
+    let schedule = if args == ["off"] {
+        None
+    } else {
+        let default_timezone = {
+            let data = ctx.data.read().await;
+            data.get::<Config>().expect("config is set").timezone
+        };
+        match parse_schedule(args, default_timezone) {
+            Ok(schedule) => Some(schedule),
+            Err(reply) => {
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+        }
+    };
+
+    let reply = match &schedule {
+        Some(schedule) => format!(
+            "Blz, o áudio `{label}` só toca nesse horário (fuso {})!!",
+            schedule.timezone
+        ),
+        None => format!("Blz, o áudio `{label}` toca a qualquer hora!!"),
+    };
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.sounds.entry(label).or_default().schedule = schedule;
+    })
+    .await;
+
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Keeps every join quiet for some hours each day, like `quiet-hours 23-7` or
+/// `quiet-hours 23-7 America/Sao_Paulo`, or plays at any hour again with `off`.
+#[command("quiet-hours")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn quiet_hours(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let args: Vec<&str> = args.raw().collect();
+    let quiet_hours = match args.as_slice() {
+        ["off"] => None,
+        [hours, ..] if hours.contains('-') => {
+            let default_timezone = {
+                let data = ctx.data.read().await;
+                data.get::<Config>().expect("config is set").timezone
+            };
+            // every day, on those hours
+            let mut schedule_args = vec!["*"];
+            schedule_args.extend(&args);
+            match parse_schedule(&schedule_args, default_timezone) {
+                Ok(schedule) => Some(schedule),
+                Err(reply) => {
+                    refuse(ctx, msg, reply).await;
+                    return Ok(());
+                }
+            }
+        }
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o horário, tipo `quiet-hours 23-7` ou `quiet-hours 23-7 America/Sao_Paulo`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    let reply = match &quiet_hours {
+        Some(Schedule {
+            hours: Some((start, end)),
+            timezone,
+            ..
+        }) => format!("Blz, das {start}h às {end}h não toco nada (fuso {timezone})!!"),
+        _ => "Blz, toco a qualquer hora!!".to_string(),
+    };
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.quiet_hours = quiet_hours
+    })
+    .await;
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Why nothing the members ask for plays right now, when the guild is paused or in
+/// its quiet hours.
+fn held_back(guild_config: &GuildConfig) -> Option<&'static str> {
+    if guild_config.paused {
+        Some("Os áudios tão pausados, manda `paused off` antes")
+    } else if in_quiet_hours(guild_config) {
+        Some("Agora é horário de silêncio, ver `quiet-hours`")
+    } else {
+        None
+    }
+}
+
+/// Whether it's within `guild_config`'s `quiet_hours` right now.
+fn in_quiet_hours(guild_config: &GuildConfig) -> bool {
+    guild_config
+        .quiet_hours
+        .as_ref()
+        .is_some_and(|quiet_hours| quiet_hours.is_active(Utc::now()))
+}
+
+#[command("source")]
+#[aliases(probe)]
+#[only_in(guilds)]
+async fn source_info(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let label = args.single::<String>().ok();
+    let reply = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        match find_sound(&sound_store, SoundKey::Guild(gid), label.as_deref()) {
+            Some(sound) => {
+                let unknown = || "?".to_string();
+                let meta = &sound.metadata;
+                format!(
+                    "Áudio: `{}`\nCodec: {}\nTaxa: {} Hz\nCanais: {}\nDuração: {}",
+                    sound.label,
+                    sound.codec.clone().unwrap_or_else(unknown),
+                    meta.sample_rate.map_or_else(unknown, |r| r.to_string()),
+                    meta.channels.map_or_else(unknown, |c| c.to_string()),
+                    meta.duration
+                        .map_or_else(unknown, |d| format!("{:.2}s", d.as_secs_f64())),
+                )
+            }
+            None => "Não tem nenhum áudio configurado!!".to_string(),
+        }
+    };
+
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("min-members")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn min_members(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match args.single::<usize>() {
+        Ok(min_members) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.min_members = min_members
+            })
+            .await;
+            Ok(format!(
+                "Blz, só vou tocar se tiver pelo menos {min_members} pessoas no canal!!"
+            ))
+        }
+        Err(_) => Err("Tem que me dizer um número, tipo `min-members 2`".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Longest cooldown a guild can set, anything above is probably a typo.
+const MAX_COOLDOWN_SECS: u64 = 24 * 60 * 60;
+
+/// Sets the seconds between the guild's sounds, like `cooldown 30`, or between plays
+/// of one of them instead, like `cooldown buzina 300`. `cooldown buzina off` goes
+/// back to the guild's for it.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn cooldown(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    if args.len() == 2 {
+        let label = args.single::<String>().unwrap_or_default();
+        sound_cooldown(ctx, msg, gid, &label, args.rest()).await;
+        return Ok(());
+    }
+
+    let reply = match args.single::<u64>() {
+        Ok(secs) if secs > MAX_COOLDOWN_SECS => {
+            Err(format!("Calma lá, o máximo é {MAX_COOLDOWN_SECS} segundos"))
+        }
+        Ok(secs) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.cooldown_secs = Some(secs)
+            })
+            .await;
+            Ok(format!(
+                "Blz, vou esperar {secs} segundos entre um áudio e outro!!"
+            ))
+        }
+        Err(_) => Err("Tem que me dizer os segundos, tipo `cooldown 30`".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// The `cooldown buzina 300` form of `cooldown`.
+async fn sound_cooldown(ctx: &Context, msg: &Message, gid: GuildId, label: &str, secs: &str) {
+    let secs = match secs {
+        "off" => None,
+        secs => match secs.parse::<u64>() {
+            Ok(secs) if secs <= MAX_COOLDOWN_SECS => Some(secs),
+            Ok(_) => {
+                let reply = format!("Calma lá, o máximo é {MAX_COOLDOWN_SECS} segundos");
+                refuse(ctx, msg, reply).await;
+                return;
+            }
+            Err(_) => {
+                refuse(
+                    ctx,
+                    msg,
+                    "Tem que me dizer os segundos, tipo `cooldown buzina 300`",
+                )
+                .await;
+                return;
+            }
+        },
+    };
+
+    let exists = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        find_sound(&sound_store, SoundKey::Guild(gid), Some(label)).is_some()
+    };
+    let reply = if exists {
+        update_guild_config(ctx, gid, |guild_config| {
+            guild_config
+                .sounds
+                .entry(label.to_string())
+                .or_default()
+                .cooldown_secs = secs;
+        })
+        .await;
+        match secs {
+            Some(secs) => Ok(format!(
+                "Blz, o áudio `{label}` só toca de novo depois de {secs} segundos!!"
+            )),
+            None => Ok(format!(
+                "Blz, o áudio `{label}` volta a seguir o cooldown do server!!"
+            )),
+        }
+    } else {
+        Err(format!("Não tem nenhum áudio `{label}`"))
+    };
+
+    answer(ctx, msg, reply).await;
+}
+
+/// Draws the guild's sound for a join by the author, or whoever they mention, and
+/// replies with its label without playing it, to check the weights and assignments.
+/// Every join draws again, so the next one may well play another.
+#[command("next-sound")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn next_sound(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let joiner = msg.mentions.first().map_or(msg.author.id, |user| user.id);
+    let reply = match pick_label(ctx, SoundKey::Guild(gid), Some(joiner), None).await {
+        Some(label) => Ok(format!(
+            "Se {} entrasse agora tocaria o `{label}`",
+            joiner.mention()
+        )),
+        None => Err("Não tem nenhum áudio pra tocar agora".to_string()),
+    };
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Most joins `simulate-joins` simulates.
+const MAX_SIMULATED_JOINS: u32 = 100;
+
+/// Runs `n` joins `gap` seconds apart, like `simulate-joins 10 2`, through the dice
+/// roll and the cooldown without joining anything, and tells how many would have
+/// played and how many of those would have found the bot still lingering, taking
+/// each sound to last as long as the guild's do on average. The guild's real
+/// cooldown is left as it is.
+#[command("simulate-joins")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn simulate_joins(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let (joins, gap) = match (args.single::<u32>(), args.single::<u64>().unwrap_or(1)) {
+        (Ok(joins), _) if joins > MAX_SIMULATED_JOINS => {
+            refuse(
+                ctx,
+                msg,
+                format!("Calma lá, o máximo é {MAX_SIMULATED_JOINS} entradas"),
+            )
+            .await;
+            return Ok(());
+        }
+        (Ok(_), gap) if gap > MAX_COOLDOWN_SECS => {
+            let reply = format!("Calma lá, o máximo é {MAX_COOLDOWN_SECS} segundos entre elas");
+            refuse(ctx, msg, reply).await;
+            return Ok(());
+        }
+        (Ok(joins), gap) if joins > 0 => (joins, Duration::from_secs(gap)),
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer quantas entradas e os segundos entre elas, tipo `simulate-joins 10 2`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    let guild_config = guild_config(ctx, gid).await;
+    let cooldown = guild_cooldown(ctx, &guild_config).await;
+    let linger = match guild_config.linger_secs {
+        Some(secs) => Duration::from_secs(secs),
+        None => {
+            let data = ctx.data.read().await;
+            data.get::<Config>().expect("config is set").linger
+        }
+    };
+    let sound_length = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        let durations: Vec<Duration> = sound_store
+            .get(&SoundKey::Guild(gid))
+            .into_iter()
+            .flatten()
+            .filter_map(|sound| sound.metadata.duration)
+            .collect();
+        match durations.len() {
+            0 => Duration::ZERO,
+            n => durations.iter().sum::<Duration>() / n as u32,
+        }
+    };
+
+    let clock = SteppedClock {
+        start: Instant::now(),
+        step: gap,
+        reads: AtomicU32::new(0),
+    };
+    let mut last_plays = HashMap::new();
+    let (mut played, mut lingering, mut lost_roll) = (0, 0, 0);
+    for _ in 0..joins {
+        let now = clock.now();
+        if let Some(chance) = guild_config.chance {
+            if rand::thread_rng().gen_range(0..100) >= chance {
+                lost_roll += 1;
+                continue;
+            }
+        }
+        // the last play, before the cooldown check moves it to now
+        let last = last_plays.get(&gid).copied();
+        if !check_cooldown(&mut last_plays, gid, cooldown, now) {
+            played += 1;
+            // the bot lingers once the last sound is over
+            let stays = sound_length + linger;
+            if last.is_some_and(|last: Instant| now.saturating_duration_since(last) < stays) {
+                lingering += 1;
+            }
+        }
+    }
+    info!(
+        joins,
+        ?gap,
+        ?cooldown,
+        ?linger,
+        played,
+        lingering,
+        lost_roll,
+        "Simulated joins"
+    );
+
+    let mut reply = format!(
+        "De {joins} entradas a cada {}s, {played} tocariam (cooldown de {}s)",
+        gap.as_secs(),
+        cooldown.as_secs()
+    );
+    if lost_roll > 0 {
+        reply += &format!(", {lost_roll} perderiam no `chance`");
+    }
+    if !linger.is_zero() && played > 1 {
+        reply += &format!(
+            ", e {lingering} dessas pegariam o bot ainda no canal (linger de {}s)",
+            linger.as_secs()
+        );
+    }
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Lists the guild's sounds by how many times they played.
+#[command]
+#[only_in(guilds)]
+async fn top(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let sound_configs = guild_config(ctx, gid).await.sounds;
+    let mut plays: Vec<(String, u64)> = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        let labels: BTreeSet<&String> = sound_store
+            .get(&SoundKey::Guild(gid))
+            .map_or_else(BTreeSet::new, |sounds| {
+                sounds.iter().map(|sound| &sound.label).collect()
+            });
+        labels
+            .into_iter()
+            .map(|label| {
+                let plays = sound_configs.get(label).map_or(0, |config| config.plays);
+                (label.clone(), plays)
+            })
+            .collect()
+    };
+    if plays.is_empty() {
+        respond(ctx, msg, "Não tem nenhum áudio configurado!!").await;
+        return Ok(());
+    }
+    plays.sort_by_key(|(_, plays)| std::cmp::Reverse(*plays));
+
+    let lines: Vec<_> = plays
+        .iter()
+        .enumerate()
+        .map(|(i, (label, plays))| format!("{}. `{label}`: {plays}x", i + 1))
+        .collect();
+    let pages = paginate("Áudios que mais tocaram:", &lines);
+    if pages.len() == 1 {
+        respond(ctx, msg, &pages[0]).await;
+        return Ok(());
+    }
+    show_pages(ctx, msg, &pages).await;
+
+    Ok(())
+}
+
+/// Zeroes the counts `top` shows.
+#[command("reset-top")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn reset_top(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    update_guild_config(ctx, gid, |guild_config| {
+        for sound_config in guild_config.sounds.values_mut() {
+            sound_config.plays = 0;
+        }
+    })
+    .await;
+    warn!("Play counts of guild {gid} reset by {}", msg.author.id);
+
+    confirm(ctx, msg, "Blz, zerei a contagem!!").await;
+
+    Ok(())
+}
+
+/// Sets the percent of the joins that play a sound at all, for a surprise now and
+/// then instead of every time.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn chance(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let reply = match arg.as_deref().map(|arg| arg.trim_end_matches('%')) {
+        Some("off") | Some("100") => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.chance = None).await;
+            Ok("Blz, toco em toda entrada!!".to_string())
+        }
+        Some(percent) => match percent.parse::<u8>() {
+            Ok(percent) if percent <= 100 => {
+                update_guild_config(ctx, gid, |guild_config| guild_config.chance = Some(percent))
+                    .await;
+                Ok(format!("Blz, toco em {percent}% das entradas!!"))
+            }
+            _ => Err("Tem que me dizer de 0 a 100%, tipo `chance 30`, ou `off`".to_string()),
+        },
+        None => Err("Tem que me dizer de 0 a 100%, tipo `chance 30`, ou `off`".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Longest a guild may have the bot linger after a sound.
+const MAX_LINGER_SECS: u64 = 60 * 60;
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn linger(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let reply = match arg.as_deref() {
+        Some("off") => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.linger_secs = None).await;
+            Ok("Blz, voltei pro tempo padrão!!".to_string())
+        }
+        Some(secs) => match secs.parse::<u64>() {
+            Ok(secs) if secs > MAX_LINGER_SECS => {
+                Err(format!("Calma lá, o máximo é {MAX_LINGER_SECS} segundos"))
+            }
+            Ok(0) => {
+                update_guild_config(ctx, gid, |guild_config| guild_config.linger_secs = Some(0))
+                    .await;
+                Ok("Blz, vou sair assim que o áudio acabar!!".to_string())
+            }
+            Ok(secs) => {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.linger_secs = Some(secs)
+                })
+                .await;
+                Ok(format!(
+                    "Blz, vou ficar {secs} segundos no canal depois do áudio!!"
+                ))
+            }
+            Err(_) => Err("Tem que me dizer os segundos, tipo `linger 30`, ou `off`".to_string()),
+        },
+        None => Err("Tem que me dizer os segundos, tipo `linger 30`, ou `off`".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Turns playing sounds at all on and off, what the last step of `setup` sets.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn paused(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match args
+        .single::<String>()
+        .ok()
+        .as_deref()
+        .and_then(parse_toggle)
+    {
+        Some(paused) => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.paused = paused).await;
+            if paused {
+                Ok("Blz, pausei os áudios!!")
+            } else {
+                Ok("Blz, voltei a tocar os áudios!!")
+            }
+        }
+        None => Err("Tem que me dizer `on` ou `off`"),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("play-afk")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn play_afk(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match args
+        .single::<String>()
+        .ok()
+        .as_deref()
+        .and_then(parse_toggle)
+    {
+        Some(play_in_afk) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.play_in_afk = play_in_afk
+            })
+            .await;
+            if play_in_afk {
+                Ok("Blz, vou tocar no canal AFK também!!")
+            } else {
+                Ok("Blz, não vou mais tocar no canal AFK!!")
+            }
+        }
+        None => Err("Tem que me dizer `on` ou `off`"),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("nudge")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn nudge_unset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match args
+        .single::<String>()
+        .ok()
+        .as_deref()
+        .and_then(parse_toggle)
+    {
+        Some(nudge_unset) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.nudge_unset = nudge_unset
+            })
+            .await;
+            if nudge_unset {
+                Ok("Blz, se alguém entrar sem ter áudio configurado eu aviso no canal do sistema!!")
+            } else {
+                Ok("Blz, não aviso mais quando faltar áudio!!")
+            }
+        }
+        None => Err("Tem que me dizer `on` ou `off`"),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("first-join")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn first_join(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match args
+        .single::<String>()
+        .ok()
+        .as_deref()
+        .and_then(parse_toggle)
+    {
+        Some(first_join_only) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.first_join_only = first_join_only
+            })
+            .await;
+            if first_join_only {
+                Ok("Blz, só vou tocar na primeira vez que cada um entrar!!")
+            } else {
+                Ok("Blz, vou tocar sempre que alguém entrar!!")
+            }
+        }
+        None => Err("Tem que me dizer `on` ou `off`"),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("command-channel")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn command_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let reply = match arg.as_deref() {
+        Some("off") => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.command_channel = None).await;
+            Ok("Blz, aceito comando em qualquer canal!!".to_string())
+        }
+        Some(channel) => match channel.parse::<ChannelId>() {
+            Ok(channel_id) => {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.command_channel = Some(channel_id)
+                })
+                .await;
+                Ok(format!(
+                    "Blz, só aceito comando no {}!!",
+                    channel_id.mention()
+                ))
+            }
+            Err(_) => Err("Esse canal aí não existe".to_string()),
+        },
+        None => Err("Tem que me dizer o canal, tipo `command-channel #bot`, ou `off`".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("block-channel")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn block_channel(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_channel_blocked(ctx, msg, args, true).await;
+
+    Ok(())
+}
+
+#[command("unblock-channel")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn unblock_channel(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_channel_blocked(ctx, msg, args, false).await;
+
+    Ok(())
+}
+
+/// Adds or removes the channel in `args` from the guild's blocked channels.
+async fn set_channel_blocked(ctx: &Context, msg: &Message, mut args: Args, blocked: bool) {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return;
+    };
+
+    let reply = match args.single::<ChannelId>() {
+        Ok(channel_id) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                if blocked {
+                    guild_config.blocked_channels.insert(channel_id);
+                } else {
+                    guild_config.blocked_channels.remove(&channel_id);
+                }
+            })
+            .await;
+            if blocked {
+                Ok(format!("Blz, não toco mais no {}!!", channel_id.mention()))
+            } else {
+                Ok(format!("Blz, volto a tocar no {}!!", channel_id.mention()))
+            }
+        }
+        Err(_) => Err("Tem que me dizer o canal, tipo o id dele ou <#id>".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+}
+
+#[command("block-user")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn block_user(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_user_blocked(ctx, msg, args, true).await;
+
+    Ok(())
+}
+
+#[command("unblock-user")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn unblock_user(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_user_blocked(ctx, msg, args, false).await;
+
+    Ok(())
+}
+
+#[command("allow-bot")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn allow_bot(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_bot_allowed(ctx, msg, args, true).await;
+
+    Ok(())
+}
+
+#[command("disallow-bot")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn disallow_bot(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_bot_allowed(ctx, msg, args, false).await;
+
+    Ok(())
+}
+
+/// Adds or removes the bot in `args` from those whose joins play sounds.
+async fn set_bot_allowed(ctx: &Context, msg: &Message, mut args: Args, allowed: bool) {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return;
+    };
+
+    let reply = match args.single::<UserId>() {
+        Ok(uid) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                if allowed {
+                    guild_config.allowed_bots.insert(uid);
+                } else {
+                    guild_config.allowed_bots.remove(&uid);
+                }
+            })
+            .await;
+            if allowed {
+                Ok(format!("Blz, toco quando o {} entrar!!", uid.mention()))
+            } else {
+                Ok(format!("Blz, volto a ignorar o {}!!", uid.mention()))
+            }
+        }
+        Err(_) => Err("Tem que me dizer qual bot, tipo o id ou marcando".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+}
+
+/// Plays nothing for the member's joins for a while, like `silence @fulano 2h`,
+/// without touching their sounds. `unsilence` lifts it early.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn silence(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let uid = args.single::<UserId>().ok();
+    let secs = args
+        .single::<String>()
+        .ok()
+        .and_then(|span| parse_span(&span));
+    let reply = match (uid, secs) {
+        (Some(uid), Some(secs)) => {
+            let now = unix_now();
+            let until = now.saturating_add(secs);
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.silenced.retain(|_, &mut until| now < until);
+                guild_config.silenced.insert(uid, until);
+            })
+            .await;
+            Ok(format!(
+                "Blz, não toco nada pro {} até <t:{until}:f>!!",
+                uid.mention()
+            ))
+        }
+        _ => Err(
+            "Tem que me dizer quem e por quanto tempo, tipo `silence @fulano 30m`, `2h` ou `1d`"
+                .to_string(),
+        ),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn unsilence(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let reply = match args.single::<UserId>() {
+        Ok(uid) => {
+            let now = unix_now();
+            let mut silenced = false;
+            update_guild_config(ctx, gid, |guild_config| {
+                silenced = guild_config
+                    .silenced
+                    .remove(&uid)
+                    .is_some_and(|until| now < until);
+            })
+            .await;
+            if silenced {
+                Ok(format!("Blz, volto a tocar pro {}!!", uid.mention()))
+            } else {
+                Err(format!("O {} não tava silenciado", uid.mention()))
+            }
+        }
+        Err(_) => Err("Tem que me dizer quem, tipo o id ou marcando".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Adds or removes the member in `args` from those who may not change the guild's
+/// sounds.
+async fn set_user_blocked(ctx: &Context, msg: &Message, mut args: Args, blocked: bool) {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return;
+    };
+
+    let reply = match args.single::<UserId>() {
+        Ok(uid) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                if blocked {
+                    guild_config.blocked_users.insert(uid);
+                } else {
+                    guild_config.blocked_users.remove(&uid);
+                }
+            })
+            .await;
+            if blocked {
+                Ok(format!("Blz, {} não mexe mais nos áudios!!", uid.mention()))
+            } else {
+                Ok(format!(
+                    "Blz, {} pode mexer nos áudios de novo!!",
+                    uid.mention()
+                ))
+            }
+        }
+        Err(_) => Err("Tem que me dizer quem, tipo o id ou marcando".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+}
+
+/// Whether `msg`'s author is blocked from changing sounds in its guild, telling
+/// them so when they are.
+async fn is_blocked_user(ctx: &Context, msg: &Message) -> bool {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return false;
+    };
+
+    let blocked = guild_config(ctx, gid)
+        .await
+        .blocked_users
+        .contains(&msg.author.id);
+    if blocked {
+        refuse(ctx, msg, "Tu não pode mexer nos áudios daqui").await;
+    }
+    blocked
+}
+
+/// Writes down every entrance sound played and who it was for in a channel, like
+/// `transcript-channel #acessibilidade`. `off` stops.
+#[command("transcript-channel")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn transcript_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let reply = match arg.as_deref() {
+        Some("off") => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.transcript_channel = None
+            })
+            .await;
+            Ok("Blz, não escrevo mais os áudios que toco!!".to_string())
+        }
+        Some(channel) => match channel.parse::<ChannelId>() {
+            // posting in some other guild's channel is not on
+            Ok(channel_id)
+                if ctx
+                    .cache
+                    .guild_field(gid, |guild| guild.channels.contains_key(&channel_id))
+                    .await
+                    != Some(true) =>
+            {
+                Err(format!("O canal {} não é daqui", channel_id.mention()))
+            }
+            Ok(channel_id) => {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.transcript_channel = Some(channel_id)
+                })
+                .await;
+                Ok(format!(
+                    "Blz, vou escrever no {} cada áudio que tocar e pra quem!!",
+                    channel_id.mention()
+                ))
+            }
+            Err(_) => Err("Esse canal aí não existe".to_string()),
+        },
+        None => Err(
+            "Tem que me dizer o canal, tipo `transcript-channel #acessibilidade`, ou `off`"
+                .to_string(),
+        ),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("announce-channel")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn announce_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let reply = match arg.as_deref() {
+        Some("off") => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.announce_channel = None
+            })
+            .await;
+            Ok("Blz, não posto mais as legendas!!".to_string())
+        }
+        Some(channel) => match channel.parse::<ChannelId>() {
+            // posting in some other guild's channel is not on
+            Ok(channel_id)
+                if ctx
+                    .cache
+                    .guild_field(gid, |guild| guild.channels.contains_key(&channel_id))
+                    .await
+                    != Some(true) =>
+            {
+                Err(format!("O canal {} não é daqui", channel_id.mention()))
+            }
+            Ok(channel_id) => {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.announce_channel = Some(channel_id)
+                })
+                .await;
+                Ok(format!(
+                    "Blz, as legendas dos áudios vão pro {}!!",
+                    channel_id.mention()
+                ))
+            }
+            Err(_) => Err("Esse canal aí não existe".to_string()),
+        },
+        None => {
+            Err("Tem que me dizer o canal, tipo `announce-channel #geral`, ou `off`".to_string())
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Longest caption a sound can post as it plays.
+const MAX_CAPTION_LEN: usize = 200;
+
+/// Gives a sound a message to post in the `announce-channel` every time it plays,
+/// like `caption buzina 📢 BUZINA!`, or takes it away with `off`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn caption(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let label = args.single::<String>().ok();
+    let caption = args.rest().trim();
+    let reply = match label {
+        Some(label) if !caption.is_empty() => {
+            let exists = {
+                let sound_store = sound_store(ctx).await;
+                let sound_store = sound_store.read().await;
+                find_sound(&sound_store, SoundKey::Guild(gid), Some(&label)).is_some()
+            };
+            let caption = (caption != "off").then(|| caption.to_string());
+            if !exists {
+                Err(format!("Não tem nenhum áudio `{label}`"))
+            } else if caption
+                .as_ref()
+                .is_some_and(|caption| caption.chars().count() > MAX_CAPTION_LEN)
+            {
+                Err(format!(
+                    "Calma lá, a legenda pode ter no máximo {MAX_CAPTION_LEN} caracteres"
+                ))
+            } else {
+                let set = caption.is_some();
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config
+                        .sounds
+                        .entry(label.clone())
+                        .or_default()
+                        .caption = caption;
+                })
+                .await;
+                if set {
+                    Ok(format!("Blz, o áudio `{label}` agora tem legenda!!"))
+                } else {
+                    Ok(format!("Blz, tirei a legenda do áudio `{label}`!!"))
+                }
+            }
+        }
+        _ => Err(
+            "Tem que me dizer o áudio e a legenda, tipo `caption buzina 📢 BUZINA!`, ou `off`"
+                .to_string(),
+        ),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Longest `set-message` template, so the reply stays a reply.
+const MAX_SET_MESSAGE_LEN: usize = 200;
+
+#[command("set-message")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn set_message(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let template = args.rest().trim();
+    let reply = match template {
+        "" => Err(
+            "Tem que me dizer a mensagem, tipo `set-message Show, {label} salvo!`, ou `off`"
+                .to_string(),
+        ),
+        "off" => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.set_message = None).await;
+            Ok("Blz, voltei pra mensagem de sempre!!".to_string())
+        }
+        template if template.chars().count() > MAX_SET_MESSAGE_LEN => Err(format!(
+            "Calma lá, a mensagem pode ter no máximo {MAX_SET_MESSAGE_LEN} caracteres"
+        )),
+        template => {
+            let template = template.to_string();
+            let example = template.replace("{label}", "buzina");
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.set_message = Some(template)
+            })
+            .await;
+            Ok(format!("Blz, vai ficar assim: {example}"))
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Longest `farewell`, like the `set-message` template.
+const MAX_FAREWELL_LEN: usize = MAX_SET_MESSAGE_LEN;
+
+/// Sets a message posted to the announce channel whenever the bot leaves after a
+/// sound, like `farewell até mais!`, or stops posting it with `off`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn farewell(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let farewell = args.rest().trim();
+    let reply = match farewell {
+        "" => Err("Tem que me dizer a mensagem, tipo `farewell até mais!`, ou `off`".to_string()),
+        "off" => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.farewell = None).await;
+            Ok("Blz, saio sem falar nada!!".to_string())
+        }
+        farewell if farewell.chars().count() > MAX_FAREWELL_LEN => Err(format!(
+            "Calma lá, a mensagem pode ter no máximo {MAX_FAREWELL_LEN} caracteres"
+        )),
+        farewell => {
+            let farewell = farewell.to_string();
+            let announce_channel = guild_config(ctx, gid).await.announce_channel;
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.farewell = Some(farewell)
+            })
+            .await;
+            Ok(match announce_channel {
+                Some(channel_id) => format!("Blz, mando no {} quando sair!!", channel_id.mention()),
+                None => "Blz, mas só mando quando tiver um canal no `announce-channel`".to_string(),
+            })
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Longest nickname Discord accepts.
+const MAX_NICKNAME_LEN: usize = 32;
+
+/// Sets the bot's nickname in the guild, like `nickname DJ Entrada`, or goes back
+/// to its username with `off`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn nickname(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let nickname = match args.rest().trim() {
+        "" => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o apelido, tipo `nickname DJ Entrada`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+        "off" => None,
+        nickname if nickname.chars().count() > MAX_NICKNAME_LEN => {
+            refuse(
+                ctx,
+                msg,
+                format!("Calma lá, o apelido pode ter no máximo {MAX_NICKNAME_LEN} caracteres"),
+            )
+            .await;
+            return Ok(());
+        }
+        nickname => Some(nickname),
+    };
+
+    let reply = match gid.edit_nickname(&ctx.http, nickname).await {
+        Ok(()) => Ok(match nickname {
+            Some(nickname) => format!("Blz, agora eu sou o {nickname}!!"),
+            None => "Blz, voltei pro meu nome!!".to_string(),
+        }),
+        Err(serenity::Error::Http(e)) if is_forbidden(&e) => Err(
+            "Não tenho permissão pra mudar meu apelido aqui, me dá o `Change Nickname`".to_string(),
+        ),
+        Err(e) => {
+            warn!("Error setting the nickname in guild {gid}: {e}");
+            Err("Não consegui mudar meu apelido, tenta de novo".to_string())
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Dumps the guild's settings as json, to be restored later with `config-import`
+/// (here or in another guild). The sounds themselves are left to `export`.
+#[command("config-export")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn config_export(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let json = serde_json::to_string_pretty(&guild_config(ctx, gid).await)
+        .expect("guild config serializes");
+    let block = format!("```json\n{json}\n```");
+    if block.chars().count() <= DISCORD_MESSAGE_LEN {
+        respond(ctx, msg, block).await;
+        return Ok(());
+    }
+
+    let file = AttachmentType::Bytes {
+        data: json.into_bytes().into(),
+        filename: format!("config-{gid}.json"),
+    };
+    if let Err(e) = msg.channel_id.send_files(ctx, [file], |m| m).await {
+        warn!("Error sending the config of guild {gid}: {e}");
+        refuse(ctx, msg, "Deu pau").await;
+    }
+
+    Ok(())
+}
+
+/// Longest span `stats-export` goes back.
+const MAX_STATS_SPAN: u64 = 90 * 24 * 60 * 60;
+
+/// Sends the guild's latest sets, removes and plays as csv, from the last 30 days or
+/// some other span up to 90 days, like `stats-export 7d`. Each sound's total plays
+/// are in `top`.
+#[command("stats-export")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn stats_export(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let span = match args.single::<String>().ok() {
+        None => 30 * 24 * 60 * 60,
+        Some(span) => match parse_span(&span) {
+            Some(span) if span <= MAX_STATS_SPAN => span,
+            Some(_) => {
+                refuse(ctx, msg, "Calma lá, o máximo é `90d`").await;
+                return Ok(());
+            }
+            None => {
+                refuse(ctx, msg, "Não entendi o período, tipo `stats-export 7d`").await;
+                return Ok(());
+            }
+        },
+    };
+
+    let since = unix_now().saturating_sub(span);
+    let mut csv = "time,event,label,user\n".to_string();
+    let mut rows = 0;
+    let data_dir = {
+        let data = ctx.data.read().await;
+        data.get::<Config>()
+            .expect("config is set")
+            .data_dir
+            .clone()
+    };
+    let activity = match read_activity(&data_dir, gid).await {
+        Ok(activity) => activity,
+        Err(e) => {
+            warn!("Error reading the activity of guild {gid}: {e}");
+            refuse(ctx, msg, "Deu pau").await;
+            return Ok(());
+        }
+    };
+    for activity in activity {
+        if activity.at < since {
+            continue;
+        }
+        let time = DateTime::from_timestamp(activity.at as i64, 0)
+            .map_or_else(String::new, |time| time.to_rfc3339());
+        // sounds set here have safe labels, but an imported config may carry anything
+        let label = activity.label.map_or_else(String::new, |label| {
+            format!("\"{}\"", label.replace('"', "\"\""))
+        });
+        let user = activity
+            .user
+            .map_or_else(String::new, |user| user.to_string());
+        csv += &format!("{time},{},{label},{user}\n", activity.kind);
+        rows += 1;
+    }
+    if rows == 0 {
+        respond(ctx, msg, "Não aconteceu nada nesse período").await;
+        return Ok(());
+    }
+
+    let file = AttachmentType::Bytes {
+        data: csv.into_bytes().into(),
+        filename: format!("stats-{gid}.csv"),
+    };
+    if let Err(e) = msg.channel_id.send_files(ctx, [file], |m| m).await {
+        warn!("Error sending the stats of guild {gid}: {e}");
+        refuse(ctx, msg, "Deu pau").await;
+    }
+
+    Ok(())
+}
+
+/// Longest message Discord accepts.
+const DISCORD_MESSAGE_LEN: usize = 2000;
+
+/// Largest config `config-import` reads from an attachment.
+const MAX_CONFIG_BYTES: u64 = 256 * 1024;
+
+/// Replaces the guild's settings with the json given right after the command (a
+/// code block is fine) or attached, as made by `config-export`. Nothing changes
+/// unless all of it is valid.
+#[command("config-import")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn config_import(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let json = match msg.attachments.first() {
+        Some(attach) if attach.size > MAX_CONFIG_BYTES => {
+            refuse(ctx, msg, "Esse arquivo é grande demais pra ser uma config").await;
+            return Ok(());
+        }
+        Some(attach) => match attach.download().await {
+            Ok(content) => String::from_utf8_lossy(&content).into_owned(),
+            Err(e) => {
+                warn!("Error downloading config for guild {gid}: {e}");
+                refuse(ctx, msg, "Não consegui baixar o arquivo, tenta de novo").await;
+                return Ok(());
+            }
+        },
+        None => args.rest().to_string(),
+    };
+    let json = json.trim();
+    let json = json
+        .strip_prefix("```")
+        .and_then(|json| json.strip_suffix("```"))
+        .map_or(json, |json| json.strip_prefix("json").unwrap_or(json));
+
+    if json.trim().is_empty() {
+        refuse(
+            ctx,
+            msg,
+            "Tem que me mandar a config, do jeito que o `config-export` dá",
+        )
+        .await;
+        return Ok(());
+    }
+
+    let imported: GuildConfig = match serde_json::from_str(json) {
+        Ok(imported) => imported,
+        Err(e) => {
+            refuse(ctx, msg, format!("Essa config tá estragada: {e}")).await;
+            return Ok(());
+        }
+    };
+    if let Err(reply) = validate_guild_config(ctx, gid, &imported).await {
+        refuse(ctx, msg, reply).await;
+        return Ok(());
+    }
+
+    update_guild_config(ctx, gid, |guild_config| *guild_config = imported).await;
+    redecode_guild_sounds(&ctx.data, gid).await;
+    confirm(ctx, msg, "Blz, config importada!!").await;
+
+    Ok(())
+}
+
+/// Checks `profile` holds nothing `eq`, `fade-out` or `pan` would refuse.
+fn validate_profile(profile: &AudioProfile) -> Result<(), String> {
+    if let Some(Pan::Fixed(balance)) = profile.pan {
+        if !PAN_RANGE.contains(&balance) {
+            return Err("O pan tem que ser entre -1 e 1".to_string());
+        }
+    }
+    if profile
+        .fade_out_ms
+        .is_some_and(|ms| ms == 0 || ms > MAX_FADE_OUT_MS)
+    {
+        return Err(format!("O fade-out tem que ser até {MAX_FADE_OUT_MS}ms"));
+    }
+    let eq = &profile.eq;
+    if eq.bands.len() > MAX_EQ_BANDS
+        || eq.bands.keys().any(|freq| !EQ_FREQ_RANGE.contains(freq))
+        || eq.gains().any(|gain| !EQ_GAIN_RANGE.contains(&gain))
+    {
+        return Err("O equalizador tá fora dos limites do `eq`".to_string());
+    }
+    Ok(())
+}
+
+/// Checks an imported config holds nothing the commands setting it would refuse.
+async fn validate_guild_config(
+    ctx: &Context,
+    gid: GuildId,
+    guild_config: &GuildConfig,
+) -> Result<(), String> {
+    let ceiling = {
+        let data = ctx.data.read().await;
+        let config = data.get::<Config>().expect("config is set");
+        config.max_duration_ceiling
+    };
+    check_guild_config(guild_config, ceiling)?;
+
+    // channels of some other guild would never match anyway, and announcing there is not on
+    let channels = guild_config
+        .command_channel
+        .iter()
+        .chain(&guild_config.blocked_channels)
+        .chain(&guild_config.announce_channel)
+        .chain(&guild_config.transcript_channel)
+        .chain(guild_config.borrowed_channels.keys());
+    for channel_id in channels {
+        let here = ctx
+            .cache
+            .guild_field(gid, |guild| guild.channels.contains_key(channel_id))
+            .await;
+        if here == Some(false) {
+            return Err(format!("O canal {} não é daqui", channel_id.mention()));
+        }
+    }
+
+    Ok(())
+}
+
+/// The range checks of [`validate_guild_config`], which don't depend on the guild,
+/// also run on the config file's `[guild]` defaults.
+fn check_guild_config(guild_config: &GuildConfig, ceiling: Duration) -> Result<(), String> {
+    if guild_config
+        .cooldown_secs
+        .is_some_and(|secs| secs > MAX_COOLDOWN_SECS)
+    {
+        return Err(format!("O cooldown máximo é {MAX_COOLDOWN_SECS}s"));
+    }
+    if guild_config
+        .linger_secs
+        .is_some_and(|secs| secs > MAX_LINGER_SECS)
+    {
+        return Err(format!("O linger máximo é {MAX_LINGER_SECS}s"));
+    }
+    if let Some(rotation) = &guild_config.rotation {
+        if rotation.every_secs < MIN_ROTATION_SECS {
+            return Err(format!(
+                "Cada áudio tem que ficar pelo menos {MIN_ROTATION_SECS}s no rodízio"
+            ));
+        }
+    }
+    if guild_config.chance.is_some_and(|chance| chance > 100) {
+        return Err("A chance tem que ser entre 0 e 100%".to_string());
+    }
+    if guild_config
+        .quiet_hours
+        .as_ref()
+        .is_some_and(|quiet_hours| quiet_hours.hours.is_none() || !quiet_hours.is_valid())
+    {
+        return Err("O `quiet-hours` tá inválido".to_string());
+    }
+    if let Some(kbps) = guild_config.bitrate_kbps {
+        if !BITRATE_KBPS_RANGE.contains(&kbps) {
+            return Err(format!(
+                "O bitrate tem que ser entre {} e {} kbps",
+                BITRATE_KBPS_RANGE.start(),
+                BITRATE_KBPS_RANGE.end()
+            ));
+        }
+    }
+    if let Some(secs) = guild_config.max_duration_secs {
+        let ceiling = ceiling.as_secs();
+        if secs == 0 || secs > ceiling {
+            return Err(format!("A duração máxima tem que ser entre 1 e {ceiling}s"));
+        }
+    }
+    validate_profile(&AudioProfile::of(guild_config))?;
+    if guild_config.profiles.len() > MAX_PROFILES {
+        return Err(format!("Dá pra salvar no máximo {MAX_PROFILES} perfis"));
+    }
+    for (name, profile) in &guild_config.profiles {
+        if builtin_profile(name).is_some() || sanitize_label(name) != *name {
+            return Err(format!("O perfil `{name}` tem um nome inválido"));
+        }
+        validate_profile(profile).map_err(|e| format!("No perfil `{name}`: {e}"))?;
+    }
+    if guild_config
+        .volume
+        .is_some_and(|volume| volume > MAX_VOLUME_PERCENT)
+    {
+        return Err(format!("O volume tem que ser até {MAX_VOLUME_PERCENT}%"));
+    }
+    if guild_config
+        .loudness_target
+        .is_some_and(|target| !AGC_TARGET_RANGE.contains(&target))
+    {
+        return Err(format!(
+            "O alvo do `agc` tem que ser entre {} e {} LUFS",
+            AGC_TARGET_RANGE.start(),
+            AGC_TARGET_RANGE.end()
+        ));
+    }
+    if guild_config
+        .boost
+        .as_ref()
+        .is_some_and(|boost| boost.percent == 0 || boost.percent > MAX_BOOST_PERCENT)
+    {
+        return Err(format!("O boost tem que ser de 1 a {MAX_BOOST_PERCENT}%"));
+    }
+    if let Some(template) = &guild_config.set_message {
+        if template.chars().count() > MAX_SET_MESSAGE_LEN {
+            return Err(format!(
+                "A mensagem do set pode ter no máximo {MAX_SET_MESSAGE_LEN} caracteres"
+            ));
+        }
+    }
+
+    if guild_config
+        .comeback
+        .as_ref()
+        .is_some_and(|comeback| comeback.after_secs == 0)
+    {
+        return Err("O tempo do `comeback` tem que ser maior que zero".to_string());
+    }
+    if guild_config
+        .milestone
+        .as_ref()
+        .is_some_and(|milestone| !(1..=MAX_MILESTONE_MEMBERS).contains(&milestone.members))
+    {
+        return Err(format!(
+            "O `milestone` tem que ser de 1 a {MAX_MILESTONE_MEMBERS} pessoas"
+        ));
+    }
+
+    for (label, sound_config) in &guild_config.sounds {
+        if sound_config.only_for.len() > MAX_ASSIGNED {
+            return Err(format!(
+                "`{label}` passa do máximo de {MAX_ASSIGNED} membros"
+            ));
+        }
+        if sound_config.weight > MAX_WEIGHT {
+            return Err(format!("O peso de `{label}` passa do máximo, {MAX_WEIGHT}"));
+        }
+        if sound_config
+            .volume
+            .is_some_and(|volume| volume > MAX_VOLUME_PERCENT)
+        {
+            return Err(format!(
+                "O volume de `{label}` passa do máximo, {MAX_VOLUME_PERCENT}%"
+            ));
+        }
+        if sound_config
+            .cooldown_secs
+            .is_some_and(|secs| secs > MAX_COOLDOWN_SECS)
+        {
+            return Err(format!(
+                "O cooldown de `{label}` passa do máximo, {MAX_COOLDOWN_SECS} segundos"
+            ));
+        }
+        if sound_config
+            .loop_secs
+            .is_some_and(|secs| secs == 0 || secs > MAX_LOOP_SECS)
+        {
+            return Err(format!(
+                "`{label}` só pode repetir de 1 a {MAX_LOOP_SECS} segundos"
+            ));
+        }
+        if sound_config
+            .schedule
+            .as_ref()
+            .is_some_and(|schedule| !schedule.is_valid())
+        {
+            return Err(format!("O horário de `{label}` tá inválido"));
+        }
+        if sound_config
+            .caption
+            .as_ref()
+            .is_some_and(|caption| caption.chars().count() > MAX_CAPTION_LEN)
+        {
+            return Err(format!(
+                "A legenda de `{label}` pode ter no máximo {MAX_CAPTION_LEN} caracteres"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Joins the voice channel given by id, mention or name and stays there, sounds
+/// ending or not, until `release`. Entrance sounds of other channels don't play
+/// meanwhile, they'd move it out.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn hold(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.rest().trim();
+    let channel_id = match arg.parse::<ChannelId>() {
+        Ok(channel_id) => Some(channel_id),
+        Err(_) if arg.is_empty() => None,
+        Err(_) => ctx
+            .cache
+            .guild_field(gid, |guild| {
+                guild
+                    .channels
+                    .values()
+                    .find(|channel| {
+                        matches!(channel.kind, ChannelType::Voice | ChannelType::Stage)
+                            && channel.name.eq_ignore_ascii_case(arg)
+                    })
+                    .map(|channel| channel.id)
+            })
+            .await
+            .flatten(),
+    };
+    let channel_id = match channel_id {
+        Some(channel_id) => channel_id,
+        None => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o canal de voz, tipo `hold Geral`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    if join(ctx, gid, channel_id).await.is_none() {
+        refuse(ctx, msg, "Não consegui entrar nesse canal").await;
+        return Ok(());
+    }
+    set_held(ctx, gid, Some(channel_id)).await;
+    info!(
+        "Held in channel {channel_id} of guild {gid} by {}",
+        msg.author.id
+    );
+
+    let reply = format!(
+        "Blz, fico no {} até alguém mandar `release`!!",
+        channel_id.mention()
+    );
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Makes the sounds played for joins loop with the bot staying, for ambiance, like
+/// `stay on`, or go back to playing once and leaving with `off`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn stay(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let stay = match args
+        .single::<String>()
+        .ok()
+        .as_deref()
+        .and_then(parse_toggle)
+    {
+        Some(stay) => stay,
+        None => {
+            refuse(ctx, msg, "Tem que me dizer `on` ou `off`").await;
+            return Ok(());
+        }
+    };
+
+    update_guild_config(ctx, gid, |guild_config| guild_config.stay = stay).await;
+    let reply = if stay {
+        "Blz, os áudios vão ficar tocando em loop até todo mundo sair ou alguém mandar `stay off`!!"
+    } else {
+        stop_staying(ctx, gid).await;
+        "Blz, os áudios tocam uma vez só de novo!!"
+    };
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Longest `follow` window.
+const MAX_FOLLOW_SECS: u64 = 300;
+
+/// Makes the bot move along with the member it just played for, like `follow 60`
+/// for their moves over the next minute, or stay put with `off`.
+#[command("follow")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn follow_member(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let follow_secs = match args.single::<String>().ok().as_deref() {
+        Some("off") => None,
+        Some(secs) => match secs.trim_end_matches('s').parse::<u64>() {
+            Ok(secs) if (1..=MAX_FOLLOW_SECS).contains(&secs) => Some(secs),
+            _ => {
+                let reply = format!("Tem que ser de 1 a {MAX_FOLLOW_SECS}s");
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+        },
+        None => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer por quantos segundos, tipo `follow 60`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.follow_secs = follow_secs
+    })
+    .await;
+    let reply = match follow_secs {
+        Some(secs) => {
+            format!("Blz, vou atrás de quem eu tocar por {secs}s se a pessoa mudar de canal!!")
+        }
+        None => {
+            let mut data = ctx.data.write().await;
+            let following = data.get_mut::<Following>().expect("following is set");
+            following.remove(&gid);
+            "Blz, não vou mais atrás de ninguém!!".to_string()
+        }
+    };
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+struct Following;
+
+impl TypeMapKey for Following {
+    /// The member the bot follows in each guild and until when, see [`follow`].
+    type Value = HashMap<GuildId, (UserId, Instant)>;
+}
+
+/// Moves the bot, and whatever it's playing, to the channel `new` moved to if it's
+/// following them. Whether it did, in which case the move plays nothing else. It
+/// stops following once the window is over or they leave voice. Like joins, it
+/// moves nowhere while paused or in quiet hours, nor to a blocked or AFK channel.
+async fn follow(ctx: &Context, gid: GuildId, new: &VoiceState) -> bool {
+    let until = {
+        let data = ctx.data.read().await;
+        let following = data.get::<Following>().expect("following is set");
+        match following.get(&gid) {
+            Some(&(uid, until)) if uid == new.user_id => until,
+            _ => return false,
+        }
+    };
+    let channel_id = match new.channel_id {
+        Some(channel_id) if Instant::now() < until => channel_id,
+        _ => {
+            let mut data = ctx.data.write().await;
+            let following = data.get_mut::<Following>().expect("following is set");
+            following.remove(&gid);
+            return false;
+        }
+    };
+    // mutes and the like are updates too
+    if connected_to(ctx, gid, channel_id).await || held_in(ctx, gid).await.is_some() {
+        return false;
+    }
+    // still following, they may move on to somewhere it does go
+    let guild_config = guild_config(ctx, gid).await;
+    if guild_config.paused
+        || in_quiet_hours(&guild_config)
+        || guild_config.blocked_channels.contains(&channel_id)
+        || (!guild_config.play_in_afk && afk_channel(ctx, gid).await == Some(channel_id))
+    {
+        debug!(
+            "Not following {} to channel {channel_id} of guild {gid}",
+            new.user_id
+        );
+        return false;
+    }
+
+    debug!(
+        "Following {} to channel {channel_id} of guild {gid}",
+        new.user_id
+    );
+    join(ctx, gid, channel_id).await.is_some()
+}
+
+/// Leaves `channel_id` once nobody is left there to hear the sound looping for
+/// `GuildConfig::stay`.
+async fn leave_if_alone(ctx: &Context, gid: GuildId, channel_id: ChannelId) {
+    // on every voice state update, not worth cloning the whole config for
+    let stay = {
+        let data = ctx.data.read().await;
+        let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+        let defaults = data.get::<GuildDefaults>().expect("guild defaults are set");
+        guild_configs
+            .get(&gid)
+            .or(defaults.as_ref())
+            .is_some_and(|guild_config| guild_config.stay)
+    };
+    if !stay || !connected_to(ctx, gid, channel_id).await {
+        return;
+    }
+    let bot = ctx.cache.current_user_id().await;
+    if members_in_channel(ctx, gid, channel_id, bot).await == 0 {
+        debug!("Everyone left channel {channel_id} of guild {gid}, stopping the loop");
+        stop_staying(ctx, gid).await;
+    }
+}
+
+/// Stops the sound looping in `gid`, see [`Looping::Forever`], and leaves unless
+/// the bot is held.
+async fn stop_staying(ctx: &Context, gid: GuildId) {
+    let last_track = {
+        let data = ctx.data.read().await;
+        let tracks = data.get::<Tracks>().expect("tracks are set");
+        tracks.get(&gid).cloned()
+    };
+    if let Some(track) = last_track {
+        // already ended or stopped otherwise
+        let _ = track.stop();
+    }
+    if held_in(ctx, gid).await.is_some() {
+        return;
+    }
+    let call = match songbird::get(ctx).await {
+        Some(manager) => manager.get(gid),
+        None => None,
+    };
+    if let Some(call) = call {
+        leave(&call).await;
+    }
+}
+
+/// Lets the bot go back to leaving after sounds, leaving right away if nothing is
+/// playing.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn release(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    if held_in(ctx, gid).await.is_none() {
+        refuse(ctx, msg, "Não to preso em canal nenhum").await;
+        return Ok(());
+    }
+    set_held(ctx, gid, None).await;
+    info!("Released in guild {gid} by {}", msg.author.id);
+
+    let last_track = {
+        let data = ctx.data.read().await;
+        let tracks = data.get::<Tracks>().expect("tracks are set");
+        tracks.get(&gid).cloned()
+    };
+    let playing = match last_track {
+        Some(track) => track
+            .get_info()
+            .await
+            .is_ok_and(|state| state.playing == TrackPlayMode::Play),
+        None => false,
+    };
+    // a sound still playing leaves once it ends, like always
+    if !playing {
+        let call = match songbird::get(ctx).await {
+            Some(manager) => manager.get(gid),
+            None => None,
+        };
+        if let Some(call) = call {
+            leave(&call).await;
+        }
+    }
+
+    confirm(ctx, msg, "Blz, to livre de novo!!").await;
+
+    Ok(())
+}
+
+/// Reports where the bot is connected in the guild and whether it's playing, to
+/// tell a stuck bot apart from one that's just lingering.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let call = match songbird::get(ctx).await {
+        Some(manager) => manager.get(gid),
+        None => None,
+    };
+    let (channel, queued) = match &call {
+        Some(call) => {
+            let call = call.lock().await;
+            (call.current_channel(), call.queue().current())
+        }
+        None => (None, None),
+    };
+
+    let last_track = {
+        let data = ctx.data.read().await;
+        let tracks = data.get::<Tracks>().expect("tracks are set");
+        tracks.get(&gid).cloned()
+    };
+    let mut playing = false;
+    for track in queued.into_iter().chain(last_track) {
+        if let Ok(state) = track.get_info().await {
+            playing |= state.playing == TrackPlayMode::Play;
+        }
+    }
+
+    let reply = match (call, channel) {
+        (None, _) => "Não tenho nenhuma chamada nesse servidor".to_string(),
+        (Some(_), None) => "Tenho uma chamada aberta mas não to em nenhum canal".to_string(),
+        (Some(_), Some(channel)) => format!(
+            "To no {}, {}",
+            ChannelId(channel.0).mention(),
+            if playing { "tocando" } else { "parado" }
+        ),
+    };
+
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("override")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn sound_override(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let sound_override = match arg.as_deref() {
+        Some("off") => {
+            clear_override(ctx, gid).await;
+            confirm(ctx, msg, "Blz, voltei pro áudio de sempre!!").await;
+            return Ok(());
+        }
+        Some(limit) => parse_override(limit),
+        None => None,
+    };
+    let sound_override = match sound_override {
+        Some(sound_override) => sound_override,
+        None => {
+            let usage = "Tem que me dizer por quantas entradas (`override 10`) ou por quanto \
+                tempo (`override 30m`, `2h`, `1d`), e mandar o áudio junto";
+            refuse(ctx, msg, usage).await;
+            return Ok(());
+        }
+    };
+
+    let url = args.single::<String>().ok();
+    let upload = url.map(Upload::Url);
+    let result = match save_from_message(ctx, msg, SoundKey::Override(gid), upload, None).await {
+        Some(result) => result,
+        None => return Ok(()),
+    };
+
+    let reply = match result {
+        Ok(_) => {
+            let reply = match (sound_override.remaining, sound_override.expires_at) {
+                (Some(joins), _) => {
+                    format!("Blz, vou tocar esse áudio nas próximas {joins} entradas!!")
+                }
+                (_, Some(at)) => format!("Blz, vou tocar esse áudio até <t:{at}:f>!!"),
+                _ => unreachable!("parse_override always sets a limit"),
+            };
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.sound_override = Some(sound_override)
+            })
+            .await;
+            Ok(reply)
+        }
+        Err(e) => Err(error_reply(&e, locale(ctx, Some(gid)).await)),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// `10` means the next 10 joins, `30m`, `2h` or `1d` from now on.
+fn parse_override(limit: &str) -> Option<SoundOverride> {
+    if let Ok(joins) = limit.parse::<u32>() {
+        return (joins > 0).then_some(SoundOverride {
+            remaining: Some(joins),
+            expires_at: None,
+        });
+    }
+
+    Some(SoundOverride {
+        remaining: None,
+        expires_at: Some(unix_now().saturating_add(parse_span(limit)?)),
+    })
+}
+
+/// Longest span [`parse_span`] takes, a year.
+const MAX_SPAN_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Seconds in a span like `30m`, `2h` or `1d`, up to [`MAX_SPAN_SECS`].
+fn parse_span(span: &str) -> Option<u64> {
+    let unit_secs = match span.chars().last()? {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let amount: u64 = span[..span.len() - 1].parse().ok().filter(|&n| n > 0)?;
+    amount
+        .checked_mul(unit_secs)
+        .filter(|&secs| secs <= MAX_SPAN_SECS)
+}
+
+/// Counts a join the override played on, dropping it once it's used up.
+async fn use_override(ctx: &Context, gid: GuildId) {
+    let mut exhausted = false;
+    update_guild_config(ctx, gid, |guild_config| {
+        if let Some(sound_override) = &mut guild_config.sound_override {
+            if let Some(remaining) = &mut sound_override.remaining {
+                *remaining = remaining.saturating_sub(1);
+            }
+            exhausted = !sound_override.is_active();
+        }
+    })
+    .await;
+
+    if exhausted {
+        clear_override(ctx, gid).await;
+    }
+}
+
+/// Makes the guild's sounds play louder for a while, like `boost 50 10` for the next
+/// 10 joins at 150% of the volume, or `boost 50 30m` for half an hour. Goes back on
+/// its own, or with `boost off`.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn boost(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let (percent, limit) = (args.single::<String>().ok(), args.single::<String>().ok());
+    let boost = match (percent.as_deref(), limit.as_deref()) {
+        (Some("off"), None) => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.boost = None).await;
+            confirm(ctx, msg, "Blz, voltei pro volume de sempre!!").await;
+            return Ok(());
+        }
+        (Some(percent), Some(limit)) => {
+            let percent = match percent.trim_end_matches('%').parse::<u32>() {
+                Ok(percent) if (1..=MAX_BOOST_PERCENT).contains(&percent) => percent,
+                _ => {
+                    let reply = format!("O boost tem que ser de 1 a {MAX_BOOST_PERCENT}%");
+                    refuse(ctx, msg, reply).await;
+                    return Ok(());
+                }
+            };
+            parse_override(limit).map(|until| VolumeBoost { percent, until })
+        }
+        _ => None,
+    };
+    let boost = match boost {
+        Some(boost) => boost,
+        None => {
+            let usage = "Tem que me dizer quanto mais alto e por quantas entradas (`boost 50 10`) \
+                ou por quanto tempo (`boost 50 30m`, `2h`, `1d`), ou `off`";
+            refuse(ctx, msg, usage).await;
+            return Ok(());
+        }
+    };
+
+    let reply = match (boost.until.remaining, boost.until.expires_at) {
+        (Some(joins), _) => format!(
+            "Blz, os sons vão tocar {}% mais alto nas próximas {joins} entradas!!",
+            boost.percent
+        ),
+        (_, Some(at)) => format!(
+            "Blz, os sons vão tocar {}% mais alto até <t:{at}:f>!!",
+            boost.percent
+        ),
+        _ => unreachable!("parse_override always sets a limit"),
+    };
+    update_guild_config(ctx, gid, |guild_config| guild_config.boost = Some(boost)).await;
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Counts a join the boost played louder on, dropping it once it's used up.
+async fn use_boost(ctx: &Context, gid: GuildId) {
+    update_guild_config(ctx, gid, |guild_config| {
+        if let Some(boost) = &mut guild_config.boost {
+            if let Some(remaining) = &mut boost.until.remaining {
+                *remaining = remaining.saturating_sub(1);
+            }
+            if !boost.until.is_active() {
+                guild_config.boost = None;
+            }
+        }
+    })
+    .await;
+}
+
+/// Drops the guild's override along with its sound.
+async fn clear_override(ctx: &Context, gid: GuildId) {
+    update_guild_config(ctx, gid, |guild_config| guild_config.sound_override = None).await;
+
+    clear_key_sound(ctx, SoundKey::Override(gid)).await;
+}
+
+/// What Discord allows for voice channels, in kbps.
+const BITRATE_KBPS_RANGE: RangeInclusive<u32> = 8..=384;
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn bitrate(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let reply = match arg.as_deref() {
+        Some("off") => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.bitrate_kbps = None).await;
+            Ok("Blz, voltei pro bitrate padrão!!".to_string())
+        }
+        Some(kbps) => match kbps.parse::<u32>() {
+            Ok(kbps) if BITRATE_KBPS_RANGE.contains(&kbps) => {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.bitrate_kbps = Some(kbps)
+                })
+                .await;
+                Ok(format!(
+                    "Blz, vou tocar a {kbps} kbps (ou o máximo do canal, se for menor)!!"
+                ))
+            }
+            _ => Err(format!(
+                "O bitrate tem que ser entre {} e {} kbps",
+                BITRATE_KBPS_RANGE.start(),
+                BITRATE_KBPS_RANGE.end()
+            )),
+        },
+        None => Err("Tem que me dizer o bitrate em kbps, tipo `bitrate 96`, ou `off`".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("max-duration")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn max_duration(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let (default, ceiling) = {
+        let data = ctx.data.read().await;
+        let config = data.get::<Config>().expect("config is set");
+        (config.max_duration, config.max_duration_ceiling)
+    };
+
+    let arg = args.single::<String>().ok();
+    let reply = match arg.as_deref() {
+        Some("off") => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.max_duration_secs = None
+            })
+            .await;
+            Ok(format!(
+                "Blz, voltei pro limite padrão, {}s!!",
+                default.as_secs()
+            ))
+        }
+        Some(secs) => match secs.parse::<u64>() {
+            Ok(secs) if secs > ceiling.as_secs() => Err(format!(
+                "Calma lá, o máximo que dá pra liberar é {}s",
+                ceiling.as_secs()
+            )),
+            Ok(secs) if secs > 0 => {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.max_duration_secs = Some(secs)
+                })
+                .await;
+                Ok(format!(
+                    "Blz, agora os áudios do server podem ter até {secs}s!!"
+                ))
+            }
+            _ => Err("Tem que ser um número de segundos maior que zero".to_string()),
+        },
+        None => Err(format!(
+            "Tem que me dizer os segundos, tipo `max-duration 30` (até {}s), ou `off`",
+            ceiling.as_secs()
+        )),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn pan(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let pan = match arg.as_deref() {
+        Some("off") => None,
+        Some("sweep") => Some(Pan::Sweep),
+        Some(balance) => match balance.replace(',', ".").parse::<f32>() {
+            Ok(balance) if PAN_RANGE.contains(&balance) => Some(Pan::Fixed(balance)),
+            _ => {
+                refuse(
+                    ctx,
+                    msg,
+                    "O pan tem que ser entre -1 (esquerda) e 1 (direita), ou `sweep`",
+                )
+                .await;
+                return Ok(());
+            }
+        },
+        None => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o pan, tipo `pan -0.5`, `pan sweep` ou `pan off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    update_guild_config(ctx, gid, |guild_config| guild_config.pan = pan).await;
+    redecode_guild_sounds(&ctx.data, gid).await;
+
+    let reply = match pan {
+        Some(pan) => format!("Blz, os sons do server vão tocar com pan {pan}!!"),
+        None => "Blz, os sons do server vão tocar no meio de novo!!".to_string(),
+    };
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Boosts or cuts the bass, the treble or a band around some frequency of the
+/// guild's sounds, like `eq bass 6`, `eq treble -3` or `eq 1000 2`. `off` in place of
+/// the gain takes that one away, `eq off` all of them.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn eq(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let usage = "Tem que me dizer o quê e quantos dB, tipo `eq bass 6`, `eq treble -3` ou \
+        `eq 1000 2`, ou `eq off`";
+    let mut eq = guild_config(ctx, gid).await.eq;
+    let (what, gain) = (args.single::<String>().ok(), args.single::<String>().ok());
+    let gain = match gain.as_deref() {
+        None if what.as_deref() != Some("off") => {
+            refuse(ctx, msg, usage).await;
+            return Ok(());
+        }
+        Some("off") | None => None,
+        Some(gain) => match gain.replace(',', ".").parse::<f32>() {
+            Ok(gain) if EQ_GAIN_RANGE.contains(&gain) => Some(gain),
+            _ => {
+                let reply = format!(
+                    "O ganho tem que ser entre {} e {} dB",
+                    EQ_GAIN_RANGE.start(),
+                    EQ_GAIN_RANGE.end()
+                );
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+        },
+    };
+    match what.as_deref() {
+        Some("off") => eq = Equalizer::default(),
+        Some("bass") => eq.bass = gain,
+        Some("treble") => eq.treble = gain,
+        Some(freq) => match freq.trim_end_matches("Hz").parse::<u32>() {
+            Ok(freq) if EQ_FREQ_RANGE.contains(&freq) => {
+                match gain {
+                    Some(gain) => eq.bands.insert(freq, gain),
+                    None => eq.bands.remove(&freq),
+                };
+                if eq.bands.len() > MAX_EQ_BANDS {
+                    let reply = format!("Calma lá, o máximo é {MAX_EQ_BANDS} frequências");
+                    refuse(ctx, msg, reply).await;
+                    return Ok(());
+                }
+            }
+            Ok(_) => {
+                let reply = format!(
+                    "A frequência tem que ser entre {} e {} Hz",
+                    EQ_FREQ_RANGE.start(),
+                    EQ_FREQ_RANGE.end()
+                );
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+            Err(_) => {
+                refuse(ctx, msg, usage).await;
+                return Ok(());
+            }
+        },
+        None => {
+            refuse(ctx, msg, usage).await;
+            return Ok(());
+        }
+    }
+
+    let reply = match eq.filter() {
+        Some(_) => format!("Blz, os sons do server vão tocar com {eq}!!"),
+        None => "Blz, os sons do server vão tocar sem equalizador!!".to_string(),
+    };
+    update_guild_config(ctx, gid, |guild_config| guild_config.eq = eq).await;
+    redecode_guild_sounds(&ctx.data, gid).await;
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Plays one of the guild's sounds in the caller's channel, to hear how `eq` and
+/// `pan` left it.
+#[command("eq-preview")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn eq_preview(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let channel_id = if let Some(channel_id) = voice_channel_of(ctx, gid, msg.author.id).await {
+        channel_id
+    } else {
+        refuse(ctx, msg, "Entra num canal de voz primeiro!!").await;
+        return Ok(());
+    };
+    let (label, sound) =
+        if let Some(picked) = pick_labeled_sound(ctx, SoundKey::Guild(gid), None, None).await {
+            picked
+        } else {
+            refuse(ctx, msg, "Não tem nenhum áudio configurado!!").await;
+            return Ok(());
+        };
+    let volume = playback_volume(&guild_config(ctx, gid).await, Some(&label));
+    play(ctx, gid, channel_id, &sound, volume).await;
+
+    Ok(())
+}
+
+/// Decodes one of the guild's sounds again from its file, with the filters the guild
+/// has right now, and plays the result in the caller's channel, like
+/// `filter-preview buzina`. The stored sound is left as it is.
+#[command("filter-preview")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn filter_preview(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let channel_id = if let Some(channel_id) = voice_channel_of(ctx, gid, msg.author.id).await {
+        channel_id
+    } else {
+        refuse(ctx, msg, "Entra num canal de voz primeiro!!").await;
+        return Ok(());
+    };
+    let label = match args.single::<String>() {
+        Ok(label) => label,
+        Err(_) => {
+            refuse(ctx, msg, "Qual áudio? Tipo `filter-preview buzina`").await;
+            return Ok(());
+        }
+    };
+
+    let key = SoundKey::Guild(gid);
+    let source = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        find_sound(&sound_store, key, Some(&label)).map(|sound| sound.source.clone())
+    };
+    let path = match source {
+        Some(Some(path)) => path,
+        Some(None) => {
+            let reply = format!("O áudio `{label}` não tem arquivo pra decodificar de novo");
+            refuse(ctx, msg, reply).await;
+            return Ok(());
+        }
+        None => {
+            refuse(ctx, msg, format!("Não tem nenhum áudio `{label}`")).await;
+            return Ok(());
+        }
+    };
+
+    let (config, downloads) = {
+        let data = ctx.data.read().await;
+        let config = data.get::<Config>().expect("config is set").clone();
+        let downloads = data.get::<Downloads>().expect("downloads are set").clone();
+        (config, downloads)
+    };
+    let filter = key_filter(ctx, key).await;
+    let decoded = {
+        let _permit = downloads
+            .acquire_owned()
+            .await
+            .expect("downloads are never closed");
+        // it was already checked against the limit when it was set
+        tokio::time::timeout(config.audio_timeout, decode(&path, None, filter, &config))
+            .await
+            .map_err(|_| AudioError::Timeout)
+            .and_then(|decoded| decoded)
+    };
+    let decoded = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            warn!("Error decoding {path:?} for a filter preview: {e}");
+            refuse(ctx, msg, error_reply(&e, locale(ctx, Some(gid)).await)).await;
+            return Ok(());
+        }
+    };
+
+    let volume = playback_volume(&guild_config(ctx, gid).await, Some(&label));
+    play(ctx, gid, channel_id, &decoded.memory, volume).await;
+
+    Ok(())
+}
+
+/// Longest fade-out, more and it's just the sound getting quieter.
+const MAX_FADE_OUT_MS: u64 = 5000;
+
+/// Loudest a guild or one of its sounds may be set to, in percent.
+const MAX_VOLUME_PERCENT: u32 = 200;
+
+/// The volume a guild sound plays at: the guild's `volume` times the sound's, so a
+/// sound at 50% in a guild at 150% plays at 75%. Both are 100% unless set. An active
+/// [`VolumeBoost`] adds to the guild's, and the result is clamped to
+/// [`MAX_VOLUME_PERCENT`] however they multiply. Sounds that aren't the guild's, with
+/// no `label`, only get the guild's.
+///
+/// With a `loudness_target`, sounds whose loudness was measured are also turned up
+/// or down by the difference, up to [`MAX_AGC_GAIN_DB`].
+fn playback_volume(guild_config: &GuildConfig, label: Option<&str>) -> f32 {
+    let percent = |volume: Option<u32>| volume.unwrap_or(100) as f32 / 100.0;
+    let sound = label.and_then(|label| guild_config.sounds.get(label));
+    let boost = match &guild_config.boost {
+        Some(boost) if boost.until.is_active() => 1.0 + boost.percent as f32 / 100.0,
+        _ => 1.0,
+    };
+    let agc = match (guild_config.loudness_target, sound.and_then(|s| s.loudness)) {
+        (Some(target), Some(loudness)) => {
+            let db = (target - loudness).clamp(-MAX_AGC_GAIN_DB, MAX_AGC_GAIN_DB);
+            10f32.powf(db / 20.0)
+        }
+        _ => 1.0,
+    };
+    let max = MAX_VOLUME_PERCENT as f32 / 100.0;
+    let volume = percent(guild_config.volume) * boost * percent(sound.and_then(|s| s.volume));
+    (volume * agc).clamp(0.0, max)
+}
+
+/// Loudness targets `agc` takes, in LUFS. Streaming services aim for about -14.
+const AGC_TARGET_RANGE: RangeInclusive<f32> = -40.0..=-5.0;
+
+/// Most `agc` turns a sound up or down by, in dB, so near silent sounds aren't
+/// blown up.
+const MAX_AGC_GAIN_DB: f32 = 12.0;
+
+/// Turns each of the guild's sounds up or down as it plays so they all sound as
+/// loud, like `agc -16` for -16 LUFS, or plays them as they are with `off`. Files
+/// are left untouched, sounds set before their loudness was measured get measured
+/// now.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn agc(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let target = match args.single::<String>().ok().as_deref() {
+        Some("off") => None,
+        Some(target) => match target.trim_end_matches("LUFS").replace(',', ".").parse() {
+            Ok(target) if AGC_TARGET_RANGE.contains(&target) => Some(target),
+            _ => {
+                let reply = format!(
+                    "O alvo tem que ser entre {} e {} LUFS",
+                    AGC_TARGET_RANGE.start(),
+                    AGC_TARGET_RANGE.end()
+                );
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+        },
+        None => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o alvo em LUFS, tipo `agc -16`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.loudness_target = target
+    })
+    .await;
+
+    let target = if let Some(target) = target {
+        target
+    } else {
+        confirm(ctx, msg, "Blz, os sons do server vão tocar como são!!").await;
+        return Ok(());
+    };
+
+    let unmeasured: Vec<String> = {
+        let guild_config = guild_config(ctx, gid).await;
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        sound_store
+            .get(&SoundKey::Guild(gid))
+            .into_iter()
+            .flatten()
+            .filter(|sound| sound.source.is_some())
+            .filter(|sound| {
+                let config = guild_config.sounds.get(&sound.label);
+                config.and_then(|config| config.loudness).is_none()
+            })
+            .map(|sound| sound.label.clone())
+            .collect()
+    };
+    for label in &unmeasured {
+        let _permit = download_permit(ctx).await;
+        measure_loudness(ctx, gid, label).await;
+    }
+
+    confirm(
+        ctx,
+        msg,
+        format!("Blz, os sons do server vão tocar perto de {target} LUFS!!"),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Sets the volume of the guild's sounds in percent, like `volume 80`, or of one of
+/// them, like `volume buzina 50`, on top of the guild's. `off` goes back to 100%.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn volume(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let args: Vec<&str> = args.raw().collect();
+    let (label, volume) = match args.as_slice() {
+        [volume] => (None, *volume),
+        [label, volume] => (Some(label.to_string()), *volume),
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o volume, tipo `volume 80` ou `volume buzina 50`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+    let volume = match volume.trim_end_matches('%') {
+        "off" => None,
+        percent => match percent.parse::<u32>() {
+            Ok(percent) if percent <= MAX_VOLUME_PERCENT => Some(percent),
+            _ => {
+                let reply = format!("O volume tem que ser de 0 a {MAX_VOLUME_PERCENT}%");
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+        },
+    };
+
+    let reply = match label {
+        Some(label) => {
+            let exists = {
+                let sound_store = sound_store(ctx).await;
+                let sound_store = sound_store.read().await;
+                find_sound(&sound_store, SoundKey::Guild(gid), Some(&label)).is_some()
+            };
+            if exists {
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.sounds.entry(label.clone()).or_default().volume = volume;
+                })
+                .await;
+                Ok(format!(
+                    "Blz, o áudio `{label}` agora toca a {}% do volume do server!!",
+                    volume.unwrap_or(100)
+                ))
+            } else {
+                Err(format!("Não tem nenhum áudio `{label}`"))
+            }
+        }
+        None => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.volume = volume).await;
+            Ok(format!(
+                "Blz, os sons do server agora tocam a {}%!!",
+                volume.unwrap_or(100)
+            ))
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Longest a sound can be set to loop for.
+const MAX_LOOP_SECS: u32 = 30;
+
+/// Makes one of the guild's sounds play over and over for as many seconds, like
+/// `loop chuva 10`, handy for short ambient clips. `off` plays it once again.
+#[command("loop")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn loop_sound(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let args: Vec<&str> = args.raw().collect();
+    let (label, secs) = match args.as_slice() {
+        [label, secs] => (sanitize_label(label), *secs),
+        _ => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer o áudio e por quantos segundos, tipo `loop chuva 10`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+    let secs = match secs.trim_end_matches('s') {
+        "off" => None,
+        secs => match secs.parse::<u32>() {
+            Ok(secs) if (1..=MAX_LOOP_SECS).contains(&secs) => Some(secs),
+            _ => {
+                let reply = format!("Tem que ser de 1 a {MAX_LOOP_SECS} segundos");
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+        },
+    };
+
+    let exists = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        find_sound(&sound_store, SoundKey::Guild(gid), Some(&label)).is_some()
+    };
+    let reply = if exists {
+        update_guild_config(ctx, gid, |guild_config| {
+            guild_config
+                .sounds
+                .entry(label.clone())
+                .or_default()
+                .loop_secs = secs;
+        })
+        .await;
+        match secs {
+            Some(secs) => Ok(format!(
+                "Blz, o áudio `{label}` agora fica repetindo por {secs}s!!"
+            )),
+            None => Ok(format!("Blz, o áudio `{label}` agora toca uma vez só!!")),
+        }
+    } else {
+        Err(format!("Não tem nenhum áudio `{label}`"))
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Makes the guild's sounds fade out over their last milliseconds, like
+/// `fade-out 800`, or end as they are with `off`.
+#[command("fade-out")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn fade_out(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let fade_out_ms = match args.single::<String>().ok().as_deref() {
+        Some("off") => None,
+        Some(ms) => match ms.trim_end_matches("ms").parse::<u64>() {
+            Ok(ms) if (1..=MAX_FADE_OUT_MS).contains(&ms) => Some(ms),
+            _ => {
+                let reply = format!("O fade-out tem que ser de 1 a {MAX_FADE_OUT_MS}ms");
+                refuse(ctx, msg, reply).await;
+                return Ok(());
+            }
+        },
+        None => {
+            refuse(
+                ctx,
+                msg,
+                "Tem que me dizer quantos ms, tipo `fade-out 800`, ou `off`",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    if let Some(reply) = fade_too_long(ctx, gid, fade_out_ms).await {
+        refuse(ctx, msg, reply).await;
+        return Ok(());
+    }
+
+    update_guild_config(ctx, gid, |guild_config| {
+        guild_config.fade_out_ms = fade_out_ms
+    })
+    .await;
+    redecode_guild_sounds(&ctx.data, gid).await;
+
+    let reply = match fade_out_ms {
+        Some(ms) => format!("Blz, os sons do server vão sumir aos poucos nos últimos {ms}ms!!"),
+        None => "Blz, os sons do server vão acabar sem fade-out!!".to_string(),
+    };
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Why a fade of `fade_out_ms` can't be used, if one of the guild's sounds is no
+/// longer than that: it would leave none of it at full volume.
+async fn fade_too_long(ctx: &Context, gid: GuildId, fade_out_ms: Option<u64>) -> Option<String> {
+    let ms = fade_out_ms?;
+    let shortest = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        sound_store
+            .iter()
+            .filter(|(key, _)| key_guild(**key) == Some(gid))
+            .flat_map(|(_, sounds)| sounds)
+            .filter_map(|sound| Some((sound.metadata.duration?, sound.label.clone())))
+            .min()
+    };
+    let (duration, label) = shortest.filter(|(d, _)| *d <= Duration::from_millis(ms))?;
+    Some(format!(
+        "O áudio `{label}` só tem {}ms, o fade-out tem que ser mais curto",
+        duration.as_millis()
+    ))
+}
+
+/// Switches the guild's `eq`, `fade-out` and `pan` to a saved [`AudioProfile`],
+/// like `profile retro`, re-decoding its sounds with it. `profile save <name>` keeps
+/// the current ones under a name, `profile remove <name>` forgets one, and
+/// `profile` alone lists them.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn profile(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let guild_config = guild_config(ctx, gid).await;
+    let first = args.single::<String>().ok();
+    let name = args
+        .single::<String>()
+        .ok()
+        .map(|name| sanitize_label(&name));
+    let reply = match (first.as_deref(), name) {
+        (None, _) => {
+            let current = AudioProfile::of(&guild_config);
+            let names = BUILTIN_PROFILES
+                .iter()
+                .map(|name| (name.to_string(), builtin_profile(name)))
+                .chain(
+                    guild_config
+                        .profiles
+                        .iter()
+                        .map(|(name, profile)| (name.clone(), Some(profile.clone()))),
+                )
+                .map(|(name, profile)| {
+                    if profile.as_ref() == Some(&current) {
+                        format!("**`{name}`** (atual)")
+                    } else {
+                        format!("`{name}`")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            Ok(format!("Perfis: {names}"))
+        }
+        (Some("save" | "remove"), None) => {
+            Err("Tem que me dizer o nome do perfil, tipo `profile save meu-perfil`".to_string())
+        }
+        (Some("save" | "remove"), Some(name)) if builtin_profile(&name).is_some() => Err(format!(
+            "O perfil `{name}` já vem com o bot, escolhe outro nome"
+        )),
+        (Some("save"), Some(name)) => {
+            if !guild_config.profiles.contains_key(&name)
+                && guild_config.profiles.len() >= MAX_PROFILES
+            {
+                Err(format!(
+                    "Calma lá, dá pra salvar no máximo {MAX_PROFILES} perfis"
+                ))
+            } else {
+                let profile = AudioProfile::of(&guild_config);
+                let reply = format!("Blz, salvei os ajustes de agora como `{name}`!!");
+                update_guild_config(ctx, gid, |guild_config| {
+                    guild_config.profiles.insert(name, profile);
+                })
+                .await;
+                Ok(reply)
+            }
+        }
+        (Some("remove"), Some(name)) => {
+            let mut removed = false;
+            update_guild_config(ctx, gid, |guild_config| {
+                removed = guild_config.profiles.remove(&name).is_some();
+            })
+            .await;
+            if removed {
+                Ok(format!("Blz, apaguei o perfil `{name}`!!"))
+            } else {
+                Err(format!("Não tem nenhum perfil `{name}`"))
+            }
+        }
+        (Some(name), _) => {
+            let name = sanitize_label(name);
+            let profile =
+                builtin_profile(&name).or_else(|| guild_config.profiles.get(&name).cloned());
+            match profile {
+                None => Err(format!("Não tem nenhum perfil `{name}`")),
+                Some(profile) => match fade_too_long(ctx, gid, profile.fade_out_ms).await {
+                    Some(reply) => Err(reply),
+                    None => {
+                        update_guild_config(ctx, gid, |guild_config| profile.apply(guild_config))
+                            .await;
+                        redecode_guild_sounds(&ctx.data, gid).await;
+                        Ok(format!(
+                            "Blz, os sons do server vão tocar com o perfil `{name}`!!"
+                        ))
+                    }
+                },
+            }
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Sets which replies the guild gets, see [`Verbosity`].
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn verbosity(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let verbosity = match args.single::<String>().ok().as_deref() {
+        Some("verbose") => Some(Verbosity::Verbose),
+        Some("quiet") => Some(Verbosity::Quiet),
+        Some("silent") => Some(Verbosity::Silent),
+        _ => None,
+    };
+    let verbosity = match verbosity {
+        Some(verbosity) => verbosity,
+        None => {
+            let usage = "Tem que me dizer `verbose` (respondo tudo), `quiet` (só quando dá \
+                errado) ou `silent` (só reajo)";
+            refuse(ctx, msg, usage).await;
+            return Ok(());
+        }
+    };
+    update_guild_config(ctx, gid, |guild_config| guild_config.verbosity = verbosity).await;
+
+    let reply = match verbosity {
+        Verbosity::Verbose => "Blz, vou responder tudo!!",
+        Verbosity::Quiet => "Blz, só respondo quando der errado!!",
+        Verbosity::Silent => "Blz, só vou reagir!!",
+    };
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn language(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let arg = args.single::<String>().ok();
+    let reply = match arg.as_deref() {
+        Some("off") => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.locale = None).await;
+            let locale = locale(ctx, Some(gid)).await;
+            Ok(format!(
+                "Blz, vou seguir a língua do server, que dá {locale}!!"
+            ))
+        }
+        Some(locale) => match locale.parse::<Locale>() {
+            Ok(locale) => {
+                update_guild_config(ctx, gid, |guild_config| guild_config.locale = Some(locale))
+                    .await;
+                Ok(format!("Blz, vou responder em {locale}!!"))
+            }
+            Err(()) => Err("Só sei `pt-BR` e `en`".to_string()),
+        },
+        None => Err("Tem que me dizer a língua, tipo `language en`, ou `off`".to_string()),
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Drops the decoded audio of `gid`'s own sounds, so they're decoded again with its
+/// current [`audio_filter`] next time they play. Sounds without a file on disk keep
+/// the audio they have, there'd be nothing to decode them from.
+async fn redecode_guild_sounds(data: &RwLock<TypeMap>, gid: GuildId) {
+    let (sound_store, filter) = {
+        let data = data.read().await;
+        let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+        let filter = audio_filter(SoundKey::Guild(gid), guild_configs);
+        let sound_store = data.get::<SoundStore>().expect("sound store is set");
+        (sound_store.clone(), filter)
+    };
+
+    let mut sound_store = sound_store.write().await;
+    let keys = sound_store
+        .iter_mut()
+        .filter(|(key, _)| key_guild(**key) == Some(gid));
+    for (key, sounds) in keys {
+        for sound in sounds.iter_mut().filter(|sound| sound.filter != filter) {
+            if sound.source.is_some() {
+                sound.memory = None;
+                sound.filter = filter.clone();
+            } else {
+                warn!(
+                    "Can't change the filter of {key}'s sound {}, it has no file",
+                    sound.label
+                );
+            }
+        }
+    }
+}
+
+#[command("reset-seen")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn reset_seen(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    update_guild_config(ctx, gid, |guild_config| guild_config.seen.clear()).await;
+
+    confirm(
+        ctx,
+        msg,
+        "Blz, esqueci todo mundo, vou dar boas-vindas de novo!!",
+    )
+    .await;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let mode = match args.single::<String>().ok().as_deref() {
+        Some("room" | "sala") => Some(PlayMode::Room),
+        Some("joiner" | "pessoa") => Some(PlayMode::Joiner),
+        _ => None,
+    };
+
+    let reply = match mode {
+        Some(mode) => {
+            update_guild_config(ctx, gid, |guild_config| guild_config.mode = mode).await;
+            match mode {
+                PlayMode::Room => Ok("Blz, vou tocar o áudio do servidor pra todo mundo!!"),
+                PlayMode::Joiner => Ok("Blz, vou tocar o áudio de cada um quando entrar!!"),
+            }
+        }
+        None => {
+            Err("Tem que me dizer `room` (áudio do servidor) ou `joiner` (áudio de quem entrou)")
+        }
+    };
+
+    answer(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+fn parse_toggle(arg: &str) -> Option<bool> {
+    match arg.to_lowercase().as_str() {
+        "on" | "sim" | "liga" => Some(true),
+        "off" | "não" | "nao" | "desliga" => Some(false),
+        _ => None,
+    }
+}
+
+/// How many of the most shared sounds `duplicates` lists.
+const DUPLICATES_SHOWN: usize = 10;
+
+#[command]
+async fn duplicates(ctx: &Context, msg: &Message) -> CommandResult {
+    let mut shared = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+
+        let mut guilds_by_hash = HashMap::<ContentHash, HashSet<SoundKey>>::new();
+        for (key, sounds) in sound_store.iter() {
+            for sound in sounds {
+                guilds_by_hash.entry(sound.hash).or_default().insert(*key);
+            }
+        }
+        guilds_by_hash
+            .into_iter()
+            .map(|(hash, keys)| (hash, keys.len()))
+            .filter(|&(_, guilds)| guilds > 1)
+            .collect::<Vec<_>>()
+    };
+    shared.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let reply = if shared.is_empty() {
+        "Nenhum áudio repetido entre servidores".to_string()
+    } else {
+        let mut reply = format!("{} áudios usados em mais de um servidor:", shared.len());
+        for (hash, guilds) in shared.iter().take(DUPLICATES_SHOWN) {
+            reply.push_str(&format!("\n`{}`: {guilds} servidores", &hex(hash)[..16]));
+        }
+        if shared.len() > DUPLICATES_SHOWN {
+            reply.push_str(&format!("\n... e mais {}", shared.len() - DUPLICATES_SHOWN));
+        }
+        reply
+    };
+
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command]
+async fn mute(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let reply = match args
+        .single::<String>()
+        .ok()
+        .as_deref()
+        .and_then(parse_toggle)
+    {
+        Some(muted) => {
+            let playing: Vec<GuildId> = {
+                let data = ctx.data.read().await;
+                data.get::<Muted>()
+                    .expect("muted is set")
+                    .store(muted, Ordering::Relaxed);
+                let tracks = data.get::<Tracks>().expect("tracks are set");
+                tracks.keys().copied().collect()
+            };
+            if muted {
+                warn!("Playback muted in every guild by {}", msg.author.id);
+                // what's already playing goes quiet too, not only what comes next
+                for gid in playing {
+                    stop_playback(ctx, gid).await;
+                }
+                "Blz, não toco mais nada em lugar nenhum!!"
+            } else {
+                warn!("Playback unmuted by {}", msg.author.id);
+                "Blz, voltei a tocar!!"
+            }
+        }
+        None => "Tem que me dizer `on` ou `off`",
+    };
+
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Stops whatever `gid` is playing, queued sounds included. The bot then leaves, or
+/// stays, as if they had ended on their own.
+async fn stop_playback(ctx: &Context, gid: GuildId) {
+    let last_track = {
+        let data = ctx.data.read().await;
+        let tracks = data.get::<Tracks>().expect("tracks are set");
+        tracks.get(&gid).cloned()
+    };
+    if let Some(track) = last_track {
+        // already ended or stopped otherwise
+        let _ = track.stop();
+    }
+    let call = match songbird::get(ctx).await {
+        Some(manager) => manager.get(gid),
+        None => None,
+    };
+    if let Some(call) = call {
+        let mut call = call.lock().await;
+        call.queue().stop();
+        call.stop();
+    }
+}
+
+/// Drains like SIGTERM does, then shuts down.
+#[command("drain")]
+async fn start_drain(ctx: &Context, msg: &Message) -> CommandResult {
+    if is_draining(ctx).await {
+        respond(ctx, msg, "Já to desligando").await;
+        return Ok(());
+    }
+    warn!("Drain started by {}", msg.author.id);
+
+    let data = ctx.data.clone();
+    let shard_manager = {
+        let data = data.read().await;
+        data.get::<Shards>().expect("shards are set").clone()
+    };
+    tokio::spawn(async move {
+        drain(&data).await;
+        flush(&data).await;
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
+    respond(
+        ctx,
+        msg,
+        "Blz, termino o que tá tocando e desligo, não pego áudio novo!!",
+    )
+    .await;
+
+    Ok(())
+}
+
+#[command]
+async fn stats(ctx: &Context, msg: &Message) -> CommandResult {
+    let reply = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        let sounds = sound_store.values().flatten().count();
+        let loaded = sound_store
+            .values()
+            .flatten()
+            .filter(|s| s.memory.is_some())
+            .count();
+        let guilds = sound_store
+            .keys()
+            .filter(|key| matches!(key, SoundKey::Guild(_)))
+            .count();
+        let muted = is_muted(ctx).await;
+        let prefixes = {
+            let data = ctx.data.read().await;
+            let config = data.get::<Config>().expect("config is set");
+            config
+                .prefixes
+                .iter()
+                .map(|prefix| format!("`{prefix}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        format!(
+            "Servidores com áudio: {guilds}\nÁudios: {sounds} ({loaded} na memória)\nMudo: {}\nPrefixos: {prefixes} ou me marcando",
+            if muted { "sim" } else { "não" },
+        )
+    };
+
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Past this much decoded audio in memory `memory` suggests ways to use less.
+const MEMORY_WARNING_BYTES: f64 = 512.0 * 1024.0 * 1024.0;
+
+/// Rough memory taken by `audio`, from its duration. Audio shared by several sounds
+/// counts for each of them.
+fn approx_memory(audio: &CachedAudio) -> f64 {
+    let secs = audio
+        .metadata()
+        .duration
+        .map_or(0.0, |duration| duration.as_secs_f64());
+    match audio {
+        CachedAudio::Raw(_) => secs * DECODED_BYTES_PER_SEC,
+        // about a tenth, see `CacheFormat::Opus`
+        CachedAudio::Opus(_) => secs * DECODED_BYTES_PER_SEC / 10.0,
+    }
+}
+
+/// Replies how much memory the decoded sounds take and the settings that bound it,
+/// with a hint when it's getting a lot.
+#[command]
+async fn memory(ctx: &Context, msg: &Message) -> CommandResult {
+    let (cache_format, cache_ttl, max_decoded_bytes) = {
+        let data = ctx.data.read().await;
+        let config = data.get::<Config>().expect("config is set");
+        (
+            config.cache_format,
+            config.cache_ttl,
+            config.max_decoded_bytes,
+        )
+    };
+    let (sounds, loaded, used, freeable) = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        // guilds that uploaded the same file share its decoded audio
+        let mut seen = HashSet::new();
+        let (mut loaded, mut used, mut freeable) = (0, 0.0, 0.0);
+        for sound in sound_store.values().flatten() {
+            let audio = match &sound.memory {
+                Some(audio) => audio,
+                None => continue,
+            };
+            loaded += 1;
+            if seen.insert((sound.hash, sound.filter.as_deref())) {
+                used += approx_memory(audio);
+            }
+            if sound.source.is_some() {
+                freeable += approx_memory(audio);
+            }
+        }
+        let sounds = sound_store.values().map(Vec::len).sum::<usize>();
+        (sounds, loaded, used, freeable.min(used))
+    };
+
+    let mb = |bytes: f64| bytes / (1024.0 * 1024.0);
+    let mut reply = format!(
+        "Memória: ~{:.1} MB, {loaded} de {sounds} áudios decodificados
+Liberável com `purge-cache`: ~{:.1} MB
+Formato: {}
+Limite por áudio: {} MB
+Cache TTL: {}",
+        mb(used),
+        mb(freeable),
+        match cache_format {
+            CacheFormat::Raw => "raw",
+            CacheFormat::Opus => "opus",
+        },
+        max_decoded_bytes / (1024 * 1024),
+        match cache_ttl {
+            Some(ttl) => format!("{}s", ttl.as_secs()),
+            None => "nenhum, fica tudo na memória".to_string(),
+        },
+    );
+    if used >= MEMORY_WARNING_BYTES {
+        if cache_format == CacheFormat::Raw {
+            reply.push_str("\nDica: `CACHE_FORMAT=opus` usa uns 10% da memória");
+        }
+        if cache_ttl.is_none() && freeable > 0.0 {
+            reply.push_str("\nDica: `CACHE_TTL_SECS` tira da memória o que não toca faz tempo");
+        }
+    }
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Checks the usual reasons a deployment can't play anything and replies with what
+/// it found, so bug reports come with it.
+#[command]
+async fn diagnose(ctx: &Context, msg: &Message) -> CommandResult {
+    let (ffmpeg_path, data_dir) = {
+        let data = ctx.data.read().await;
+        let config = data.get::<Config>().expect("config is set");
+        (config.ffmpeg_path.clone(), config.data_dir.clone())
+    };
+    let check = |ok: bool| if ok { '✅' } else { '❌' };
+
+    let ffmpeg = match find_executable(&ffmpeg_path) {
+        Some(path) => match ffmpeg_version(&path).await {
+            Ok(version) => format!("{} ffmpeg: {version} ({path:?})", check(true)),
+            Err(e) => format!("{} ffmpeg: {path:?} não roda: {e}", check(false)),
+        },
+        None => format!("{} ffmpeg: {ffmpeg_path:?} não encontrado", check(false)),
+    };
+    let songbird = format!(
+        "{} songbird registrado",
+        check(songbird::get(ctx).await.is_some())
+    );
+    let mut writable = Vec::new();
+    for (name, dir) in [("temp", env::temp_dir()), ("data", data_dir)] {
+        writable.push(match can_write(&dir).await {
+            Ok(()) => format!("{} escrita em {name} ({dir:?})", check(true)),
+            Err(e) => format!("{} escrita em {name} ({dir:?}): {e}", check(false)),
+        });
+    }
+    let cache = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        let loaded: Vec<&Sound> = sound_store
+            .values()
+            .flatten()
+            .filter(|sound| sound.memory.is_some())
+            .collect();
+        let secs: f64 = loaded
+            .iter()
+            .filter_map(|sound| sound.metadata.duration)
+            .map(|duration| duration.as_secs_f64())
+            .sum();
+        let mb = secs * DECODED_BYTES_PER_SEC / (1024.0 * 1024.0);
+        format!("Cache: {} áudios na memória (~{mb:.1} MB)", loaded.len())
+    };
+
+    let reply = format!("{ffmpeg}\n{songbird}\n{}\n{cache}", writable.join("\n"));
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// First line of `ffmpeg -version`, like `ffmpeg version 6.1.1 Copyright ...`.
+async fn ffmpeg_version(ffmpeg_path: &Path) -> io::Result<String> {
+    let out = tokio::process::Command::new(ffmpeg_path)
+        .arg("-version")
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !out.status.success() {
+        return Err(io::Error::other(format!("saiu com {}", out.status)));
+    }
+    let version = String::from_utf8_lossy(&out.stdout);
+    let line = version.lines().next().unwrap_or_default();
+    Ok(line.split(" Copyright").next().unwrap_or(line).to_string())
+}
+
+/// Creates and deletes a file in `dir`.
+async fn can_write(dir: &Path) -> io::Result<()> {
+    let path = dir.join(format!(".diagnose-{}", std::process::id()));
+    tokio::fs::write(&path, b"").await?;
+    tokio::fs::remove_file(&path).await
+}
+
+/// Short 440Hz beep, 48kHz mono 16 bit wav.
+const TEST_TONE: &[u8] = include_bytes!("../assets/test-tone.wav");
+
+/// Decodes `TEST_TONE` (and the pre-roll, if any) the same way uploads are, to check
+/// the host can actually decode sounds before going live. Doesn't touch Discord.
+async fn selftest(config: &Config) -> bool {
+    let path = temp_path(SoundKey::Guild(GuildId(0)), "selftest.wav");
+    if let Err(e) = tokio::fs::write(&path, TEST_TONE).await {
+        error!("Self-test failed writing {path:?}: {e}");
+        return false;
+    }
+
+    let mut ok = true;
+    let clips = std::iter::once(path.clone()).chain(config.preroll.clone());
+    for clip in clips {
+        match tokio::time::timeout(config.audio_timeout, decode(&clip, None, None, config)).await {
+            Ok(Ok(_)) => info!("Self-test decoded {clip:?}"),
+            Ok(Err(e)) => {
+                error!("Self-test failed decoding {clip:?}: {e}");
+                ok = false;
+            }
+            Err(_) => {
+                error!("Self-test timed out decoding {clip:?}");
+                ok = false;
+            }
+        }
+    }
+
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        warn!("Error deleting {path:?}: {e}");
+    }
+    ok
+}
+
+/// Length of the canonical wav header in front of `TEST_TONE`'s samples.
+const WAV_HEADER_LEN: usize = 44;
+
+/// Plays `TEST_TONE` in the invoker's channel, to check the bot can be heard at all
+/// without any upload or ffmpeg involved.
+#[command("echo-test")]
+#[only_in(guilds)]
+async fn echo_test(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let channel_id = if let Some(channel_id) = voice_channel_of(ctx, gid, msg.author.id).await {
+        channel_id
+    } else {
+        respond(ctx, msg, "Entra num canal de voz primeiro!!").await;
+        return Ok(());
+    };
+
+    if let Some(tone) = test_tone() {
+        play(ctx, gid, channel_id, &tone, 1.0).await;
+    }
+
+    Ok(())
+}
+
+fn test_tone() -> Option<CachedAudio> {
+    let input = Input::new(
+        false,
+        Reader::from_memory(TEST_TONE[WAV_HEADER_LEN..].to_vec()),
+        Codec::Pcm,
+        Container::Raw,
+        None,
+    );
+    match Memory::new(input) {
+        Ok(tone) => Some(CachedAudio::Raw(tone)),
+        Err(e) => {
+            error!("Error loading the test tone: {e}");
+            None
+        }
+    }
+}
+
+/// Longest `latency` waits for the test tone to start.
+const LATENCY_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Plays `TEST_TONE` in the invoker's channel like `echo-test`, replying how long it
+/// took to connect and then to start playing, for sounds that play late.
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    let channel_id = if let Some(channel_id) = voice_channel_of(ctx, gid, msg.author.id).await {
+        channel_id
+    } else {
+        refuse(ctx, msg, "Entra num canal de voz primeiro!!").await;
+        return Ok(());
+    };
+    if is_muted(ctx).await {
+        refuse(ctx, msg, "Tô no mudo agora").await;
+        return Ok(());
+    }
+    let input = match test_tone().map(|tone| tone.input()) {
+        Some(Ok(input)) => input,
+        Some(Err(e)) => {
+            error!("Error building the test tone's input for guild {gid}: {e}");
+            refuse(ctx, msg, "Deu pau").await;
+            return Ok(());
+        }
+        None => {
+            refuse(ctx, msg, "Deu pau").await;
+            return Ok(());
+        }
+    };
+
+    if let Some(held) = held_in(ctx, gid).await.filter(|&held| held != channel_id) {
+        let reply = format!("To preso no {}, manda `release` antes", held.mention());
+        refuse(ctx, msg, reply).await;
+        return Ok(());
+    }
+
+    // already being there leaves only the time to start playing to measure
+    let connected = connected_to(ctx, gid, channel_id).await;
+    let started = Instant::now();
+    let call = if let Some(call) = join(ctx, gid, channel_id).await {
+        call
+    } else {
+        refuse(ctx, msg, "Não consegui entrar no canal").await;
+        return Ok(());
+    };
+    let joined = started.elapsed();
+
+    let (track, handle) = songbird::create_player(input);
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let playing = Notify(Mutex::new(Some(tx)));
+    let disconnect = disconnect(ctx, gid, &call).await;
+    let registered = handle
+        .add_event(FIRST_FRAME, playing)
+        .and_then(|()| handle.add_event(Event::Track(TrackEvent::End), disconnect));
+    if let Err(e) = registered {
+        error!("Error registering the latency events for guild {gid}: {e}");
+        leave(&call).await;
+        refuse(ctx, msg, "Deu pau").await;
+        return Ok(());
+    }
+    {
+        let mut call = call.lock().await;
+        call.queue().stop();
+        call.play_only(track);
+    }
+
+    let first_audio = match tokio::time::timeout(LATENCY_TIMEOUT, rx).await {
+        Ok(Ok(playing_at)) => playing_at.saturating_duration_since(started),
+        _ => {
+            warn!(?joined, "Test tone didn't start playing in guild {gid}");
+            let reply = format!("Conectei em {joined:.2?}, mas o áudio não começou a tocar");
+            refuse(ctx, msg, reply).await;
+            return Ok(());
+        }
+    };
+    info!(guild = %gid, channel = %channel_id, ?joined, ?first_audio, connected, "Measured latency");
+
+    let joined = if connected {
+        "já tava no canal".to_string()
+    } else {
+        format!("conectei em {joined:.2?}")
+    };
+    let reply = format!("Blz, {joined} e o áudio começou em {first_audio:.2?}!!");
+    confirm(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Tells when the event it was added for first fired, like the mixer playing a
+/// track's first frame or the track ending.
+struct Notify(Mutex<Option<tokio::sync::oneshot::Sender<Instant>>>);
+
+#[async_trait]
+impl VoiceEventHandler for Notify {
+    async fn act(&self, _: &EventContext<'_>) -> Option<Event> {
+        if let Some(tx) = self.0.lock().await.take() {
+            let _ = tx.send(Instant::now());
+        }
+        Some(Event::Cancel)
+    }
+}
+
+/// Plays the guild's sound in every voice channel with somebody in it. A bot has a
+/// single voice connection per guild, so it goes through them one at a time, each
+/// sound ending before it moves to the next channel.
+#[command]
+#[only_in(guilds)]
+async fn broadcast(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = if let Some(gid) = msg.guild_id {
+        gid
+    } else {
+        return Ok(());
+    };
+
+    if let Some(held) = held_in(ctx, gid).await {
+        let reply = format!("To preso no {}, manda `release` antes", held.mention());
+        respond(ctx, msg, reply).await;
+        return Ok(());
+    }
+    let (volume, sound) = match pick_labeled_sound(ctx, SoundKey::Guild(gid), None, None).await {
+        Some((label, sound)) => (
+            playback_volume(&guild_config(ctx, gid).await, Some(&label)),
+            sound,
+        ),
+        None => {
+            respond(ctx, msg, "Não tem nenhum áudio configurado!!").await;
+            return Ok(());
+        }
+    };
+
+    let bot_id = ctx.cache.current_user_id().await;
+    let guild_config = guild_config(ctx, gid).await;
+    let afk = afk_channel(ctx, gid).await;
+    let channels: BTreeSet<ChannelId> = ctx
+        .cache
+        .guild_field(gid, |guild| {
+            guild
+                .voice_states
+                .values()
+                .filter(|vs| vs.user_id != bot_id)
+                .filter(|vs| !guild.members.get(&vs.user_id).is_some_and(|m| m.user.bot))
+                .filter_map(|vs| vs.channel_id)
+                .collect()
+        })
+        .await
+        .unwrap_or_default();
+    let channels: Vec<ChannelId> = channels
+        .into_iter()
+        .filter(|channel_id| !guild_config.blocked_channels.contains(channel_id))
+        .filter(|channel_id| guild_config.play_in_afk || afk != Some(*channel_id))
+        .collect();
+    if channels.is_empty() {
+        respond(ctx, msg, "Não tem ninguém em canal de voz nenhum").await;
+        return Ok(());
+    }
+
+    respond(
+        ctx,
+        msg,
+        format!(
+            "Blz, vou tocar em {} canais, um de cada vez!!",
+            channels.len()
+        ),
+    )
+    .await;
+    info!("Broadcasting in {} channels of guild {gid}", channels.len());
+
+    // held so the end of each sound doesn't leave the channel the next one joins
+    let started = Instant::now();
+    for channel_id in channels {
+        set_held(ctx, gid, Some(channel_id)).await;
+        play(ctx, gid, channel_id, &sound, volume).await;
+        wait_for_track(ctx, gid).await;
+    }
+    set_held(ctx, gid, None).await;
+    if let Some(call) = songbird::get(ctx)
+        .await
+        .and_then(|manager| manager.get(gid))
+    {
+        leave(&call).await;
+    }
+    info!("Broadcast in guild {gid} done in {:?}", started.elapsed());
+
+    Ok(())
+}
+
+/// Waits until the last track played in `gid` ends. It's paused while a preroll
+/// plays first, so this waits on its end event rather than its play mode.
+async fn wait_for_track(ctx: &Context, gid: GuildId) {
+    let track = {
+        let data = ctx.data.read().await;
+        let tracks = data.get::<Tracks>().expect("tracks are set");
+        tracks.get(&gid).cloned()
+    };
+    let track = match track {
+        Some(track) => track,
+        None => return,
+    };
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let ended = Notify(Mutex::new(Some(tx)));
+    // fails once the track is gone already, and the sender is dropped along with
+    // the driver if the call goes away, either way there's nothing to wait for
+    if track
+        .add_event(Event::Track(TrackEvent::End), ended)
+        .is_ok()
+    {
+        let _ = rx.await;
+    }
+}
+
+/// Rough size of decoded audio: 48kHz stereo f32.
+const DECODED_BYTES_PER_SEC: f64 = 48_000.0 * 2.0 * 4.0;
+
+/// Same as the admin API's `POST /reload`.
+#[command]
+async fn reload(ctx: &Context, msg: &Message) -> CommandResult {
+    let changed = reload_guild_configs(&ctx.data).await;
+    let reply = if changed.is_empty() {
+        "Recarreguei as configs, nada mudou".to_string()
+    } else {
+        let guilds: Vec<String> = changed
+            .iter()
+            .map(|(gid, settings)| format!("`{gid}`: {}", settings.join(", ")))
+            .collect();
+        format!("Recarreguei as configs, mudou:\n{}", guilds.join("\n"))
+    };
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Most of each kind of problem `check-store` lists, the rest are only counted.
+const CHECK_STORE_LISTED: usize = 5;
+
+/// What `check-store` found out of place, each as the key and label of the sound.
+#[derive(Default)]
+struct StoreCheck {
+    /// Persisted files looked at.
+    checked: usize,
+    /// Files that aren't audio, moved to [`QUARANTINE_DIR`] and their sound removed.
+    unreadable: Vec<(SoundKey, String)>,
+    /// Files that couldn't be checked, with why. ffmpeg missing or timing out says
+    /// nothing about the file, so they're left alone.
+    failed: Vec<(SoundKey, String, String)>,
+    /// Files that decode but whose sound isn't in memory, or is there from some
+    /// other file, loaded again.
+    unloaded: Vec<(SoundKey, String)>,
+    /// Sounds in memory whose file is gone, dropped since they wouldn't be back
+    /// after a restart anyway.
+    missing: Vec<(SoundKey, String)>,
+    /// Guild sound settings with no sound left, removed.
+    orphaned: Vec<(SoundKey, String)>,
+}
+
+/// Probes every persisted sound and compares them with the [`SoundStore`] and the
+/// guilds' [`SoundConfig`]s, repairing what's off when `fix` is set. Only the files
+/// that need loading again are decoded, and only when fixing.
+async fn check_store(ctx: &Context, fix: bool) -> io::Result<StoreCheck> {
+    let config = Arc::clone(
+        ctx.data
+            .read()
+            .await
+            .get::<Config>()
+            .expect("config is set"),
+    );
+    let sound_store = sound_store(ctx).await;
+    let mut check = StoreCheck::default();
+    // the sounds that would be left after a fix
+    let mut kept = HashSet::new();
+
+    for (key, label, path) in persisted_files(&config).await? {
+        check.checked += 1;
+        let content = match read_sound_file(&path).await {
+            Ok(content) => content,
+            Err(e) => {
+                kept.insert((key, label.clone()));
+                check.failed.push((key, label, e.to_string()));
+                continue;
+            }
+        };
+        // only what's stale gets decoded, the rest just has to be audio
+        let format = match probe_persisted(key, &path, &content, &config).await {
+            Ok(_) => None,
+            Err(InputError::Streams) => Some(AudioError::Format),
+            Err(e) => {
+                kept.insert((key, label.clone()));
+                check.failed.push((key, label, e.to_string()));
+                continue;
+            }
+        };
+        let hash = content_hash(&content);
+        drop(content);
+
+        let stale = format.is_none() && {
+            let sound_store = sound_store.read().await;
+            find_sound(&sound_store, key, Some(&label))
+                .is_none_or(|loaded| loaded.hash != hash || loaded.source.as_ref() != Some(&path))
+        };
+        if !fix {
+            match format {
+                Some(_) => check.unreadable.push((key, label)),
+                None => {
+                    kept.insert((key, label.clone()));
+                    if stale {
+                        check.unloaded.push((key, label));
+                    }
+                }
+            }
+            continue;
+        }
+        if format.is_none() && !stale {
+            kept.insert((key, label));
+            continue;
+        }
+
+        // a set or remove of the same sound in the meantime would be undone
+        let _lock = lock_sounds(ctx, key).await;
+        let decoded = match format {
+            Some(e) => Err(e),
+            None => decode_persisted(key, &path, key_filter(ctx, key).await, &config).await,
+        };
+        match decoded {
+            Ok(decoded) => {
+                let mut sound = Sound::new(label.clone(), decoded, hash, Some(path.clone()));
+                if let Ok(set_at) = tokio::fs::metadata(&path)
+                    .await
+                    .and_then(|meta| meta.modified())
+                {
+                    sound.set_at = set_at;
+                }
+                insert_sound(&mut *sound_store.write().await, key, sound);
+                kept.insert((key, label.clone()));
+                check.unloaded.push((key, label));
+            }
+            Err(AudioError::Format) => {
+                let dir = sounds_dir(&config.data_dir, key).join(&label);
+                let quarantined = if path.parent() == Some(dir.as_path()) {
+                    // the object store's copy would come back on the next start
+                    match quarantine(&config, &dir).await {
+                        Ok(to) => remove_persisted(&config, &dir).await.map(|()| to),
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    quarantine(&config, &path).await
+                };
+                match quarantined {
+                    Ok(to) => info!("Moved the unreadable sound file {path:?} to {to:?}"),
+                    Err(e) => warn!("Error moving the unreadable sound file {path:?}: {e}"),
+                }
+                let mut sound_store = sound_store.write().await;
+                if let Some(sounds) = sound_store.get_mut(&key) {
+                    sounds.retain(|sound| sound.label != label);
+                }
+                check.unreadable.push((key, label));
+            }
+            Err(e) => {
+                // still on disk, so it isn't missing either
+                kept.insert((key, label.clone()));
+                check.failed.push((key, label, e.to_string()));
+            }
+        }
+    }
+
+    let loaded: Vec<(SoundKey, String, Option<PathBuf>)> = {
+        let sound_store = sound_store.read().await;
+        sound_store
+            .iter()
+            .flat_map(|(key, sounds)| {
+                sounds
+                    .iter()
+                    .map(move |sound| (*key, sound.label.clone(), sound.source.clone()))
+            })
+            .collect()
+    };
+    let sounds_root = config.data_dir.join(SOUNDS_DIR);
+    for (key, label, source) in loaded {
+        let entry = (key, label);
+        if kept.contains(&entry) || check.unreadable.contains(&entry) {
+            continue;
+        }
+        // the rest came from somewhere else, like the manifest, or never made it to disk
+        let persisted = source.filter(|source| source.starts_with(&sounds_root));
+        match persisted {
+            Some(source) if tokio::fs::metadata(&source).await.is_err() => {
+                if fix {
+                    let _lock = lock_sounds(ctx, key).await;
+                    let mut sound_store = sound_store.write().await;
+                    if let Some(sounds) = sound_store.get_mut(&key) {
+                        sounds.retain(|sound| sound.source.as_ref() != Some(&source));
+                    }
+                }
+                check.missing.push(entry);
+            }
+            _ => {
+                kept.insert(entry);
+            }
+        }
+    }
+    if fix {
+        sound_store
+            .write()
+            .await
+            .retain(|_, sounds| !sounds.is_empty());
+    }
+
+    let orphaned: Vec<(GuildId, String)> = {
+        let data = ctx.data.read().await;
+        let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+        guild_configs
+            .iter()
+            .flat_map(|(gid, guild_config)| {
+                guild_config
+                    .sounds
+                    .keys()
+                    .map(move |label| (*gid, label.clone()))
+            })
+            .filter(|(gid, label)| !kept.contains(&(SoundKey::Guild(*gid), label.clone())))
+            .collect()
+    };
+    for (gid, label) in orphaned {
+        if fix {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.sounds.remove(&label);
+            })
+            .await;
+        }
+        check.orphaned.push((SoundKey::Guild(gid), label));
+    }
+
+    info!(
+        fix,
+        checked = check.checked,
+        unreadable = check.unreadable.len(),
+        failed = check.failed.len(),
+        unloaded = check.unloaded.len(),
+        missing = check.missing.len(),
+        orphaned = check.orphaned.len(),
+        "Checked the persisted sounds"
+    );
+    Ok(check)
+}
+
+/// Moves the persisted sound file or directory at `path` under [`QUARANTINE_DIR`],
+/// stamped with the time so an earlier one of the same name is kept.
+async fn quarantine(config: &Config, path: &Path) -> io::Result<PathBuf> {
+    let relative = path
+        .strip_prefix(config.data_dir.join(SOUNDS_DIR))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "not a persisted sound"))?;
+    let name = relative.file_name().unwrap_or_default().to_string_lossy();
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let to = config
+        .data_dir
+        .join(QUARANTINE_DIR)
+        .join(relative)
+        .with_file_name(format!("{name}.{secs}"));
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::rename(path, &to).await?;
+    Ok(to)
+}
+
+/// Checks the persisted sounds against what's loaded, see [`check_store`]. Only
+/// reports what's off unless given `--fix`.
+#[command("check-store")]
+async fn check_store_command(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let fix = args.rest().trim() == "--fix";
+    let check = match check_store(ctx, fix).await {
+        Ok(check) => check,
+        Err(e) => {
+            warn!("Error reading persisted sounds: {e}");
+            refuse(ctx, msg, "Não consegui ler os áudios salvos, olha os logs").await;
+            return Ok(());
+        }
+    };
+
+    let mut reply = format!("Conferi {} arquivos de áudio", check.checked);
+    let problems = [
+        ("arquivos que não abrem", &check.unreadable),
+        ("arquivos que não tavam carregados", &check.unloaded),
+        ("áudios na memória sem arquivo", &check.missing),
+        ("configs de áudios que não existem", &check.orphaned),
+    ];
+    for (what, sounds) in problems.iter().filter(|(_, sounds)| !sounds.is_empty()) {
+        let listed: Vec<String> = sounds
+            .iter()
+            .take(CHECK_STORE_LISTED)
+            .map(|(key, label)| format!("`{key}/{label}`"))
+            .collect();
+        reply += &format!("\n{} {what}: {}", sounds.len(), listed.join(", "));
+        if sounds.len() > CHECK_STORE_LISTED {
+            reply += &format!(" e mais {}", sounds.len() - CHECK_STORE_LISTED);
+        }
+    }
+    if !check.failed.is_empty() {
+        let listed: Vec<String> = check
+            .failed
+            .iter()
+            .take(CHECK_STORE_LISTED)
+            .map(|(key, label, e)| format!("`{key}/{label}` ({e})"))
+            .collect();
+        reply += &format!(
+            "\n{} arquivos que não consegui conferir, deixei como tão: {}",
+            check.failed.len(),
+            listed.join(", ")
+        );
+        if check.failed.len() > CHECK_STORE_LISTED {
+            reply += &format!(" e mais {}", check.failed.len() - CHECK_STORE_LISTED);
+        }
+    }
+    let clean = problems.iter().all(|(_, sounds)| sounds.is_empty());
+    if clean && check.failed.is_empty() {
+        reply += ", tá tudo certo!!";
+    } else if !clean && fix {
+        reply += if check.failed.is_empty() {
+            "\nConsertei tudo!!"
+        } else {
+            "\nConsertei o resto!!"
+        };
+    } else if !clean {
+        reply += "\nManda `check-store --fix` pra consertar";
+    }
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Writes the PCM cache now rather than on shutdown, for restarts that won't shut
+/// down cleanly.
+#[command("save-cache")]
+async fn save_cache(ctx: &Context, msg: &Message) -> CommandResult {
+    let path = {
+        let data = ctx.data.read().await;
+        data.get::<Config>()
+            .expect("config is set")
+            .pcm_cache
+            .clone()
+    };
+    let reply = match path {
+        Some(path) => match save_pcm_cache(&ctx.data, &path).await {
+            Ok(sounds) => format!("Blz, salvei {sounds} áudios no cache!!"),
+            Err(e) => format!("Deu pau salvando o cache: {e}"),
+        },
+        None => "Não tem `PCM_CACHE_PATH` configurado".to_string(),
+    };
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+/// Replaces every guild's config with the one in the latest snapshot, for when the
+/// configs in the data dir went bad while running.
+#[command("restore")]
+async fn restore_snapshot(ctx: &Context, msg: &Message) -> CommandResult {
+    let (path, data_dir) = {
+        let data = ctx.data.read().await;
+        let config = data.get::<Config>().expect("config is set");
+        (config.backup_path.clone(), config.data_dir.clone())
+    };
+    let path = if let Some(path) = path {
+        path
+    } else {
+        respond(ctx, msg, "Não tem `BACKUP_PATH` configurado").await;
+        return Ok(());
+    };
+    let snapshot = match read_snapshot(&path).await {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            error!("Error reading the snapshot {path:?}: {e}");
+            respond(ctx, msg, format!("Não consegui ler o snapshot: {e}")).await;
+            return Ok(());
+        }
+    };
+
+    let mut failed = 0;
+    for (gid, guild_config) in &snapshot.guild_configs {
+        if let Err(e) = save_guild_config(&data_dir, *gid, guild_config).await {
+            warn!("Error saving the restored config of guild {gid}: {e}");
+            failed += 1;
+        }
+    }
+    let changed = reload_guild_configs(&ctx.data).await;
+    info!(
+        guilds = snapshot.guild_configs.len(),
+        changed = changed.len(),
+        failed,
+        taken_at = snapshot.taken_at,
+        "Restored guild configs from {path:?}"
+    );
+
+    let mut reply = format!(
+        "Restaurei as configs de {} servers, {} mudaram",
+        snapshot.guild_configs.len(),
+        changed.len()
+    );
+    if failed > 0 {
+        reply.push_str(&format!(", {failed} não deu pra salvar"));
+    }
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+#[command("purge-cache")]
+async fn purge_cache(ctx: &Context, msg: &Message) -> CommandResult {
+    let (before, after, freed_secs) = {
+        let sound_store = sound_store(ctx).await;
+        let mut sound_store = sound_store.write().await;
+        let loaded = |sound_store: &HashMap<SoundKey, Vec<Sound>>| {
+            sound_store
+                .values()
+                .flatten()
+                .filter(|s| s.memory.is_some())
+                .count()
+        };
+        let before = loaded(&sound_store);
+
+        // sounds without a file to reload from have to stay
+        let freed_secs: f64 = sound_store
+            .values_mut()
+            .flatten()
+            .filter(|sound| sound.source.is_some())
+            .filter_map(|sound| sound.memory.take().and(sound.metadata.duration))
+            .map(|duration| duration.as_secs_f64())
+            .sum();
+
+        let after = loaded(&sound_store);
+        (before, after, freed_secs)
+    };
+
+    let freed_mb = freed_secs * DECODED_BYTES_PER_SEC / (1024.0 * 1024.0);
+    info!("Purged the sound cache, {before} -> {after} sounds in memory, ~{freed_mb:.1} MB freed");
+
+    let reply = format!("Áudios na memória: {before} → {after} (~{freed_mb:.1} MB liberados)");
+    respond(ctx, msg, reply).await;
+
+    Ok(())
+}
+
+async fn voice_channel_of(ctx: &Context, gid: GuildId, uid: UserId) -> Option<ChannelId> {
+    ctx.cache
+        .guild_field(gid, |guild| {
+            guild
+                .voice_states
+                .get(&uid)
+                .and_then(|voice_state| voice_state.channel_id)
+        })
+        .await
+        .flatten()
+}
+
+struct Handler;
+
+#[async_trait]
+impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        // every shard gets its own ready
+        match ready.shard {
+            Some([id, total]) => info!("Connected as {} on shard {id}/{total}", ready.user.name),
+            None => info!("Connected as {}", ready.user.name),
+        }
+        let mut data = ctx.data.write().await;
+        let discord = data
+            .get_mut::<DiscordContext>()
+            .expect("discord context is set");
+        if discord.is_none() {
+            *discord = Some(ctx.clone());
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::MessageComponent(component) = interaction {
+            soundboard_click(&ctx, &component).await;
+        }
+    }
+
+    async fn message(&self, ctx: Context, msg: Message) {
+        // boosts, and the levels they unlock, come as system messages by the booster
+        let boosted = matches!(
+            msg.kind,
+            MessageType::NitroBoost
+                | MessageType::NitroTier1
+                | MessageType::NitroTier2
+                | MessageType::NitroTier3
+        );
+        if let (true, Some(gid)) = (boosted, msg.guild_id) {
+            celebrate_boost(&ctx, gid, &msg).await;
+        }
+    }
+
+    async fn guild_create(&self, ctx: Context, guild: Guild, is_new: bool) {
+        {
+            let mut data = ctx.data.write().await;
+            let occupancy = data.get_mut::<VoiceCounts>().expect("voice counts are set");
+            occupancy.insert(guild.id, VoiceOccupancy::new(guild.voice_states.values()));
+        }
+        // so whatever reads the configs directly sees the config file's defaults too
+        {
+            let mut data = ctx.data.write().await;
+            let defaults = data.get::<GuildDefaults>().expect("guild defaults are set");
+            if let Some(defaults) = defaults.clone() {
+                let guild_configs = data
+                    .get_mut::<GuildConfigs>()
+                    .expect("guild configs are set");
+                guild_configs.entry(guild.id).or_insert(defaults);
+            }
+        }
+
+        // guilds we were already in come through here on every startup too
+        if !is_new {
+            return;
+        }
+
+        let (max_guilds, action) = {
+            let data = ctx.data.read().await;
+            let config = data.get::<Config>().expect("config is set");
+            (config.max_guilds, config.guild_limit_action)
+        };
+        let max_guilds = if let Some(max_guilds) = max_guilds {
+            max_guilds
+        } else {
+            return;
+        };
+        let guilds = ctx.cache.guild_count().await;
+        if guilds <= max_guilds {
+            return;
+        }
+
+        match action {
+            GuildLimitAction::Warn => {
+                warn!(
+                    "Added to guild {} ({}), now in {guilds} guilds, over the limit of {max_guilds}",
+                    guild.id, guild.name
+                );
+            }
+            GuildLimitAction::Leave => {
+                info!(
+                    "Leaving guild {} ({}), {guilds} guilds is over the limit of {max_guilds}",
+                    guild.id, guild.name
+                );
+                if let Some(channel_id) = guild.system_channel_id {
+                    let content = "Já to em servidores demais, não dá pra ficar aqui. Falou!!";
+                    if let Err(e) = channel_id.say(&ctx, content).await {
+                        warn!("Error saying goodbye to guild {}: {e}", guild.id);
+                    }
+                }
+                if let Err(e) = guild.leave(&ctx).await {
+                    error!("Error leaving guild {}: {e}", guild.id);
+                }
+            }
+        }
+    }
+
+    async fn voice_state_update(
+        &self,
+        ctx: Context,
+        gid: Option<GuildId>,
+        old: Option<VoiceState>,
+        new: VoiceState,
+    ) {
+        let gid = if let Some(gid) = gid {
+            gid
+        } else {
+            return;
+        };
+
+        // where they were as far as the updates so far go, `old` comes from the cache
+        // and can be missing or already replaced by a duplicate update
+        let previous = {
+            let mut data = ctx.data.write().await;
+            let occupancy = data.get_mut::<VoiceCounts>().expect("voice counts are set");
+            occupancy
+                .get_mut(&gid)
+                .map(|occupancy| occupancy.moved(new.user_id, new.channel_id))
+        };
+
+        match update_from(ctx.cache.current_user_id().await, &new) {
+            UpdateFrom::Member => {}
+            UpdateFrom::Bot => return,
+            UpdateFrom::BotDisconnected => {
+                forget_call(&ctx, gid).await;
+                return;
+            }
+        }
+
+        let left = previous.unwrap_or_else(|| old.as_ref().and_then(|old| old.channel_id));
+        if let Some(left) = left.filter(|&left| Some(left) != new.channel_id) {
+            leave_if_alone(&ctx, gid, left).await;
+        }
+
+        if is_bot(&ctx, gid, &new).await
+            && !guild_config(&ctx, gid)
+                .await
+                .allowed_bots
+                .contains(&new.user_id)
+        {
+            return;
+        }
+
+        if is_muted(&ctx).await {
+            return;
+        }
+
+        if follow(&ctx, gid, &new).await {
+            return;
+        }
+
+        if let Some(channel_id) = unmuted_in(old.as_ref(), &new) {
+            if !claim_transition(&ctx, gid, &new, channel_id, Transition::Unmute).await {
+                debug!("Already unmuted in channel {channel_id} of guild {gid} just now, ignoring");
+                return;
+            }
+            let guild_config = guild_config(&ctx, gid).await;
+            if guild_config.paused
+                || in_quiet_hours(&guild_config)
+                || guild_config.blocked_channels.contains(&channel_id)
+            {
+                return;
+            }
+
+            let _lock = lock_playback(&ctx, gid).await;
+            if let Some(sound) = pick_sound(&ctx, SoundKey::Unmute(gid)).await {
+                if cooling_down(&ctx, gid, guild_cooldown(&ctx, &guild_config).await).await {
+                    return;
+                }
+                let volume = playback_volume(&guild_config, None);
+                let intro = intro(&ctx, gid, &guild_config).await;
+                play_with_intro(&ctx, gid, channel_id, &sound, volume, intro, Looping::Once).await;
+            }
+            return;
+        }
+
+        // if somebody joined some channel
+        if let Some(channel_id) = joined_channel(old.as_ref(), previous, &new) {
+            if !claim_transition(&ctx, gid, &new, channel_id, Transition::Join).await {
+                debug!("Already joined channel {channel_id} of guild {gid} just now, ignoring");
+                return;
+            }
+            let _lock = lock_playback(&ctx, gid).await;
+            welcome(&ctx, gid, channel_id, &new).await;
+        }
+    }
+}
+
+/// Plays the sound for `new` joining `channel_id`, unless something rules it out.
+/// Everything logged on the way carries who joined where, so a report of a sound
+/// that didn't play can be traced back to why.
+#[tracing::instrument(skip_all, fields(guild = %gid, channel = %channel_id, user = %new.user_id))]
+async fn welcome(ctx: &Context, gid: GuildId, channel_id: ChannelId, new: &VoiceState) {
+    let guild_config = guild_config(ctx, gid).await;
+    // every join counts, played for or not
+    let now = unix_now();
+    let away = guild_config
+        .last_seen
+        .get(&new.user_id)
+        .map(|&last_seen| now.saturating_sub(last_seen));
+    if guild_config.comeback.is_some() {
+        update_guild_config(ctx, gid, |guild_config| {
+            guild_config.last_seen.insert(new.user_id, now);
+        })
+        .await;
+    }
+    if guild_config.paused {
+        debug!("Paused in the guild");
+        return;
+    }
+    if in_quiet_hours(&guild_config) {
+        debug!("In the guild's quiet hours");
+        return;
+    }
+
+    let members = members_in_channel(ctx, gid, channel_id, new.user_id).await;
+    if members < guild_config.min_members {
+        debug!(
+            members,
+            min_members = guild_config.min_members,
+            "Not enough members in the channel"
+        );
+        return;
+    }
+
+    if !guild_config.play_in_afk && afk_channel(ctx, gid).await == Some(channel_id) {
+        debug!("Joined the AFK channel");
+        return;
+    }
+
+    if guild_config.blocked_channels.contains(&channel_id) {
+        debug!("Joined a blocked channel");
+        return;
+    }
+
+    if guild_config.first_join_only && guild_config.seen.contains(&new.user_id) {
+        debug!("Already welcomed once");
+        return;
+    }
+
+    match guild_config.silenced.get(&new.user_id) {
+        Some(&until) if now < until => {
+            debug!(until, "Silenced");
+            return;
+        }
+        Some(_) => {
+            update_guild_config(ctx, gid, |guild_config| {
+                guild_config.silenced.remove(&new.user_id);
+            })
+            .await;
+        }
+        None => {}
+    }
+
+    if let Some(chance) = guild_config.chance {
+        if rand::thread_rng().gen_range(0..100) >= chance {
+            debug!(chance, "Lost the dice roll");
+            return;
+        }
+    }
+
+    let overriding = match &guild_config.sound_override {
+        Some(sound_override) if sound_override.is_active() => {
+            pick_sound(ctx, SoundKey::Override(gid)).await
+        }
+        Some(_) => {
+            clear_override(ctx, gid).await;
+            None
+        }
+        None => None,
+    };
+    // the lending guild may have stopped sharing since the channel was linked
+    let borrowed = match guild_config.borrowed_channels.get(&channel_id) {
+        Some(&from) if crate::guild_config(ctx, from).await.allow_sharing => {
+            pick_sound(ctx, SoundKey::Guild(from)).await
+        }
+        _ => None,
+    };
+    let is_borrowed = borrowed.is_some();
+    let is_override = !is_borrowed && overriding.is_some();
+    let overriding = borrowed.or(overriding);
+
+    // members seen for the first time aren't coming back from anywhere
+    let comeback = match (&overriding, &guild_config.comeback, away) {
+        (None, Some(comeback), Some(away)) if away >= comeback.after_secs => {
+            debug!(away, "Back after a long time");
+            fetch_sound(ctx, SoundKey::Guild(gid), &comeback.label)
+                .await
+                .map(|sound| (comeback.label.clone(), sound))
+        }
+        _ => None,
+    };
+    let overriding = overriding.or_else(|| comeback.as_ref().map(|(_, sound)| sound.clone()));
+    // `members` doesn't count the joiner
+    let milestone = match (&overriding, &guild_config.milestone) {
+        (None, Some(milestone)) if members + 1 == milestone.members => {
+            debug!(members = milestone.members, "Reached the milestone");
+            fetch_sound(ctx, SoundKey::Guild(gid), &milestone.label)
+                .await
+                .map(|sound| (milestone.label.clone(), sound))
+        }
+        _ => None,
+    };
+    let overriding = overriding.or_else(|| milestone.as_ref().map(|(_, sound)| sound.clone()));
+
+    // precedence is documented on `SoundKey`
+    let mut personal = None;
+    if overriding.is_none() {
+        for (source, key) in personal_keys(gid, new.user_id, guild_config.mode) {
+            if let Some(sound) = pick_sound(ctx, key).await {
+                personal = Some((source, sound));
+                break;
+            }
+        }
+    }
+    let role = match (&overriding, &personal) {
+        (None, None) => pick_role_sound(ctx, gid, new).await,
+        _ => None,
+    };
+    let source = if is_borrowed {
+        "borrowed"
+    } else if is_override {
+        "override"
+    } else if comeback.is_some() {
+        "comeback"
+    } else if milestone.is_some() {
+        "milestone"
+    } else if let Some((source, _)) = personal {
+        source
+    } else if role.is_some() {
+        "role"
+    } else {
+        "guild"
+    };
+    let platform = platform_of(ctx, gid, new.user_id).await;
+    // if there is a sound set to play on the guild, only their plays are counted
+    let mut guild_label = comeback.or(milestone).map(|(label, _)| label);
+    let sound = match overriding.or(personal.map(|(_, sound)| sound)).or(role) {
+        Some(sound) => Some(sound),
+        None => pick_labeled_sound(ctx, SoundKey::Guild(gid), Some(new.user_id), platform)
+            .await
+            .map(|(label, sound)| {
+                guild_label = Some(label);
+                sound
+            }),
+    };
+
+    let sound = match sound {
+        Some(sound) => sound,
+        None => {
+            debug!("No sound to play");
+            if guild_config.nudge_unset {
+                nudge(ctx, gid).await;
+            }
+            return;
+        }
+    };
+    debug!(source, "Picked a sound");
+
+    let join_jitter = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").join_jitter
+    };
+    if !join_jitter.is_zero() {
+        let delay = rand::thread_rng().gen_range(Duration::ZERO..=join_jitter);
+        debug!(?delay, "Waiting before joining");
+        tokio::time::sleep(delay).await;
+
+        // they may have left or moved on while we waited
+        if voice_channel_of(ctx, gid, new.user_id).await != Some(channel_id) {
+            debug!("Left the channel while we waited");
+            return;
+        }
+    }
+
+    let sound_cooldown = guild_label
+        .as_ref()
+        .and_then(|label| Some((label, guild_config.sounds.get(label)?.cooldown_secs?)));
+    match sound_cooldown {
+        Some((label, secs)) => {
+            let cooldown = Duration::from_secs(secs);
+            if sound_cooling_down(ctx, gid, label, cooldown).await {
+                debug!(?cooldown, "The sound is still cooling down");
+                return;
+            }
+        }
+        None => {
+            let cooldown = guild_cooldown(ctx, &guild_config).await;
+            if cooling_down(ctx, gid, cooldown).await {
+                debug!(?cooldown, "Still cooling down");
+                return;
+            }
+        }
+    }
+
+    // TODO: check if not already playing on another channel
+
+    if guild_config.first_join_only {
+        update_guild_config(ctx, gid, |guild_config| {
+            guild_config.seen.insert(new.user_id);
+        })
+        .await;
+    }
+
+    if is_override {
+        use_override(ctx, gid).await;
+    }
+    if guild_config.boost.is_some() {
+        use_boost(ctx, gid).await;
+    }
+
+    info!(source, "Playing the entrance sound");
+    record_activity(
+        ctx,
+        gid,
+        ActivityKind::Play,
+        guild_label.clone(),
+        new.user_id,
+    )
+    .await;
+    if let Some(label) = &guild_label {
+        update_guild_config(ctx, gid, |guild_config| {
+            guild_config.sounds.entry(label.clone()).or_default().plays += 1;
+        })
+        .await;
+    }
+    let volume = playback_volume(&guild_config, guild_label.as_deref());
+    let intro = intro(ctx, gid, &guild_config).await;
+    let loop_secs = guild_label
+        .as_ref()
+        .and_then(|label| guild_config.sounds.get(label)?.loop_secs);
+    let looping = match loop_secs {
+        _ if guild_config.stay => Looping::Forever,
+        Some(secs) => Looping::For(Duration::from_secs(secs.into())),
+        None => Looping::Once,
+    };
+    play_with_intro(ctx, gid, channel_id, &sound, volume, intro, looping).await;
+    if let Some(secs) = guild_config.follow_secs {
+        if connected_to(ctx, gid, channel_id).await {
+            let until = Instant::now() + Duration::from_secs(secs);
+            let mut data = ctx.data.write().await;
+            let following = data.get_mut::<Following>().expect("following is set");
+            following.insert(gid, (new.user_id, until));
+        }
+    }
+
+    if let Some(transcript_channel) = guild_config.transcript_channel {
+        let played = match (&guild_label, source) {
+            (Some(label), _) => format!("`{label}`"),
+            (None, "override") => "o áudio especial".to_string(),
+            (None, "borrowed") => "o áudio de outro servidor".to_string(),
+            (None, "role") => "o áudio do cargo".to_string(),
+            (None, _) => "o próprio áudio".to_string(),
+        };
+        let line = format!(
+            "🔊 {} entrou no {}: {played}",
+            new.user_id.mention(),
+            channel_id.mention()
+        );
+        // the mention is there to name them, not to ping them every time they join
+        let posted = transcript_channel
+            .send_message(ctx, |m| {
+                m.content(line).allowed_mentions(|a| a.empty_parse())
+            })
+            .await;
+        if let Err(e) = posted {
+            warn!("Error posting to the transcript channel: {e}");
+        }
+    }
+
+    let caption = guild_label.and_then(|label| guild_config.sounds.get(&label)?.caption.clone());
+    if let (Some(announce_channel), Some(caption)) = (guild_config.announce_channel, caption) {
+        if let Err(e) = announce_channel.say(ctx, caption).await {
+            warn!("Error posting the sound's caption: {e}");
+        }
+    }
+}
+
+/// Picks a sound of the highest of the joiner's roles that has any, highest being the
+/// one listed first in the guild's role settings.
+async fn pick_role_sound(ctx: &Context, gid: GuildId, joiner: &VoiceState) -> Option<CachedAudio> {
+    let roles = voice_member(ctx, gid, joiner).await?.roles;
+    let with_sounds: Vec<RoleId> = {
+        let sound_store = sound_store(ctx).await;
+        let sound_store = sound_store.read().await;
+        roles
+            .into_iter()
+            .filter(|rid| sound_store.contains_key(&SoundKey::Role(*rid)))
+            .collect()
+    };
+
+    let highest = ctx
+        .cache
+        .guild_field(gid, |guild| {
+            with_sounds
+                .iter()
+                .max_by_key(|rid| guild.roles.get(rid).map_or(0, |role| role.position))
+                .copied()
+        })
+        .await
+        .unwrap_or_else(|| with_sounds.first().copied())?;
+
+    pick_sound(ctx, SoundKey::Role(highest)).await
+}
+
+/// Picks one of `key`'s sounds at random, guild sounds following their weights, schedules
+/// and active category.
+async fn pick_sound(ctx: &Context, key: SoundKey) -> Option<CachedAudio> {
+    pick_labeled_sound(ctx, key, None, None)
+        .await
+        .map(|(_, sound)| sound)
+}
+
+/// Like [`pick_sound`], also returning the label of the sound picked. Guild sounds
+/// assigned to `joiner`, and then to their `platform`, take precedence over the
+/// others, see [`SoundKey`].
+async fn pick_labeled_sound(
+    ctx: &Context,
+    key: SoundKey,
+    joiner: Option<UserId>,
+    platform: Option<Platform>,
+) -> Option<(String, CachedAudio)> {
+    let label = pick_label(ctx, key, joiner, platform).await?;
+    let sound = fetch_sound(ctx, key, &label).await?;
+    Some((label, sound))
+}
+
+/// The label of the sound [`pick_labeled_sound`] picks, without decoding or even
+/// touching it, so it can be asked without playing anything.
+async fn pick_label(
+    ctx: &Context,
+    key: SoundKey,
+    joiner: Option<UserId>,
+    platform: Option<Platform>,
+) -> Option<String> {
+    let (sound_configs, active_category, rotating) = match key {
+        SoundKey::Guild(gid) => {
+            let guild_config = guild_config(ctx, gid).await;
+            let rotating = guild_config.rotation.and_then(|rotation| rotation.label);
+            (guild_config.sounds, guild_config.active_category, rotating)
+        }
+        SoundKey::User(_)
+        | SoundKey::Member(..)
+        | SoundKey::Role(_)
+        | SoundKey::Override(_)
+        | SoundKey::Unmute(_)
+        | SoundKey::Intro(_) => (HashMap::new(), None, None),
+    };
+
+    let sound_store = sound_store(ctx).await;
+    let sound_store = sound_store.read().await;
+    let sounds = sound_store.get(&key)?;
+
+    let assigned_to = |sound: &Sound| sound_configs.get(&sound.label).map(|c| &c.only_for);
+    let for_joiner = |sound: &Sound| {
+        joiner.is_some_and(|uid| assigned_to(sound).is_some_and(|only| only.contains(&uid)))
+    };
+    let unassigned = |sound: &Sound| assigned_to(sound).is_none_or(HashSet::is_empty);
+    let platform_of = |sound: &Sound| sound_configs.get(&sound.label)?.platform;
+    let for_platform = |sound: &Sound| platform.is_some() && platform_of(sound) == platform;
+
+    let in_category = |sound: &Sound| match &active_category {
+        Some(active) => sound_configs
+            .get(&sound.label)
+            .is_some_and(|config| config.category.as_ref() == Some(active)),
+        None => true,
+    };
+
+    // scheduled sounds take over while active, and stay quiet otherwise. One outside
+    // the active category takes over nothing, it wouldn't play anyway
+    let now = Utc::now();
+    let active = |sound: &Sound| {
+        let config = sound_configs.get(&sound.label)?;
+        Some(config.schedule.as_ref()?.is_active(now))
+    };
+    let any_active = sounds
+        .iter()
+        .any(|sound| in_category(sound) && active(sound) == Some(true));
+    let any_for_joiner = sounds
+        .iter()
+        .any(|sound| for_joiner(sound) && in_category(sound));
+    let any_for_platform = sounds
+        .iter()
+        .any(|sound| for_platform(sound) && unassigned(sound) && in_category(sound));
+    let assigned_ok = |sound: &Sound| {
+        if any_for_joiner {
+            for_joiner(sound)
+        } else if any_for_platform {
+            for_platform(sound) && unassigned(sound)
+        } else {
+            unassigned(sound) && platform_of(sound).is_none()
+        }
+    };
+
+    // the sound whose turn it is, unless it was removed meanwhile, the joiner has
+    // their own or a scheduled sound took over
+    let rotating = rotating
+        .filter(|label| {
+            sounds
+                .iter()
+                .any(|sound| &sound.label == label && active(sound).unwrap_or(!any_active))
+        })
+        .filter(|_| !any_for_joiner && !any_for_platform);
+
+    let weight = |sound: &Sound| {
+        if in_category(sound) && assigned_ok(sound) && active(sound).unwrap_or(!any_active) {
+            sound_configs
+                .get(&sound.label)
+                .map_or(DEFAULT_WEIGHT, |config| config.weight)
+        } else {
+            0
+        }
+    };
+    match rotating {
+        Some(label) => Some(label),
+        None => Some(choose_weighted(sounds, weight)?.label.clone()),
+    }
+}
+
+/// Picks one of `sounds` at random, more likely the heavier ones. `None` if there
+/// are no sounds or all of them weigh 0.
+fn choose_weighted(sounds: &[Sound], weight: impl Fn(&Sound) -> u32) -> Option<&Sound> {
+    let index = WeightedIndex::new(sounds.iter().map(weight)).ok()?;
+    sounds.get(index.sample(&mut rand::thread_rng()))
+}
+
+/// `key`'s sound labeled `label`, or its first one when no label is given.
+fn find_sound<'a>(
+    sound_store: &'a HashMap<SoundKey, Vec<Sound>>,
+    key: SoundKey,
+    label: Option<&str>,
+) -> Option<&'a Sound> {
+    let sounds = sound_store.get(&key)?;
+    match label {
+        Some(label) => sounds.iter().find(|s| s.label == label),
+        None => sounds.first(),
+    }
+}
+
+/// The decoded audio of `key`'s sound labeled `label`.
+async fn fetch_sound(ctx: &Context, key: SoundKey, label: &str) -> Option<CachedAudio> {
+    let source = {
+        let sound_store = sound_store(ctx).await;
+        let mut sound_store = sound_store.write().await;
+        let sound = sound_store
+            .get_mut(&key)?
+            .iter_mut()
+            .find(|s| s.label == label)?;
+        sound.last_used = Instant::now();
+        match &sound.memory {
+            Some(memory) => return Some(memory.clone()),
+            None => sound.source.clone()?,
+        }
+    };
+
+    // evicted by the cache TTL, bring it back
+    let config = ctx
+        .data
+        .read()
+        .await
+        .get::<Config>()
+        .expect("config is set")
+        .clone();
+    let filter = key_filter(ctx, key).await;
+    let memory = match decode_persisted(key, &source, filter, &config).await {
+        Ok(decoded) => decoded.memory,
+        Err(e) => {
+            warn!("Error reloading sound {source:?} for {key}: {e}");
+            return None;
+        }
+    };
+
+    let sound_store = sound_store(ctx).await;
+    let mut sound_store = sound_store.write().await;
+    let sound = sound_store
+        .get_mut(&key)
+        .and_then(|sounds| sounds.iter_mut().find(|s| s.label == label));
+    if let Some(sound) = sound {
+        sound.memory = Some(memory.clone());
+    }
+    Some(memory)
+}
+
+struct Muted;
+
+impl TypeMapKey for Muted {
+    /// Kill switch that stops all playback in every guild.
+    type Value = Arc<AtomicBool>;
+}
+
+async fn is_muted(ctx: &Context) -> bool {
+    let data = ctx.data.read().await;
+    let muted = data.get::<Muted>().expect("muted is set");
+    muted.load(Ordering::Relaxed)
+}
+
+struct Draining;
+
+impl TypeMapKey for Draining {
+    /// Set while shutting down for a restart, see [`drain`].
+    type Value = Arc<AtomicBool>;
+}
+
+async fn is_draining(ctx: &Context) -> bool {
+    let data = ctx.data.read().await;
+    let draining = data.get::<Draining>().expect("draining is set");
+    draining.load(Ordering::Relaxed)
+}
+
+struct Shards;
+
+impl TypeMapKey for Shards {
+    type Value = Arc<Mutex<ShardManager>>;
+}
+
+/// Guilds already told how to set a sound since startup, see [`nudge`].
+struct Nudged;
+
+impl TypeMapKey for Nudged {
+    type Value = HashSet<GuildId>;
+}
+
+/// Tells `gid`, in its system channel, how to set a sound. Only once per run, so
+/// it's a hint rather than spam.
+async fn nudge(ctx: &Context, gid: GuildId) {
+    let prefix = {
+        let mut data = ctx.data.write().await;
+        if !data.get_mut::<Nudged>().expect("nudged is set").insert(gid) {
+            return;
+        }
+        data.get::<Config>().expect("config is set").prefixes[0].clone()
+    };
+
+    let channel_id = match ctx.cache.guild_field(gid, |g| g.system_channel_id).await {
+        Some(Some(channel_id)) => channel_id,
+        _ => {
+            warn!("Guild {gid} has no system channel to nudge in");
+            return;
+        }
+    };
+    let content = format!(
+        "Nenhum áudio configurado por aqui, usa `{prefix}set` mandando um arquivo de áudio!!"
+    );
+    if let Err(e) = channel_id.say(ctx, content).await {
+        warn!("Error nudging guild {gid}: {e}");
+    }
+}
+
+struct LastPlays;
+
+impl TypeMapKey for LastPlays {
+    type Value = HashMap<GuildId, Instant>;
+}
+
+struct LastSoundPlays;
+
+impl TypeMapKey for LastSoundPlays {
+    /// When each guild sound with its own cooldown last played, by label.
+    type Value = HashMap<(GuildId, String), Instant>;
+}
+
+/// Where the time used for cooldowns comes from, so it can be controlled.
+trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that moves `step` forward every time it's read, for [`simulate_joins`].
+struct SteppedClock {
+    start: Instant,
+    step: Duration,
+    reads: AtomicU32,
+}
+
+impl Clock for SteppedClock {
+    fn now(&self) -> Instant {
+        let reads = self.reads.fetch_add(1, Ordering::SeqCst);
+        // never reached with the gaps `simulate-joins` allows, but a clock mustn't panic
+        self.step
+            .checked_mul(reads)
+            .and_then(|elapsed| self.start.checked_add(elapsed))
+            .unwrap_or(self.start)
+    }
+}
+
+struct Clocks;
+
+impl TypeMapKey for Clocks {
+    type Value = Arc<dyn Clock>;
+}
+
+/// Whether `gid` played a sound less than `cooldown` ago, if not, starts a new
+/// cooldown from now.
+async fn cooling_down(ctx: &Context, gid: GuildId, cooldown: Duration) -> bool {
+    let mut data = ctx.data.write().await;
+    let now = data.get::<Clocks>().expect("clock is set").now();
+    let last_plays = data.get_mut::<LastPlays>().expect("last plays are set");
+
+    check_cooldown(last_plays, gid, cooldown, now)
+}
+
+/// Like [`cooling_down`] for the guild's sound `label` and its own `cooldown`. The
+/// guild's cooldown starts anew when it plays all the same, for the sounds without one.
+async fn sound_cooling_down(ctx: &Context, gid: GuildId, label: &str, cooldown: Duration) -> bool {
+    let mut data = ctx.data.write().await;
+    let now = data.get::<Clocks>().expect("clock is set").now();
+    let last_sound_plays = data
+        .get_mut::<LastSoundPlays>()
+        .expect("last sound plays are set");
+    if check_cooldown(last_sound_plays, (gid, label.to_string()), cooldown, now) {
+        return true;
+    }
+    let last_plays = data.get_mut::<LastPlays>().expect("last plays are set");
+    last_plays.insert(gid, now);
+    false
+}
+
+fn check_cooldown<K: Eq + Hash>(
+    last_plays: &mut HashMap<K, Instant>,
+    key: K,
+    cooldown: Duration,
+    now: Instant,
+) -> bool {
+    match last_plays.get(&key) {
+        Some(last) if now.saturating_duration_since(*last) < cooldown => true,
+        _ => {
+            last_plays.insert(key, now);
+            false
+        }
+    }
+}
+
+struct Plays;
+
+impl TypeMapKey for Plays {
+    /// Counts playbacks started in each guild, so a lingering `Disconnect` can tell
+    /// whether something else started playing while it waited.
+    type Value = HashMap<GuildId, Arc<AtomicU64>>;
+}
+
+struct Held;
+
+impl TypeMapKey for Held {
+    /// The channel `hold` keeps the bot in for each guild, 0 when it's free to leave.
+    /// Shared with `Disconnect`s so they know not to leave.
+    type Value = HashMap<GuildId, Arc<AtomicU64>>;
+}
+
+async fn held_in(ctx: &Context, gid: GuildId) -> Option<ChannelId> {
+    let data = ctx.data.read().await;
+    let held = data.get::<Held>().expect("held is set");
+    match held.get(&gid)?.load(Ordering::SeqCst) {
+        0 => None,
+        channel_id => Some(ChannelId(channel_id)),
+    }
+}
+
+async fn set_held(ctx: &Context, gid: GuildId, channel_id: Option<ChannelId>) {
+    let mut data = ctx.data.write().await;
+    let held = data.get_mut::<Held>().expect("held is set");
+    held.entry(gid).or_default().store(
+        channel_id.map_or(0, |channel_id| channel_id.0),
+        Ordering::SeqCst,
+    );
+}
+
+struct Tracks;
+
+impl TypeMapKey for Tracks {
+    /// The track each guild played last, to tell whether it's still going.
+    type Value = HashMap<GuildId, TrackHandle>;
+}
+
+struct LastSounds;
+
+impl TypeMapKey for LastSounds {
+    /// The sound each guild played last and its volume, for `replay-last`. It's kept
+    /// until the next one plays, even if it's removed or decoded again meanwhile.
+    type Value = HashMap<GuildId, (CachedAudio, f32)>;
+}
+
+/// Joins `channel_id` and plays `sound`, leaving the channel once it ends.
+/// `volume` is what the track plays at, 1 being the sound as it is, see
+/// [`playback_volume`].
+async fn play(
+    ctx: &Context,
+    gid: GuildId,
+    channel_id: ChannelId,
+    sound: &CachedAudio,
+    volume: f32,
+) {
+    play_with_intro(ctx, gid, channel_id, sound, volume, None, Looping::Once).await
+}
+
+/// The guild's [`SoundKey::Intro`], and the volume it plays at.
+async fn intro(
+    ctx: &Context,
+    gid: GuildId,
+    guild_config: &GuildConfig,
+) -> Option<(CachedAudio, f32)> {
+    let intro = pick_sound(ctx, SoundKey::Intro(gid)).await?;
+    Some((intro, playback_volume(guild_config, None)))
+}
+
+/// Like [`play`], queueing `intro` right before the sound, after the pre-roll if
+/// there's one. The bot only leaves once the sound, the last of them, ends.
+async fn play_with_intro(
+    ctx: &Context,
+    gid: GuildId,
+    channel_id: ChannelId,
+    sound: &CachedAudio,
+    volume: f32,
+    intro: Option<(CachedAudio, f32)>,
+    looping: Looping,
+) {
+    if is_draining(ctx).await {
+        debug!("Not playing in guild {gid}, draining");
+        return;
+    }
+    if is_muted(ctx).await {
+        debug!("Not playing in guild {gid}, muted");
+        return;
+    }
+    // playing elsewhere would move the bot out of the channel it's held in
+    match held_in(ctx, gid).await {
+        Some(held) if held != channel_id => {
+            debug!("Not playing in guild {gid}, held in channel {held}");
+            return;
+        }
+        _ => {}
+    }
+    if !ready_to_speak(ctx, gid).await {
+        return;
+    }
+    let started = Instant::now();
+    // the pre-roll marks the bot arriving, not every sound it plays while there
+    let preroll = if connected_to(ctx, gid, channel_id).await {
+        None
+    } else {
+        let data = ctx.data.read().await;
+        data.get::<Preroll>().expect("preroll is set").clone()
+    };
+
+    let call = if let Some(call) = join(ctx, gid, channel_id).await {
+        call
+    } else {
+        return;
+    };
+
+    let input = match sound.input() {
+        Ok(input) => input,
+        Err(e) => {
+            error!("Error building the sound's input for guild {gid}: {e}");
+            leave(&call).await;
+            return;
+        }
+    };
+
+    // register the disconnect before handing the track to the driver, so even
+    // a clip that ends right away can't finish before anyone is listening for it
+    let (mut track, handle) = songbird::create_player(input);
+    track.set_volume(volume);
+    // started even when staying, so a lingering `Disconnect` knows not to leave
+    let disconnect = disconnect(ctx, gid, &call).await;
+    let (registered, unregistered) = match looping {
+        Looping::Forever => (Ok(()), Some(disconnect)),
+        _ => (
+            handle.add_event(Event::Track(TrackEvent::End), disconnect),
+            None,
+        ),
+    };
+    if let Err(e) = registered {
+        error!("Error registering the disconnect for guild {gid}: {e}");
+        leave(&call).await;
+        return;
+    }
+    // a looping track never ends by itself, stopping it is what sets off the disconnect
+    let looped = match looping {
+        Looping::Once => Ok(()),
+        Looping::For(looping) => track
+            .set_loops(LoopState::Infinite)
+            .map_err(|e| e.to_string())
+            .and_then(|()| {
+                handle
+                    .add_event(Event::Delayed(looping), StopLoop)
+                    .map_err(|e| e.to_string())
+            }),
+        Looping::Forever => track
+            .set_loops(LoopState::Infinite)
+            .map_err(|e| e.to_string()),
+    };
+    if let Err(e) = looped {
+        warn!("Error looping the sound for guild {gid}, playing it once: {e}");
+        let _ = track.set_loops(LoopState::Finite(0));
+        // played once, it has to leave at the end like any other sound
+        if let Some(disconnect) = unregistered {
+            if let Err(e) = handle.add_event(Event::Track(TrackEvent::End), disconnect) {
+                error!("Error registering the disconnect for guild {gid}: {e}");
+                leave(&call).await;
+                return;
+            }
+        }
+    }
+
+    {
+        let mut data = ctx.data.write().await;
+        let tracks = data.get_mut::<Tracks>().expect("tracks are set");
+        tracks.insert(gid, handle.clone());
+        let last_sounds = data.get_mut::<LastSounds>().expect("last sounds are set");
+        last_sounds.insert(gid, (sound.clone(), volume));
+    }
+
+    let fade = {
+        let data = ctx.data.read().await;
+        data.get::<Config>().expect("config is set").crossfade
+    };
+    // whatever goes before the sound, in the order it plays
+    let mut leading = Vec::new();
+    match preroll.map(|preroll| preroll.input()) {
+        Some(Ok(preroll)) => leading.push(songbird::create_player(preroll)),
+        Some(Err(e)) => warn!("Error building the pre-roll's input for guild {gid}: {e}"),
+        None => {}
+    }
+    if let Some((intro, volume)) = intro {
+        match intro.input() {
+            Ok(intro) => {
+                let (mut intro, intro_handle) = songbird::create_player(intro);
+                intro.set_volume(volume);
+                leading.push((intro, intro_handle));
+            }
+            Err(e) => warn!("Error building the intro's input for guild {gid}: {e}"),
+        }
+    }
+
+    let mut call = call.lock().await;
+    // dropped while everything was being set up, playing would go nowhere
+    if call.current_connection().is_none() {
+        warn!("Lost the connection in guild {gid} before playing, leaving");
+        if let Err(e) = call.leave().await {
+            warn!("Error leaving channel: {e}");
+        }
+        return;
+    }
+    // a preview still queued would carry on once this track stops it
+    call.queue().stop();
+    match leading.first() {
+        Some((_, first)) => {
+            call.stop();
+            first_audio(first, started);
+            for (lead, lead_handle) in leading {
+                crossfade(&lead_handle, call.queue(), fade);
+                call.enqueue(lead);
+            }
+            call.enqueue(track);
+        }
+        None => {
+            first_audio(&handle, started);
+            call.play_only(track);
+        }
+    }
+    drop(call);
+    supervise_driver(ctx, gid, &handle).await;
+}
+
+/// How many times [`play_with_intro`] plays a sound.
+#[derive(Clone, Copy)]
+enum Looping {
+    Once,
+    /// Over and over until it has played this long, see `SoundConfig::loop_secs`.
+    For(Duration),
+    /// Over and over, with the bot staying until [`stop_staying`], see
+    /// `GuildConfig::stay`.
+    Forever,
+}
+
+/// Stops a looping track once it has played for as long as it should.
+struct StopLoop;
+
+#[async_trait]
+impl VoiceEventHandler for StopLoop {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(tracks) = ctx {
+            for (_, handle) in *tracks {
+                if let Err(e) = handle.stop() {
+                    warn!("Error stopping a looping sound: {e}");
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Whether the bot can be heard in `gid`, after unmuting itself there if it was
+/// server muted and `Config::server_mute_action` says so. A muted bot still joins
+/// and plays, only nobody hears a thing.
+async fn ready_to_speak(ctx: &Context, gid: GuildId) -> bool {
+    let bot = ctx.cache.current_user_id().await;
+    // the voice state is there while connected, the member otherwise
+    let muted = ctx
+        .cache
+        .guild_field(gid, |guild| match guild.voice_states.get(&bot) {
+            Some(voice_state) => voice_state.mute,
+            None => guild.members.get(&bot).is_some_and(|member| member.mute),
+        })
+        .await
+        .unwrap_or(false);
+    if !muted {
+        return true;
+    }
+
+    let action = {
+        let data = ctx.data.read().await;
+        data.get::<Config>()
+            .expect("config is set")
+            .server_mute_action
+    };
+    if action == ServerMuteAction::Unmute {
+        match gid.edit_member(ctx, bot, |member| member.mute(false)).await {
+            Ok(_) => {
+                info!("Unmuted ourselves in guild {gid}");
+                return true;
+            }
+            Err(e) => warn!("Error unmuting ourselves in guild {gid}, missing Mute Members? {e}"),
+        }
+    }
+    warn!("Server muted in guild {gid}, not playing since nobody would hear it");
+    false
+}
+
+/// How long the driver gets to pick up a track before it's presumed dead.
+const DRIVER_STALL: Duration = Duration::from_secs(5);
+
+/// Fires on the first frame of a track the mixer plays. Songbird only sends
+/// `TrackEvent::Play` when a paused track resumes, never for one that starts out
+/// playing, but timed events go by the track's play time, which only moves while
+/// the mixer is actually mixing it.
+const FIRST_FRAME: Event = Event::Delayed(Duration::ZERO);
+
+/// Watches the driver take `handle` over. A source that makes songbird's mixer
+/// panic takes the whole call down without a word, and every later track in the
+/// guild would go nowhere, so if the driver doesn't answer for the track the call is
+/// dropped and the next play starts a fresh one.
+async fn supervise_driver(ctx: &Context, gid: GuildId, handle: &TrackHandle) {
+    let manager = if let Some(manager) = songbird::get(ctx).await {
+        manager
+    } else {
+        return;
+    };
+    // a mixing driver fires one of these for every track it gets, even one stopped
+    // early
+    let alive = Arc::new(AtomicBool::new(false));
+    for event in [FIRST_FRAME, Event::Track(TrackEvent::End)] {
+        let heard = DriverAlive(alive.clone());
+        if let Err(e) = handle.add_event(event, heard) {
+            warn!("Error registering the driver watchdog for guild {gid}: {e}");
+            return;
+        }
+    }
+
+    let (handle, data) = (handle.clone(), ctx.data.clone());
+    tokio::spawn(async move {
+        tokio::time::sleep(DRIVER_STALL).await;
+        if alive.load(Ordering::SeqCst) {
+            return;
+        }
+        // a track replaced by a newer one never gets to play or end, and asking about
+        // it fails like it would with a dead mixer
+        let current = {
+            let data = data.read().await;
+            let tracks = data.get::<Tracks>().expect("tracks are set");
+            tracks.get(&gid).map(TrackHandle::uuid)
+        };
+        if current != Some(handle.uuid()) {
+            return;
+        }
+        // a track still queued behind another is answered for, a dead mixer's isn't
+        if handle.get_info().await.is_ok() {
+            return;
+        }
+        error!("Voice driver of guild {gid} stopped responding, dropping the call to recover");
+        match manager.remove(gid).await {
+            Ok(()) | Err(JoinError::NoCall) => {
+                info!("Dropped the call of guild {gid}, the next sound joins with a new one")
+            }
+            Err(e) => error!("Error recovering the voice driver of guild {gid}: {e}"),
+        }
+    });
+}
+
+struct DriverAlive(Arc<AtomicBool>);
+
+#[async_trait]
+impl VoiceEventHandler for DriverAlive {
+    async fn act(&self, _: &EventContext<'_>) -> Option<Event> {
+        self.0.store(true, Ordering::SeqCst);
+        Some(Event::Cancel)
+    }
+}
+
+/// Logs the voice connections that broke instead of being left. Songbird tries to
+/// reconnect on its own, this is just so it doesn't happen unnoticed.
+struct DriverDisconnected;
+
+#[async_trait]
+impl VoiceEventHandler for DriverDisconnected {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::DriverDisconnect(data) = ctx {
+            if let Some(reason) = data.reason {
+                warn!(
+                    "Voice connection of guild {} broke during {:?}: {reason:?}",
+                    data.guild_id, data.kind
+                );
+            }
+        }
+        None
+    }
+}
+
+/// Logs how long after `started` `handle` began to play, which is how long members
+/// wait to hear anything after joining. Compare `CACHE_FORMAT`s with it.
+fn first_audio(handle: &TrackHandle, started: Instant) {
+    let first_audio = FirstAudio {
+        started,
+        span: Span::current(),
+    };
+    if let Err(e) = handle.add_event(FIRST_FRAME, first_audio) {
+        warn!("Error registering the latency log: {e}");
+    }
+}
+
+struct FirstAudio {
+    started: Instant,
+    span: Span,
+}
+
+#[async_trait]
+impl VoiceEventHandler for FirstAudio {
+    async fn act(&self, _: &EventContext<'_>) -> Option<Event> {
+        let _entered = self.span.enter();
+        info!(latency = ?self.started.elapsed(), "Started playing");
+        Some(Event::Cancel)
+    }
+}
+
+/// Whether the bot is already in `channel_id`.
+async fn connected_to(ctx: &Context, gid: GuildId, channel_id: ChannelId) -> bool {
+    let call = match songbird::get(ctx).await {
+        Some(manager) => manager.get(gid),
+        None => None,
+    };
+    match call {
+        Some(call) => call.lock().await.current_channel().map(|c| c.0) == Some(channel_id.0),
+        None => false,
+    }
+}
+
+struct Preroll;
+
+impl TypeMapKey for Preroll {
+    /// Clip played as the bot connects, before the sound it came to play.
+    type Value = Option<CachedAudio>;
+}
+
+/// Joins `channel_id`, unless the bot can't be heard there.
+async fn join(ctx: &Context, gid: GuildId, channel_id: ChannelId) -> Option<Arc<Mutex<Call>>> {
+    let manager = if let Some(manager) = songbird::get(ctx).await {
+        manager
+    } else {
+        error!("Songbird is not registered, can't join voice channels");
+        return None;
+    };
+
+    if !can_speak_in(ctx, channel_id).await {
+        warn!("Missing Connect or Speak permission in channel {channel_id} of guild {gid}, not joining");
+        return None;
+    }
+
+    if voice_degraded(ctx).await {
+        return None;
+    }
+    let new_call = manager.get(gid).is_none();
+    let call = match tokio::time::timeout(JOIN_TIMEOUT, manager.join(gid, channel_id)).await {
+        Ok((call, Ok(()))) => {
+            // a join can report success without leaving a connection to play into
+            if call.lock().await.current_connection().is_none() {
+                warn!("Joined channel {channel_id} of guild {gid} without a connection, leaving");
+                record_join(ctx, false).await;
+                leave(&call).await;
+                return None;
+            }
+            debug!("Joined the channel");
+            record_join(ctx, true).await;
+            call
+        }
+        Ok((_, Err(e))) => {
+            warn!("Error joining channel: {e}");
+            record_join(ctx, false).await;
+            return None;
+        }
+        Err(_) => {
+            warn!("Timed out joining channel {channel_id} of guild {gid}");
+            record_join(ctx, false).await;
+            return None;
+        }
+    };
+
+    if new_call {
+        call.lock()
+            .await
+            .add_global_event(Event::Core(CoreEvent::DriverDisconnect), DriverDisconnected);
+    }
+
+    let bitrate_kbps = match guild_config(ctx, gid).await.bitrate_kbps {
+        Some(kbps) => Some(kbps),
+        None => {
+            let data = ctx.data.read().await;
+            data.get::<Config>().expect("config is set").bitrate_kbps
+        }
+    };
+    if let Some(kbps) = bitrate_kbps {
+        // channels cap the bitrate depending on the guild's boost level
+        let channel_max = ctx
+            .cache
+            .guild_channel_field(channel_id, |channel| channel.bitrate)
+            .await
+            .flatten()
+            .unwrap_or(u64::MAX);
+        let bps = (u64::from(kbps) * 1000).min(channel_max);
+        call.lock()
+            .await
+            .set_bitrate(Bitrate::BitsPerSecond(bps as i32));
+    }
+
+    Some(call)
+}
+
+/// Longest a join may take before it counts as failed.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// This many failed joins within `JOIN_FAILURE_WINDOW` most likely means Discord's
+/// voice servers are having an outage.
+const JOIN_FAILURE_LIMIT: usize = 5;
+
+const JOIN_FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+/// How long joins are held off once voice looks degraded.
+const VOICE_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+struct VoiceHealth;
+
+impl TypeMapKey for VoiceHealth {
+    type Value = JoinFailures;
+}
+
+/// Recent failed joins across all guilds, and whether that's enough to stop trying
+/// for a while instead of hammering voice servers that are down.
+#[derive(Default)]
+struct JoinFailures {
+    failed_at: VecDeque<Instant>,
+    backoff_until: Option<Instant>,
+}
+
+impl JoinFailures {
+    fn degraded(&mut self, now: Instant) -> bool {
+        match self.backoff_until {
+            Some(until) if now < until => true,
+            Some(_) => {
+                info!("Voice backoff over, joining channels again");
+                self.backoff_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether it just started backing off.
+    fn record(&mut self, joined: bool, now: Instant) -> bool {
+        if joined {
+            self.failed_at.clear();
+            return false;
+        }
+
+        self.failed_at.push_back(now);
+        while let Some(&first) = self.failed_at.front() {
+            if now.saturating_duration_since(first) <= JOIN_FAILURE_WINDOW {
+                break;
+            }
+            self.failed_at.pop_front();
+        }
+        if self.failed_at.len() >= JOIN_FAILURE_LIMIT {
+            error!(
+                "Voice appears degraded, {} joins failed within {JOIN_FAILURE_WINDOW:?}, not joining any channel for {VOICE_BACKOFF:?}",
+                self.failed_at.len()
+            );
+            self.failed_at.clear();
+            self.backoff_until = Some(now + VOICE_BACKOFF);
+            return true;
+        }
+        false
+    }
+}
+
+async fn voice_degraded(ctx: &Context) -> bool {
+    let mut data = ctx.data.write().await;
+    let now = data.get::<Clocks>().expect("clock is set").now();
+    data.get_mut::<VoiceHealth>()
+        .expect("voice health is set")
+        .degraded(now)
+}
+
+async fn record_join(ctx: &Context, joined: bool) {
+    let backing_off = {
+        let mut data = ctx.data.write().await;
+        let now = data.get::<Clocks>().expect("clock is set").now();
+        data.get_mut::<VoiceHealth>()
+            .expect("voice health is set")
+            .record(joined, now)
+    };
+    if backing_off {
+        let text = format!(
+            "{JOIN_FAILURE_LIMIT} voice joins failed within {JOIN_FAILURE_WINDOW:?}, not joining any channel for {VOICE_BACKOFF:?}"
+        );
+        tokio::spawn(alert(ctx.data.clone(), AlertEvent::Voice, text));
+    }
+}
+
+/// Trouble worth telling the operator about, see [`alert`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum AlertEvent {
+    /// Many uploads failing to decode at once, most likely ffmpeg or the host.
+    Decode,
+    /// Voice looks degraded and joins are on hold, see [`JoinFailures`].
+    Voice,
+    /// Discord rejected the token.
+    Auth,
+}
+
+impl FromStr for AlertEvent {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "decode" => Ok(AlertEvent::Decode),
+            "voice" => Ok(AlertEvent::Voice),
+            "auth" => Ok(AlertEvent::Auth),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for AlertEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AlertEvent::Decode => "decode",
+            AlertEvent::Voice => "voice",
+            AlertEvent::Auth => "auth",
+        })
+    }
+}
+
+/// Decode failures within `Config::alert_interval` that make a [`AlertEvent::Decode`]
+/// alert, a few are just bad uploads.
+const DECODE_FAILURE_ALERT: usize = 5;
+
+/// Longest sending an alert may take.
+const ALERT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Alerts;
+
+impl TypeMapKey for Alerts {
+    type Value = AlertLog;
+}
+
+#[derive(Default)]
+struct AlertLog {
+    /// When each event was last alerted.
+    sent_at: HashMap<AlertEvent, Instant>,
+    decode_failures: VecDeque<Instant>,
+}
+
+async fn record_decode_failure(data: Arc<RwLock<TypeMap>>) {
+    let (failures, window) = {
+        let mut data = data.write().await;
+        let now = data.get::<Clocks>().expect("clock is set").now();
+        let window = data.get::<Config>().expect("config is set").alert_interval;
+        let alerts = data.get_mut::<Alerts>().expect("alerts are set");
+        alerts.decode_failures.push_back(now);
+        while let Some(&first) = alerts.decode_failures.front() {
+            if now.saturating_duration_since(first) <= window {
+                break;
+            }
+            alerts.decode_failures.pop_front();
+        }
+        if alerts.decode_failures.len() < DECODE_FAILURE_ALERT {
+            return;
+        }
+        let failures = alerts.decode_failures.len();
+        alerts.decode_failures.clear();
+        (failures, window)
+    };
+    let text =
+        format!("{failures} sounds failed to decode within {window:?}, check ffmpeg and the host");
+    tokio::spawn(alert(data, AlertEvent::Decode, text));
+}
+
+/// Posts `text` to `Config::alert_webhook`, if `event` is one of `Config::alert_events`
+/// and it wasn't alerted in the last `Config::alert_interval`. The json has Discord's
+/// `content` so Discord webhooks take it as is, and the `event` for anything else.
+async fn alert(data: Arc<RwLock<TypeMap>>, event: AlertEvent, text: String) {
+    let url = {
+        let mut data = data.write().await;
+        let now = data.get::<Clocks>().expect("clock is set").now();
+        let config = data.get::<Config>().expect("config is set").clone();
+        let url = match &config.alert_webhook {
+            Some(url) if config.alert_events.contains(&event) => url.clone(),
+            _ => return,
+        };
+        let alerts = data.get_mut::<Alerts>().expect("alerts are set");
+        match alerts.sent_at.get(&event) {
+            Some(&at) if now.saturating_duration_since(at) < config.alert_interval => {
+                debug!(%event, "Already alerted recently, not alerting again");
+                return;
+            }
+            _ => {
+                alerts.sent_at.insert(event, now);
+            }
+        }
+        url
+    };
+
+    let body = serde_json::json!({ "content": format!("⚠️ {text}"), "event": event.to_string() });
+    let sent = reqwest::Client::new()
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .timeout(ALERT_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    match sent {
+        Ok(_) => info!(%event, "Alert sent"),
+        Err(e) => warn!(%event, "Error sending alert: {e}"),
+    }
+}
+
+/// Starts a new play in `gid`, returning the handler that leaves `call` once the
+/// play's last track ends.
+async fn disconnect(ctx: &Context, gid: GuildId, call: &Arc<Mutex<Call>>) -> Disconnect {
+    let (plays, held, linger, farewell) = {
+        let mut data = ctx.data.write().await;
+        let guild_configs = data.get::<GuildConfigs>().expect("guild configs are set");
+        let guild_config = guild_configs.get(&gid);
+        let linger = match guild_config.and_then(|gc| gc.linger_secs) {
+            Some(secs) => Duration::from_secs(secs),
+            None => data.get::<Config>().expect("config is set").linger,
+        };
+        let farewell =
+            guild_config.and_then(|gc| Some((gc.announce_channel?, gc.farewell.clone()?)));
+        let farewell = farewell.map(|(channel_id, text)| {
+            let farewells = data.get_mut::<Farewells>().expect("farewells are set");
+            Farewell {
+                http: ctx.http.clone(),
+                channel_id,
+                text,
+                last_posted: farewells.entry(gid).or_default().clone(),
+            }
+        });
+        let held = data.get_mut::<Held>().expect("held is set");
+        let held = held.entry(gid).or_default().clone();
+        let plays = data.get_mut::<Plays>().expect("plays are set");
+        (
+            plays.entry(gid).or_default().clone(),
+            held,
+            linger,
+            farewell,
+        )
+    };
+    let play_id = plays.fetch_add(1, Ordering::SeqCst) + 1;
+
+    Disconnect {
+        call: call.clone(),
+        span: Span::current(),
+        linger,
+        plays,
+        play_id,
+        held,
+        farewell,
+    }
+}
+
+/// Whether the bot can join `channel_id` and be heard there. When the cache doesn't
+/// know enough to tell, let songbird try anyway.
+async fn can_speak_in(ctx: &Context, channel_id: ChannelId) -> bool {
+    let channel = match ctx.cache.guild_channel(channel_id).await {
+        Some(channel) => channel,
+        None => return true,
+    };
+
+    let bot_id = ctx.cache.current_user_id().await;
+    match channel.permissions_for_user(ctx, bot_id).await {
+        Ok(permissions) => permissions.contains(Permissions::CONNECT | Permissions::SPEAK),
+        Err(e) => {
+            warn!("Error checking permissions in channel {channel_id}: {e}");
+            true
+        }
+    }
+}
+
+async fn afk_channel(ctx: &Context, gid: GuildId) -> Option<ChannelId> {
+    if let Some(afk_channel_id) = ctx.cache.guild_field(gid, |g| g.afk_channel_id).await {
+        return afk_channel_id;
+    }
+
+    match gid.to_partial_guild(ctx).await {
+        Ok(guild) => guild.afk_channel_id,
+        Err(e) => {
+            warn!("Error fetching guild {gid}: {e}");
+            None
+        }
+    }
+}
+
+/// Counts who else is connected to `channel_id`, besides `joiner`.
+async fn members_in_channel(
+    ctx: &Context,
+    gid: GuildId,
+    channel_id: ChannelId,
+    joiner: UserId,
+) -> usize {
+    {
+        let data = ctx.data.read().await;
+        let occupancy = data.get::<VoiceCounts>().expect("voice counts are set");
+        if let Some(occupancy) = occupancy.get(&gid) {
+            let joiner_there = occupancy.channel_of.get(&joiner) == Some(&channel_id);
+            return occupancy.count(channel_id) - usize::from(joiner_there);
+        }
+    }
+
+    // going through every voice state of a huge guild on every join adds up, this
+    // is only for guilds whose voice states never came in
+    ctx.cache
+        .guild_field(gid, |guild| {
+            guild
+                .voice_states
+                .values()
+                .filter(|vs| vs.channel_id == Some(channel_id) && vs.user_id != joiner)
+                .count()
+        })
+        .await
+        .unwrap_or(0)
+}
+
+struct VoiceCounts;
+
+impl TypeMapKey for VoiceCounts {
+    type Value = HashMap<GuildId, VoiceOccupancy>;
+}
+
+/// Who is in which of a guild's voice channels, kept up to date from the voice
+/// state updates so counting a channel's members doesn't go through all of them.
+#[derive(Default)]
+struct VoiceOccupancy {
+    channel_of: HashMap<UserId, ChannelId>,
+    counts: HashMap<ChannelId, usize>,
+}
+
+impl VoiceOccupancy {
+    /// Starting from the voice states a guild comes with on connecting, since
+    /// nobody's updates made it while the bot was down.
+    fn new<'a>(voice_states: impl IntoIterator<Item = &'a VoiceState>) -> Self {
+        let mut occupancy = Self::default();
+        for vs in voice_states {
+            occupancy.moved(vs.user_id, vs.channel_id);
+        }
+        occupancy
+    }
+
+    /// Returns the channel they were in before.
+    fn moved(&mut self, uid: UserId, to: Option<ChannelId>) -> Option<ChannelId> {
+        let from = match to {
+            Some(to) => self.channel_of.insert(uid, to),
+            None => self.channel_of.remove(&uid),
+        };
+        if from == to {
+            return from;
+        }
+        if let Some(from) = from {
+            if let Some(count) = self.counts.get_mut(&from) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    self.counts.remove(&from);
+                }
+            }
+        }
+        if let Some(to) = to {
+            *self.counts.entry(to).or_default() += 1;
+        }
+        from
+    }
+
+    fn count(&self, channel_id: ChannelId) -> usize {
+        self.counts.get(&channel_id).copied().unwrap_or(0)
+    }
+}
+
+/// Cleans up after the bot left `gid`'s voice channel, maybe disconnected by an
+/// admin: lingering leaves are called off and songbird drops the call.
+async fn forget_call(ctx: &Context, gid: GuildId) {
+    let manager = if let Some(manager) = songbird::get(ctx).await {
+        manager
+    } else {
+        return;
+    };
+
+    // a play may have joined again before this update arrived
+    if let Some(call) = manager.get(gid) {
+        if call.lock().await.current_channel().is_some() {
+            return;
+        }
+    }
+
+    {
+        let data = ctx.data.read().await;
+        let plays = data.get::<Plays>().expect("plays are set");
+        if let Some(plays) = plays.get(&gid) {
+            plays.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    {
+        let mut data = ctx.data.write().await;
+        let following = data.get_mut::<Following>().expect("following is set");
+        following.remove(&gid);
+    }
+    // kicked or moved out by somebody, so whatever it was held for is over
+    if held_in(ctx, gid).await.is_some() {
+        info!("Left the channel guild {gid} held the bot in, releasing it");
+        set_held(ctx, gid, None).await;
+    }
+
+    if let Err(e) = manager.remove(gid).await {
+        if !matches!(e, JoinError::NoCall) {
+            warn!("Error dropping the call in guild {gid}: {e}");
+        }
+    }
+}
+
+async fn is_bot(ctx: &Context, gid: GuildId, vs: &VoiceState) -> bool {
+    voice_member(ctx, gid, vs)
+        .await
+        .is_some_and(|member| member.user.bot)
+}
+
+/// The member `vs` is about. `vs.user_id` is what everything keys off: the member
+/// Discord attaches can be missing, or be stale cache data about somebody else, in
+/// which case it's looked up again by `user_id`.
+async fn voice_member(ctx: &Context, gid: GuildId, vs: &VoiceState) -> Option<Member> {
+    match attached_member(vs) {
+        Some(member) => Some(member.clone()),
+        None => ctx.cache.member(gid, vs.user_id).await,
+    }
+}
+
+/// The member Discord attached to `vs`, if it's really about `vs.user_id`.
+fn attached_member(vs: &VoiceState) -> Option<&Member> {
+    vs.member
+        .as_ref()
+        .filter(|member| member.user.id == vs.user_id)
+}
+
+/// The guild's cooldown between sounds, the global one unless it has its own.
+async fn guild_cooldown(ctx: &Context, guild_config: &GuildConfig) -> Duration {
+    match guild_config.cooldown_secs {
+        Some(secs) => Duration::from_secs(secs),
+        None => {
+            let data = ctx.data.read().await;
+            data.get::<Config>().expect("config is set").cooldown
+        }
+    }
+}
+
+/// The channel a member became able to speak in without moving: their server mute
+/// (`mute`) was lifted or they stopped being suppressed (`suppress`, e.g. invited to
+/// speak on a stage), and they aren't left with the other one. Their own mute
+/// (`self_mute`) doesn't count, people toggle it all the time.
+fn unmuted_in(old: Option<&VoiceState>, new: &VoiceState) -> Option<ChannelId> {
+    let old = old?;
+    let silenced = |vs: &VoiceState| vs.mute || vs.suppress;
+    if old.channel_id == new.channel_id && silenced(old) && !silenced(new) {
+        new.channel_id
+    } else {
+        None
+    }
+}
+
+/// The keys of `uid`'s own sounds a join in `gid` tries, with what each is logged as,
+/// most specific first: their sound for the guild, then their global one. None unless
+/// the guild plays the joiner's sound.
+fn personal_keys(gid: GuildId, uid: UserId, mode: PlayMode) -> Vec<(&'static str, SoundKey)> {
+    match mode {
+        PlayMode::Joiner => vec![
+            ("member", SoundKey::Member(gid, uid)),
+            ("personal", SoundKey::User(uid)),
+        ],
+        PlayMode::Room => Vec::new(),
+    }
+}
+
+/// Whose voice state update it is.
+#[derive(Debug, PartialEq, Eq)]
+enum UpdateFrom {
+    Member,
+    /// The bot joining or moving, never taken for a member's, or joining to play a
+    /// sound would trigger another one.
+    Bot,
+    /// The bot out of voice, maybe disconnected by an admin, which still has to be
+    /// cleaned up after.
+    BotDisconnected,
+}
+
+/// Tells the bot's own updates, it's `bot_id`, apart from the members'.
+fn update_from(bot_id: UserId, new: &VoiceState) -> UpdateFrom {
+    if new.user_id != bot_id {
+        UpdateFrom::Member
+    } else if new.channel_id.is_none() {
+        UpdateFrom::BotDisconnected
+    } else {
+        UpdateFrom::Bot
+    }
+}
+
+/// The channel `new` joined, if it's one they weren't in. Where they were comes from
+/// `previous`, the channel [`VoiceOccupancy`] had them in, when the guild is tracked,
+/// and from `old` otherwise.
+fn joined_channel(
+    old: Option<&VoiceState>,
+    previous: Option<Option<ChannelId>>,
+    new: &VoiceState,
+) -> Option<ChannelId> {
+    let from = match previous {
+        Some(previous) => previous,
+        None => old.and_then(|old| old.channel_id),
+    };
+    if from != new.channel_id {
+        new.channel_id
+    } else {
+        None
+    }
+}
+
+/// How long joining the same channel again counts as the same join. Members dragged
+/// around by an admin can come with repeated or reordered updates, each of which
+/// would look like a join otherwise.
+const TRANSITION_DEDUP_WINDOW: Duration = Duration::from_secs(2);
+
+/// What a voice state update did that can play a sound.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Transition {
+    Join,
+    Unmute,
+}
+
+/// Who made a [`Transition`] into which channel, from which voice session. A
+/// redelivered update has the same key as the original.
+type TransitionKey = (GuildId, UserId, ChannelId, String, Transition);
+
+struct RecentTransitions;
+
+impl TypeMapKey for RecentTransitions {
+    /// The transitions handled within [`TRANSITION_DEDUP_WINDOW`], and when.
+    type Value = HashMap<TransitionKey, Instant>;
+}
+
+/// Records that `new` made `transition` into `channel_id`, unless the same one from
+/// the same session was already handled within [`TRANSITION_DEDUP_WINDOW`], as when
+/// the gateway redelivers updates after a reconnect.
+async fn claim_transition(
+    ctx: &Context,
+    gid: GuildId,
+    new: &VoiceState,
+    channel_id: ChannelId,
+    transition: Transition,
+) -> bool {
+    let key = (
+        gid,
+        new.user_id,
+        channel_id,
+        new.session_id.clone(),
+        transition,
+    );
+    let mut data = ctx.data.write().await;
+    let recent = data
+        .get_mut::<RecentTransitions>()
+        .expect("recent transitions are set");
+    record_transition(recent, key, Instant::now())
+}
+
+/// [`claim_transition`] against the `recent` transitions at `now`, forgetting those
+/// older than [`TRANSITION_DEDUP_WINDOW`].
+fn record_transition(
+    recent: &mut HashMap<TransitionKey, Instant>,
+    key: TransitionKey,
+    now: Instant,
+) -> bool {
+    recent.retain(|_, at| now.saturating_duration_since(*at) < TRANSITION_DEDUP_WINDOW);
+    if recent.contains_key(&key) {
+        return false;
+    }
+    recent.insert(key, now);
+    true
+}
+
+struct Disconnect {
+    call: Arc<Mutex<Call>>,
+    /// The play's span, so leaving is logged along with what led to it.
+    span: Span,
+    /// How long to stay in the channel after the track ends.
+    linger: Duration,
+    plays: Arc<AtomicU64>,
+    /// Value of `plays` when this track started.
+    play_id: u64,
+    /// The guild's entry in [`Held`], the bot stays while it's set.
+    held: Arc<AtomicU64>,
+    farewell: Option<Farewell>,
+}
+
+/// Least time between two farewells in the same guild, so members hopping in and out
+/// don't flood the channel.
+const FAREWELL_INTERVAL_SECS: u64 = 5 * 60;
+
+struct Farewells;
+
+impl TypeMapKey for Farewells {
+    /// Unix time each guild's farewell was last posted, shared with its `Disconnect`s.
+    type Value = HashMap<GuildId, Arc<AtomicU64>>;
+}
+
+/// The `GuildConfig::farewell` a `Disconnect` posts once it leaves.
+#[derive(Clone)]
+struct Farewell {
+    http: Arc<Http>,
+    channel_id: ChannelId,
+    text: String,
+    /// The guild's entry in [`Farewells`].
+    last_posted: Arc<AtomicU64>,
+}
+
+impl Farewell {
+    /// Posts the farewell, unless one went out less than [`FAREWELL_INTERVAL_SECS`] ago.
+    async fn post(&self) {
+        let now = unix_now();
+        let due = self
+            .last_posted
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                (now.saturating_sub(last) >= FAREWELL_INTERVAL_SECS).then_some(now)
+            });
+        if due.is_err() {
+            debug!("Posted a farewell just now, skipping it");
+            return;
+        }
+        // the text is whatever the guild set, an `@everyone` in it shouldn't ping
+        let posted = self
+            .channel_id
+            .send_message(&self.http, |m| {
+                m.content(&self.text).allowed_mentions(|a| a.empty_parse())
+            })
+            .await;
+        if let Err(e) = posted {
+            warn!("Error posting the farewell: {e}");
+        }
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for Disconnect {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(_) = ctx {
+            if self.held.load(Ordering::SeqCst) != 0 {
+                let _entered = self.span.enter();
+                debug!("Sound ended, staying since the bot is held");
+            } else if self.linger.is_zero() {
+                let leaving = async {
+                    debug!("Sound ended, leaving");
+                    leave(&self.call).await;
+                    if let Some(farewell) = &self.farewell {
+                        farewell.post().await;
+                    }
+                };
+                leaving.instrument(self.span.clone()).await;
+            } else {
+                let call = self.call.clone();
+                let plays = self.plays.clone();
+                let held = self.held.clone();
+                let farewell = self.farewell.clone();
+                let (linger, play_id) = (self.linger, self.play_id);
+                let lingering = async move {
+                    debug!(?linger, "Sound ended, lingering");
+                    tokio::time::sleep(linger).await;
+                    // somebody else joined while we lingered, their track leaves instead
+                    if held.load(Ordering::SeqCst) != 0 {
+                        debug!("Held while lingering, staying");
+                    } else if plays.load(Ordering::SeqCst) == play_id {
+                        debug!("Leaving after lingering");
+                        leave(&call).await;
+                        if let Some(farewell) = &farewell {
+                            farewell.post().await;
+                        }
+                    }
+                };
+                tokio::spawn(lingering.instrument(self.span.clone()));
+            }
+        }
+        None
+    }
+}
+
+async fn leave(call: &Mutex<Call>) {
+    if let Err(e) = call.lock().await.leave().await {
+        warn!("Error leaving channel: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stepped_clock(step: Duration) -> SteppedClock {
+        SteppedClock {
+            start: Instant::now(),
+            step,
+            reads: AtomicU32::new(0),
+        }
+    }
+
+    #[test]
+    fn cooldown_runs_out_after_its_duration() {
+        let clock = stepped_clock(Duration::from_secs(1));
+        let cooldown = Duration::from_secs(2);
+        let mut last_plays = HashMap::new();
+
+        assert!(!check_cooldown(&mut last_plays, 1, cooldown, clock.now()));
+        assert!(check_cooldown(&mut last_plays, 1, cooldown, clock.now()));
+        // a play refused while cooling down doesn't start the cooldown over
+        assert!(!check_cooldown(&mut last_plays, 1, cooldown, clock.now()));
+        assert!(check_cooldown(&mut last_plays, 1, cooldown, clock.now()));
+    }
+
+    #[test]
+    fn cooldown_is_kept_per_key() {
+        let clock = stepped_clock(Duration::from_millis(10));
+        let cooldown = Duration::from_secs(60);
+        let mut last_plays = HashMap::new();
+
+        assert!(!check_cooldown(&mut last_plays, 1, cooldown, clock.now()));
+        assert!(!check_cooldown(&mut last_plays, 2, cooldown, clock.now()));
+        assert!(check_cooldown(&mut last_plays, 1, cooldown, clock.now()));
+    }
+
+    #[test]
+    fn zero_cooldown_never_cools_down() {
+        let clock = stepped_clock(Duration::ZERO);
+        let mut last_plays = HashMap::new();
+
+        for _ in 0..3 {
+            assert!(!check_cooldown(
+                &mut last_plays,
+                1,
+                Duration::ZERO,
+                clock.now()
+            ));
+        }
+    }
+
+    fn voice_state(uid: u64, channel_id: Option<u64>) -> VoiceState {
+        serde_json::from_value(serde_json::json!({
+            "channel_id": channel_id.map(|c| c.to_string()),
+            "deaf": false,
+            "mute": false,
+            "self_deaf": false,
+            "self_mute": false,
+            "self_video": false,
+            "session_id": "session",
+            "suppress": false,
+            "user_id": uid.to_string(),
+        }))
+        .expect("voice state deserializes")
+    }
+
+    /// What the voice state update handler makes of `new`, given the cached `old`.
+    fn update(
+        occupancy: &mut VoiceOccupancy,
+        old: Option<&VoiceState>,
+        new: &VoiceState,
+    ) -> Option<ChannelId> {
+        let previous = occupancy.moved(new.user_id, new.channel_id);
+        joined_channel(old, Some(previous), new)
+    }
+
+    #[test]
+    fn repeated_update_is_a_single_join() {
+        let mut occupancy = VoiceOccupancy::default();
+        let joined = voice_state(1, Some(10));
+
+        assert_eq!(update(&mut occupancy, None, &joined), Some(ChannelId(10)));
+        assert_eq!(update(&mut occupancy, None, &joined), None);
+        assert_eq!(occupancy.count(ChannelId(10)), 1);
+    }
+
+    #[test]
+    fn stale_old_state_does_not_make_a_join() {
+        let mut occupancy = VoiceOccupancy::default();
+        let (left, joined) = (voice_state(1, None), voice_state(1, Some(10)));
+        update(&mut occupancy, None, &joined);
+
+        // the cache still had them out of voice, the occupancy knows better
+        assert_eq!(update(&mut occupancy, Some(&left), &joined), None);
+    }
+
+    #[test]
+    fn moving_there_and_back_joins_each_time() {
+        let mut occupancy = VoiceOccupancy::default();
+        let (a, b) = (voice_state(1, Some(10)), voice_state(1, Some(20)));
+
+        assert_eq!(update(&mut occupancy, None, &a), Some(ChannelId(10)));
+        assert_eq!(update(&mut occupancy, Some(&a), &b), Some(ChannelId(20)));
+        assert_eq!(update(&mut occupancy, Some(&b), &a), Some(ChannelId(10)));
+        assert_eq!(occupancy.count(ChannelId(10)), 1);
+        assert_eq!(occupancy.count(ChannelId(20)), 0);
+    }
+
+    #[test]
+    fn reordered_updates_leave_counts_consistent() {
+        let mut occupancy = VoiceOccupancy::default();
+        let (a, b, left) = (
+            voice_state(1, Some(10)),
+            voice_state(1, Some(20)),
+            voice_state(1, None),
+        );
+
+        // the move to b arrives before the join to a it followed
+        assert_eq!(update(&mut occupancy, None, &b), Some(ChannelId(20)));
+        assert_eq!(update(&mut occupancy, None, &a), Some(ChannelId(10)));
+        assert_eq!(occupancy.count(ChannelId(10)), 1);
+        assert_eq!(occupancy.count(ChannelId(20)), 0);
+        assert_eq!(update(&mut occupancy, Some(&a), &left), None);
+        assert_eq!(occupancy.count(ChannelId(10)), 0);
+    }
+
+    fn transition_key(session: &str) -> TransitionKey {
+        (
+            GuildId(1),
+            UserId(2),
+            ChannelId(3),
+            session.to_string(),
+            Transition::Join,
+        )
+    }
+
+    #[test]
+    fn repeated_transition_is_claimed_once_within_the_window() {
+        let start = Instant::now();
+        let mut recent = HashMap::new();
+
+        assert!(record_transition(&mut recent, transition_key("a"), start));
+        let within = start + TRANSITION_DEDUP_WINDOW / 2;
+        assert!(!record_transition(&mut recent, transition_key("a"), within));
+        // another session is another join
+        assert!(record_transition(&mut recent, transition_key("b"), within));
+    }
+
+    #[test]
+    fn repeated_transition_is_claimed_again_after_the_window() {
+        let start = Instant::now();
+        let mut recent = HashMap::new();
+
+        assert!(record_transition(&mut recent, transition_key("a"), start));
+        let after = start + TRANSITION_DEDUP_WINDOW;
+        assert!(record_transition(&mut recent, transition_key("a"), after));
+        assert_eq!(recent.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn playback_lock_only_holds_up_its_own_guild() {
+        let mut data = TypeMap::new();
+        data.insert::<PlaybackLocks>(HashMap::new());
+        let data = Arc::new(RwLock::new(data));
+        let held = lock_playback_in(&data, GuildId(1)).await;
+
+        let others: Vec<_> = (2..10)
+            .map(|gid| {
+                let data = data.clone();
+                tokio::spawn(async move { drop(lock_playback_in(&data, GuildId(gid)).await) })
+            })
+            .collect();
+        for other in others {
+            tokio::time::timeout(Duration::from_secs(5), other)
+                .await
+                .expect("other guilds don't wait")
+                .expect("lock task doesn't panic");
+        }
+
+        let same = lock_playback_in(&data, GuildId(1));
+        assert!(tokio::time::timeout(Duration::from_millis(50), same)
+            .await
+            .is_err());
+        drop(held);
+        let same = lock_playback_in(&data, GuildId(1));
+        assert!(tokio::time::timeout(Duration::from_secs(5), same)
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn upload_name_without_a_file_name_is_audio() {
+        for name in ["", "...", "dir/", " ", "a/..", "./"] {
+            assert_eq!(upload_name(name), "audio", "{name:?}");
+        }
+    }
+
+    #[test]
+    fn upload_name_keeps_only_the_file_name() {
+        assert_eq!(upload_name("laugh.ogg"), "laugh.ogg");
+        assert_eq!(upload_name(" dir/laugh.ogg "), "laugh.ogg");
+        assert_eq!(upload_name(".hidden"), ".hidden");
+    }
+
+    #[test]
+    fn temp_path_stays_in_the_temp_dir() {
+        let key = SoundKey::Guild(GuildId(1));
+        for name in ["../../etc/passwd", "/etc/passwd", "..", "a/../../b"] {
+            let path = temp_path(key, name);
+            assert_eq!(path.parent(), Some(env::temp_dir().as_path()), "{name:?}");
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap();
+            assert!(!file_name.contains(".."), "{name:?} made {file_name:?}");
+        }
+    }
+
+    /// Writes `frames` of 16-bit PCM at `rate` with `channels` to a wav file in the
+    /// temp dir, every sample at half of full scale.
+    fn write_wav(rate: u32, channels: u16, frames: u32) -> PathBuf {
+        let data_len = frames * u32::from(channels) * 2;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&rate.to_le_bytes());
+        wav.extend_from_slice(&(rate * u32::from(channels) * 2).to_le_bytes());
+        wav.extend_from_slice(&(channels * 2).to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for _ in 0..frames * u32::from(channels) {
+            wav.extend_from_slice(&(i16::MAX / 2).to_le_bytes());
+        }
+        let path = env::temp_dir().join(format!("{:08x}-test.wav", rand::random::<u32>()));
+        std::fs::write(&path, wav).unwrap();
+        path
+    }
+
+    fn decode_wav(rate: u32, channels: u16, frames: u32) -> Result<Vec<u8>, AudioError> {
+        let path = write_wav(rate, channels, frames);
+        let decoded = decode_natively(&path, None, None);
+        std::fs::remove_file(&path).unwrap();
+        decoded
+            .expect("wav decodes natively")
+            .map(|native| native.pcm)
+    }
+
+    #[test]
+    fn clip_shorter_than_the_minimum_is_refused() {
+        // 50ms
+        let pcm = decode_wav(48000, 2, 2400).unwrap();
+        match native_input(pcm, Metadata::default()) {
+            Err(AudioError::TooShort(duration)) => assert!(duration < MIN_CLIP_DURATION),
+            Err(e) => panic!("refused the tiny clip for something else: {e}"),
+            Ok(_) => panic!("took the tiny clip"),
+        }
+    }
+
+    #[test]
+    fn clip_of_the_minimum_is_taken() {
+        let frames = (MIN_CLIP_DURATION.as_millis() * 48) as u32;
+        let pcm = decode_wav(48000, 2, frames).unwrap();
+        assert!(native_input(pcm, Metadata::default()).is_ok());
+    }
+
+    fn native_frames(pcm: &[u8]) -> Vec<[f32; 2]> {
+        pcm.chunks_exact(8)
+            .map(|frame| {
+                let sample = |at: usize| f32::from_le_bytes(frame[at..at + 4].try_into().unwrap());
+                [sample(0), sample(4)]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mono_44k_becomes_48k_stereo() {
+        let samples: Vec<f32> = (0..44100).map(|i| i as f32 / 44100.0).collect();
+        let frames = native_frames(&to_native_pcm(&samples, 1, 44100));
+
+        assert_eq!(frames.len(), 48000);
+        assert!(frames.iter().all(|[left, right]| left == right));
+        // still the same ramp, only sampled more often
+        let halfway = frames[24000][0];
+        assert!((halfway - 0.5).abs() < 1e-3, "halfway at {halfway}");
+        assert!(frames.windows(2).all(|w| w[0][0] <= w[1][0]));
+    }
+
+    #[test]
+    fn mono_44k_wav_decodes_to_48k_stereo() {
+        let frames = native_frames(&decode_wav(44100, 1, 4410).unwrap());
+
+        assert_eq!(frames.len(), 4800);
+        for [left, right] in frames {
+            assert_eq!(left, right);
+            assert!((left - 0.5).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn channels_past_the_second_are_dropped() {
+        let samples = [0.1, 0.2, 0.3, 0.1, 0.2, 0.3];
+        let frames = native_frames(&to_native_pcm(&samples, 3, 48000));
+
+        assert_eq!(frames, vec![[0.1, 0.2], [0.1, 0.2]]);
+    }
+
+    #[test]
+    fn own_updates_never_count_as_a_member_joining() {
+        let bot_id = UserId(1);
+
+        assert_eq!(
+            update_from(bot_id, &voice_state(1, Some(10))),
+            UpdateFrom::Bot
+        );
+        assert_eq!(
+            update_from(bot_id, &voice_state(1, Some(20))),
+            UpdateFrom::Bot
+        );
+        assert_eq!(
+            update_from(bot_id, &voice_state(1, None)),
+            UpdateFrom::BotDisconnected
+        );
+        assert_eq!(
+            update_from(bot_id, &voice_state(2, Some(10))),
+            UpdateFrom::Member
+        );
+    }
+
+    /// Like [`voice_state`], with the member Discord attached being `member_id`.
+    fn voice_state_with_member(uid: u64, channel_id: Option<u64>, member_id: u64) -> VoiceState {
+        let mut value = serde_json::to_value(voice_state(uid, channel_id)).unwrap();
+        value["member"] = serde_json::json!({
+            "deaf": false,
+            "mute": false,
+            "guild_id": "1",
+            "roles": [],
+            "joined_at": null,
+            "nick": null,
+            "premium_since": null,
+            "user": {
+                "id": member_id.to_string(),
+                "username": "someone",
+                "discriminator": "0001",
+                "avatar": null,
+            },
+        });
+        serde_json::from_value(value).expect("voice state deserializes")
+    }
+
+    #[test]
+    fn stale_attached_member_is_ignored() {
+        let fresh = voice_state_with_member(1, Some(10), 1);
+        let stale = voice_state_with_member(1, Some(10), 2);
+
+        assert_eq!(attached_member(&fresh).map(|m| m.user.id), Some(UserId(1)));
+        assert!(attached_member(&stale).is_none());
+        assert!(attached_member(&voice_state(1, Some(10))).is_none());
+    }
+
+    #[test]
+    fn occupancy_counts_by_user_id() {
+        let states = [
+            voice_state_with_member(1, Some(10), 1),
+            // a stale member from the cache, still user 2 joining
+            voice_state_with_member(2, Some(10), 1),
+            voice_state(3, Some(20)),
+            voice_state(4, None),
+        ];
+        let mut occupancy = VoiceOccupancy::new(&states);
+
+        assert_eq!(occupancy.count(ChannelId(10)), 2);
+        assert_eq!(occupancy.count(ChannelId(20)), 1);
+        assert_eq!(
+            occupancy.moved(UserId(2), Some(ChannelId(20))),
+            Some(ChannelId(10))
+        );
+        assert_eq!(occupancy.moved(UserId(1), None), Some(ChannelId(10)));
+        assert_eq!(occupancy.count(ChannelId(10)), 0);
+        assert_eq!(occupancy.count(ChannelId(20)), 2);
+        assert_eq!(occupancy.moved(UserId(4), None), None);
+    }
+
+    #[test]
+    fn guild_scoped_sound_comes_before_the_global_one() {
+        let (gid, uid) = (GuildId(1), UserId(2));
+        let keys: Vec<SoundKey> = personal_keys(gid, uid, PlayMode::Joiner)
+            .into_iter()
+            .map(|(_, key)| key)
+            .collect();
+
+        assert_eq!(keys, [SoundKey::Member(gid, uid), SoundKey::User(uid)]);
+        assert!(personal_keys(gid, uid, PlayMode::Room).is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_set_and_unset_happen_one_after_the_other() {
+        let data_dir = env::temp_dir().join(format!("{:08x}-test-data", rand::random::<u32>()));
+        let config = Arc::new(Config {
+            data_dir: data_dir.clone(),
+            object_store: None,
+            ..Config::from_env()
+        });
+        let mut data = TypeMap::new();
+        data.insert::<SoundLocks>(HashMap::new());
+        data.insert::<SoundStore>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<Config>(config.clone());
+        let data = Arc::new(RwLock::new(data));
+        let key = SoundKey::Unmute(GuildId(1));
+        let wav = write_wav(48000, 2, 4800);
+        let decoded = decode(&wav, None, None, &config).await.unwrap();
+
+        // without the lock the two interleave within a few rounds
+        for _ in 0..100 {
+            let set = {
+                let (data, config) = (data.clone(), config.clone());
+                let (decoded, wav) = (decoded.clone(), wav.clone());
+                tokio::spawn(async move {
+                    let label = "unmute".to_string();
+                    keep_sound(
+                        &data, key, label, decoded, [0; 32], true, "a.wav", &wav, &config,
+                    )
+                    .await
+                })
+            };
+            let unset = {
+                let data = data.clone();
+                tokio::spawn(async move { clear_key_sound_in(&data, key).await })
+            };
+            set.await.unwrap().unwrap();
+            unset.await.unwrap();
+
+            // whichever went last, the sound is either both loaded and persisted or neither
+            let loaded = sound_store_in(&data).await.read().await.contains_key(&key);
+            assert_eq!(loaded, sounds_dir(&data_dir, key).exists());
+        }
+        std::fs::remove_file(&wav).unwrap();
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+
+    /// The end of the output of `ffmpeg -nostats -i laugh.ogg -af ebur128 -f null -`.
+    const EBUR128_OUTPUT: &str = "\
+[Parsed_ebur128_0 @ 0x55d4c8a1e2c0] t: 2.29998    TARGET:-23 LUFS    M: -17.9 S:-120.7     I: -18.2 LUFS       LRA:   0.0 LU
+[Parsed_ebur128_0 @ 0x55d4c8a1e2c0] t: 2.39998    TARGET:-23 LUFS    M: -18.5 S:-120.7     I: -18.4 LUFS       LRA:   0.0 LU
+[out#0/null @ 0x55d4c8a1d880] video:0KiB audio:450KiB subtitle:0KiB other streams:0KiB global headers:0KiB muxing overhead: unknown
+size=N/A time=00:00:02.40 bitrate=N/A speed= 226x
+[Parsed_ebur128_0 @ 0x55d4c8a1e2c0] Summary:
+
+  Integrated loudness:
+    I:         -18.4 LUFS
+    Threshold: -28.6 LUFS
+
+  Loudness range:
+    LRA:         0.0 LU
+    Threshold:   0.0 LUFS
+    LRA low:     0.0 LUFS
+    LRA high:    0.0 LUFS
+";
+
+    #[test]
+    fn integrated_loudness_comes_from_the_summary() {
+        assert_eq!(integrated_loudness(EBUR128_OUTPUT), Some(-18.4));
+    }
+
+    #[test]
+    fn no_summary_no_integrated_loudness() {
+        let cut_short = EBUR128_OUTPUT.split("Summary").next().unwrap();
+        assert_eq!(integrated_loudness(cut_short), None);
+        assert_eq!(integrated_loudness(""), None);
+    }
+
+    #[test]
+    fn stepped_clock_does_not_overflow() {
+        let clock = stepped_clock(Duration::MAX);
+        clock.now();
+        clock.now();
+        clock.now();
+    }
+
+    #[test]
+    fn guild_defaults_are_range_checked() {
+        let defaults = GuildConfig {
+            cooldown_secs: Some(MAX_COOLDOWN_SECS + 1),
+            ..GuildConfig::default()
+        };
+        let ceiling = Duration::from_secs(60);
+        assert!(check_guild_config(&defaults, ceiling).is_err());
+        assert!(check_guild_config(&GuildConfig::default(), ceiling).is_ok());
+    }
+
+    #[test]
+    fn spans_are_capped() {
+        assert_eq!(parse_span("2h"), Some(2 * 60 * 60));
+        assert_eq!(parse_span("365d"), Some(MAX_SPAN_SECS));
+        assert_eq!(parse_span("366d"), None);
+        assert_eq!(parse_span("18446744073709551615d"), None);
+        assert!(parse_override("213503982334601d").is_none());
+    }
+
+    #[test]
+    fn admin_job_kinds_are_parsed_from_the_path() {
+        let configs = br#"{"123": {"cooldown_secs": 10}}"#;
+        match parse_admin_job("/configs", configs) {
+            Ok(Some(AdminJobKind::SetConfigs(configs))) => {
+                assert_eq!(configs[&GuildId(123)].cooldown_secs, Some(10));
+            }
+            _ => panic!("configs job not parsed"),
+        }
+        let sounds = br#"[{"guild": 123, "url": "https://example.com/a.mp3"}]"#;
+        assert!(matches!(
+            parse_admin_job("/sounds", sounds),
+            Ok(Some(AdminJobKind::SetSounds(sounds))) if sounds.len() == 1
+        ));
+        assert!(matches!(
+            parse_admin_job("/reload", b""),
+            Ok(Some(AdminJobKind::Reload))
+        ));
+        assert!(parse_admin_job("/sounds", b"{}").is_err());
+        assert!(matches!(parse_admin_job("/nuke", b""), Ok(None)));
+    }
+
+    #[test]
+    fn peak_comes_from_the_native_pcm() {
+        let pcm = decode_wav(48000, 2, 4800).unwrap();
+        assert!((peak_db(&pcm) + 6.0).abs() < 0.1);
+        assert_eq!(peak_db(&[0; 16]), f64::NEG_INFINITY);
     }
 }